@@ -19,22 +19,52 @@
 // marxism@peoplesgrocers.com
 //
 
-use chrono::Utc;
-use serde_json::Value;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
 use std::fs::{File, OpenOptions};
-use std::io::{BufWriter, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+use crate::canonical::canonicalize;
+use crate::collection::read_collection;
 use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticLevel};
 use crate::diff;
-use crate::events::{Event, Header, Observation};
-use crate::reader::{ArchiveReader, ReadMode};
+use crate::events::{Event, Header, Observation, StateHash};
+use crate::format::{self, verify_roundtrip, FormatProfile};
+use crate::hash::state_hash;
+use crate::reader::{compression_from_extension, ArchiveReader, CompressionFormat, ReadMode, ReadResult};
+use crate::replay::apply_event;
+
+#[cfg(feature = "compression")]
+use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+
+/// How the writer places a snapshot once one is due.
+///
+/// The spec says `["snapshot", id, ts, obj]` is interchangeable with an
+/// observe+deltas sequence, so there are two ways to do it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnapshotMode {
+    /// Write the observation's deltas, then a snapshot of the same observation
+    /// right after it. Redundant, but every observation keeps its deltas.
+    #[default]
+    Append,
+    /// Write the observation itself as a snapshot, carrying the observation's
+    /// id and timestamp, in place of its deltas.
+    Replace,
+}
 
-pub struct ArchiveWriter {
-    writer: BufWriter<File>,
+/// Writes archive lines to a file, or to any other `Write` with
+/// `from_writer`.
+pub struct ArchiveWriter<W: Write = BufWriter<File>> {
+    writer: W,
     observation_count: usize,
     snapshot_interval: Option<usize>,
+    snapshot_mode: SnapshotMode,
+    last_observation: Option<(String, DateTime<Utc>)>,
+    /// Chain hash of the last hashed event; `None` if not hashing.
+    chain: Option<String>,
+    canonical: bool,
     filename: String,
 }
 
@@ -65,6 +95,10 @@ impl ArchiveWriter {
             writer,
             observation_count: 0,
             snapshot_interval,
+            snapshot_mode: SnapshotMode::default(),
+            last_observation: None,
+            chain: None,
+            canonical: false,
             filename,
         })
     }
@@ -75,7 +109,7 @@ impl ArchiveWriter {
         current_observation_count: usize,
     ) -> Result<Self, Vec<Diagnostic>> {
         let filename = path.as_ref().display().to_string();
-        let file = match OpenOptions::new().append(true).open(&path) {
+        let file = match OpenOptions::new().read(true).append(true).open(&path) {
             Ok(f) => f,
             Err(e) => {
                 let diagnostic = Diagnostic::new(
@@ -90,18 +124,93 @@ impl ArchiveWriter {
                 return Err(vec![diagnostic]);
             }
         };
+        // An interrupted write can leave the last line without its newline.
+        // Finish it, so the first appended event starts on a line of its own.
+        let mut file = file;
+        if let Err(e) = terminate_last_line(&mut file) {
+            return Err(vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                format!("I couldn't prepare the archive file for appending: {}", e),
+            )]);
+        }
         let writer = BufWriter::new(file);
 
         Ok(Self {
             writer,
             observation_count: current_observation_count,
             snapshot_interval,
+            snapshot_mode: SnapshotMode::default(),
+            last_observation: None,
+            chain: None,
+            canonical: false,
             filename,
         })
     }
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Write to `writer`, which can be a buffer in memory or a response body.
+    /// `current_observation_count` is the number of observations already in
+    /// the archive `writer` continues, for the snapshot interval; 0 for a new
+    /// archive. When continuing one, `writer` must start on a fresh line.
+    pub fn from_writer(writer: W, snapshot_interval: Option<usize>, current_observation_count: usize) -> Self {
+        Self {
+            writer,
+            observation_count: current_observation_count,
+            snapshot_interval,
+            snapshot_mode: SnapshotMode::default(),
+            last_observation: None,
+            chain: None,
+            canonical: false,
+            filename: "output".to_string(),
+        }
+    }
+
+    pub fn with_snapshot_mode(mut self, mode: SnapshotMode) -> Self {
+        self.snapshot_mode = mode;
+        self
+    }
+
+    /// Hash every observation written with its state, and every snapshot,
+    /// continuing the hash chain from `chain`.
+    pub fn with_hash_chain(mut self, chain: String) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// Write the header's initial state and every snapshot with object keys
+    /// in canonical order, see `canonical::canonicalize`.
+    pub fn with_canonical_keys(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// `state` as this writer stores it in a header or snapshot.
+    fn stored_state(&self, state: &Value) -> Value {
+        if self.canonical {
+            canonicalize(state)
+        } else {
+            state.clone()
+        }
+    }
+
+    /// The next link of the hash chain for an event leaving `state` behind.
+    fn next_hash(&mut self, state: &Value) -> Option<StateHash> {
+        let hash = StateHash::next(self.chain.as_ref()?, state);
+        self.chain = Some(hash.chain.clone());
+        Some(hash)
+    }
 
     pub fn write_header(&mut self, header: &Header) -> Result<(), Vec<Diagnostic>> {
-        let header_json = match serde_json::to_string(header) {
+        let header_json = if self.canonical {
+            let mut header = header.clone();
+            header.initial = canonicalize(&header.initial);
+            serde_json::to_string(&header)
+        } else {
+            serde_json::to_string(header)
+        };
+        let header_json = match header_json {
             Ok(json) => json,
             Err(e) => {
                 return Err(vec![Diagnostic::new(
@@ -136,49 +245,96 @@ impl ArchiveWriter {
         Ok(())
     }
 
+    /// Write an observation's events. Nothing here knows the state the
+    /// observation produces, so it isn't hashed; use
+    /// `write_observation_with_state` for that.
     pub fn write_observation(&mut self, observation: Observation) -> Result<(), Vec<Diagnostic>> {
+        self.last_observation = Some((observation.id.clone(), observation.timestamp));
         let events = observation.to_events();
 
         for event in events {
-            let event_json = match serde_json::to_string(&event) {
-                Ok(json) => json,
-                Err(e) => {
-                    return Err(vec![Diagnostic::new(
-                        DiagnosticLevel::Fatal,
-                        DiagnosticCode::InvalidEventJson,
-                        format!("I couldn't serialize an event to JSON: {}", e),
-                    )]);
-                }
-            };
+            self.write_event(&event)?;
+        }
 
-            if let Err(e) = writeln!(self.writer, "{}", event_json) {
-                return Err(vec![Diagnostic::new(
-                    DiagnosticLevel::Fatal,
-                    DiagnosticCode::PathNotFound,
-                    format!("I couldn't write to the output file: {}", e),
-                )]);
-            }
+        self.observation_count += 1;
+        Ok(())
+    }
+
+    /// Write an observation together with the state it produces, inserting a
+    /// snapshot according to the snapshot interval and mode.
+    pub fn write_observation_with_state(
+        &mut self,
+        observation: Observation,
+        state: &Value,
+    ) -> Result<(), Vec<Diagnostic>> {
+        if self.snapshot_mode == SnapshotMode::Replace && self.snapshot_due_after(self.observation_count + 1) {
+            return self.write_observation_as_snapshot(observation, state);
         }
 
+        let mut observation = observation;
+        observation.hash = self.next_hash(state);
+        self.write_observation(observation)?;
+        if self.should_write_snapshot() {
+            self.write_snapshot(state)?;
+        }
+        Ok(())
+    }
+
+    /// Write an observation as a snapshot of `state` carrying its id,
+    /// timestamp and metadata, in place of its events.
+    pub fn write_observation_as_snapshot(&mut self, observation: Observation, state: &Value) -> Result<(), Vec<Diagnostic>> {
+        let snapshot = Event::Snapshot {
+            observation_id: observation.id.clone(),
+            timestamp: observation.timestamp,
+            object: self.stored_state(state),
+            hash: self.next_hash(state),
+            metadata: observation.metadata,
+        };
+        self.write_event(&snapshot)?;
+        self.last_observation = Some((observation.id, observation.timestamp));
         self.observation_count += 1;
         Ok(())
     }
 
+    /// Write a snapshot of the most recently written observation. The snapshot
+    /// reuses that observation's id and timestamp so readers see it as a
+    /// checkpoint rather than a new observation.
     pub fn write_snapshot(&mut self, object: &Value) -> Result<(), Vec<Diagnostic>> {
-        let snapshot_id = format!("snapshot-{}", Uuid::new_v4());
+        let (observation_id, timestamp) = match &self.last_observation {
+            Some((id, timestamp)) => (id.clone(), *timestamp),
+            None => (format!("snapshot-{}", Uuid::new_v4()), Utc::now()),
+        };
         let snapshot = Event::Snapshot {
-            observation_id: snapshot_id,
-            timestamp: Utc::now(),
-            object: object.clone(),
+            observation_id,
+            timestamp,
+            object: self.stored_state(object),
+            hash: self.next_hash(object),
+            metadata: None,
         };
 
-        let event_json = match serde_json::to_string(&snapshot) {
+        self.write_event(&snapshot)
+    }
+
+    /// Write an event copied from another archive that leaves the archive at
+    /// `state`. An observe or snapshot gets the next link of this writer's
+    /// hash chain in place of the hash it had, or none if the writer doesn't
+    /// hash.
+    pub fn write_copied_event(&mut self, event: &Event, state: &Value) -> Result<(), Vec<Diagnostic>> {
+        let mut event = event.clone();
+        if let Event::Observe { hash, .. } | Event::Snapshot { hash, .. } = &mut event {
+            *hash = self.next_hash(state);
+        }
+        self.write_event(&event)
+    }
+
+    pub fn write_event(&mut self, event: &Event) -> Result<(), Vec<Diagnostic>> {
+        let event_json = match serde_json::to_string(event) {
             Ok(json) => json,
             Err(e) => {
                 return Err(vec![Diagnostic::new(
                     DiagnosticLevel::Fatal,
                     DiagnosticCode::InvalidEventJson,
-                    format!("I couldn't serialize the snapshot to JSON: {}", e),
+                    format!("I couldn't serialize an event to JSON: {}", e),
                 )]);
             }
         };
@@ -195,14 +351,20 @@ impl ArchiveWriter {
     }
 
     pub fn should_write_snapshot(&self) -> bool {
+        self.snapshot_due_after(self.observation_count)
+    }
+
+    fn snapshot_due_after(&self, observation_count: usize) -> bool {
         if let Some(interval) = self.snapshot_interval {
-            self.observation_count > 0 && self.observation_count % interval == 0
+            observation_count > 0 && observation_count.is_multiple_of(interval)
         } else {
             false
         }
     }
 
-    pub fn finish(mut self) -> Result<(), Vec<Diagnostic>> {
+    /// Push everything written so far to the file, so readers see complete
+    /// observations while the writer stays open.
+    pub fn flush(&mut self) -> Result<(), Vec<Diagnostic>> {
         if let Err(e) = self.writer.flush() {
             return Err(vec![Diagnostic::new(
                 DiagnosticLevel::Fatal,
@@ -212,6 +374,17 @@ impl ArchiveWriter {
         }
         Ok(())
     }
+
+    pub fn finish(mut self) -> Result<(), Vec<Diagnostic>> {
+        self.flush()
+    }
+
+    /// Flush and hand back the underlying writer, e.g. the `Vec<u8>` the
+    /// archive was written to.
+    pub fn into_inner(mut self) -> Result<W, Vec<Diagnostic>> {
+        self.flush()?;
+        Ok(self.writer)
+    }
 }
 
 pub struct ArchiveBuilder {
@@ -219,6 +392,27 @@ pub struct ArchiveBuilder {
     current_state: Value,
     source: Option<String>,
     snapshot_interval: Option<usize>,
+    snapshot_mode: SnapshotMode,
+    observation_metadata: Map<String, Value>,
+    metadata: Option<Value>,
+    hash: bool,
+    canonical: bool,
+    /// Observations kept by `push_state` for `build` and `write_to`.
+    observations: Vec<Observation>,
+    /// Set when continuing an existing archive instead of starting one.
+    continuing: Option<Continuation>,
+}
+
+/// Where an existing archive left off, for writing only what comes after.
+struct Continuation {
+    observation_count: usize,
+    chain: Option<String>,
+}
+
+impl Default for ArchiveBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ArchiveBuilder {
@@ -228,9 +422,47 @@ impl ArchiveBuilder {
             current_state: Value::Null,
             source: None,
             snapshot_interval: None,
+            snapshot_mode: SnapshotMode::default(),
+            observation_metadata: Map::new(),
+            metadata: None,
+            hash: false,
+            canonical: false,
+            observations: Vec::new(),
+            continuing: None,
         }
     }
 
+    /// A builder for appending to the archive `existing` was read from. States
+    /// pushed are diffed against its final state, and `build` and `write_to`
+    /// write only the new observations, continuing its hash chain if it has
+    /// one. An archive read with fatal errors is refused, since its final
+    /// state isn't one the archive can be trusted to replay to.
+    ///
+    /// The snapshot interval, snapshot mode and canonical key order aren't
+    /// recorded in an archive, so they aren't carried over. Set them again
+    /// the way the archive was first written.
+    pub fn continuing(existing: &ReadResult) -> Result<Self, Vec<Diagnostic>> {
+        if existing.diagnostics.has_fatal() {
+            let mut diagnostics = vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::InvalidEventJson,
+                "The archive contains fatal errors, so I can't append to it.".to_string(),
+            )
+            .with_advice("Validate the archive to see what's wrong with it.".to_string())];
+            diagnostics.extend(existing.diagnostics.diagnostics().iter().cloned());
+            return Err(diagnostics);
+        }
+
+        let mut builder = Self::new();
+        builder.initial_state = Some(existing.final_state.clone());
+        builder.current_state = existing.final_state.clone();
+        builder.continuing = Some(Continuation {
+            observation_count: existing.observation_count,
+            chain: existing.chain.clone().or_else(|| existing.header.hash.clone()),
+        });
+        Ok(builder)
+    }
+
     pub fn with_source(mut self, source: String) -> Self {
         self.source = Some(source);
         self
@@ -241,7 +473,51 @@ impl ArchiveBuilder {
         self
     }
 
+    pub fn with_snapshot_mode(mut self, mode: SnapshotMode) -> Self {
+        self.snapshot_mode = mode;
+        self
+    }
+
+    /// Metadata for the archive header, describing the archive as a whole.
+    pub fn with_metadata(mut self, metadata: Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Hash the initial state and every observation, see the `hash` module.
+    pub fn with_hashes(mut self, hash: bool) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// Store the initial state and snapshots with object keys in canonical
+    /// order.
+    pub fn with_canonical_keys(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Metadata to attach to every observation, like the `--meta` values.
+    pub fn with_observation_metadata(mut self, metadata: Map<String, Value>) -> Self {
+        self.observation_metadata = metadata;
+        self
+    }
+
+    /// Diff `state` against the previous one and return the observation,
+    /// for callers that write observations out as they go. The builder
+    /// doesn't keep it; use `push_state` to have `build` write it.
     pub fn add_state(&mut self, state: Value) -> Option<Observation> {
+        self.add_state_with_metadata(state, Map::new())
+    }
+
+    /// Like `add_state`, with `metadata` saying where this state came from.
+    /// The builder's own observation metadata is added on top, so values the
+    /// user passed explicitly win.
+    pub fn add_state_with_metadata(
+        &mut self,
+        state: Value,
+        mut metadata: Map<String, Value>,
+    ) -> Option<Observation> {
         if self.initial_state.is_none() {
             self.initial_state = Some(state.clone());
             self.current_state = state;
@@ -259,30 +535,100 @@ impl ArchiveBuilder {
             observation.add_event(event);
         }
 
+        metadata.extend(self.observation_metadata.clone());
+        if !metadata.is_empty() {
+            observation.metadata = Some(metadata);
+        }
+
         Some(observation)
     }
 
+    /// Add `state` like `add_state`, keeping the observation for `build` and
+    /// `write_to`. The first state pushed to a new builder is the initial one.
+    pub fn push_state(&mut self, state: Value) {
+        self.push_state_with_metadata(state, Map::new());
+    }
+
+    /// Like `push_state`, with metadata as in `add_state_with_metadata`.
+    pub fn push_state_with_metadata(&mut self, state: Value, metadata: Map<String, Value>) {
+        if let Some(observation) = self.add_state_with_metadata(state, metadata) {
+            self.observations.push(observation);
+        }
+    }
+
+    /// Write the archive to `output_path`: the header and every observation
+    /// from `push_state`, or for a `continuing` builder, the new observations
+    /// appended to the archive already there.
     pub fn build<P: AsRef<Path>>(self, output_path: P) -> Result<(), Vec<Diagnostic>> {
-        if self.initial_state.is_none() {
+        let writer = match &self.continuing {
+            Some(continuation) => {
+                ArchiveWriter::new_append(output_path, self.snapshot_interval, continuation.observation_count)?
+            }
+            None => ArchiveWriter::new(output_path, self.snapshot_interval)?,
+        };
+        self.write_with(writer)?.finish()
+    }
+
+    /// Like `build`, writing to `writer` instead of a file, and handing it
+    /// back once the archive is complete. A `continuing` builder writes only
+    /// the new lines, for appending to the archive's bytes, which must end
+    /// with a newline.
+    pub fn write_to<W: Write>(self, writer: W) -> Result<W, Vec<Diagnostic>> {
+        let observation_count = self.continuing.as_ref().map_or(0, |continuation| continuation.observation_count);
+        let writer = ArchiveWriter::from_writer(writer, self.snapshot_interval, observation_count);
+        self.write_with(writer)?.into_inner()
+    }
+
+    fn write_with<W: Write>(self, writer: ArchiveWriter<W>) -> Result<ArchiveWriter<W>, Vec<Diagnostic>> {
+        let Some(mut state) = self.initial_state else {
             return Err(vec![Diagnostic::new(
                 DiagnosticLevel::Fatal,
                 DiagnosticCode::MissingHeaderField,
                 "I can't build an archive without any initial state.".to_string(),
             )]);
-        }
+        };
 
-        let header = Header::new(self.initial_state.unwrap(), self.source);
+        let mut writer = writer
+            .with_snapshot_mode(self.snapshot_mode)
+            .with_canonical_keys(self.canonical);
+        match self.continuing {
+            Some(continuation) => {
+                if let Some(chain) = continuation.chain {
+                    writer = writer.with_hash_chain(chain);
+                }
+            }
+            None => {
+                let mut header = Header::new(state.clone(), self.source);
+                header.metadata = self.metadata;
+                if self.hash {
+                    let hash = state_hash(&header.initial);
+                    writer = writer.with_hash_chain(hash.clone());
+                    header.hash = Some(hash);
+                }
+                writer.write_header(&header)?;
+            }
+        }
 
-        let mut writer = ArchiveWriter::new(output_path, self.snapshot_interval)?;
-        writer.write_header(&header)?;
-        writer.finish()?;
+        // Only the changes were kept, so replay them for the states that
+        // snapshots and hashes are made from
+        for observation in self.observations {
+            for event in &observation.events {
+                apply_event(&mut state, event.clone());
+            }
+            writer.write_observation_with_state(observation, &state)?;
+        }
 
-        Ok(())
+        Ok(writer)
     }
 
     pub fn get_initial_state(&self) -> Option<&Value> {
         self.initial_state.as_ref()
     }
+
+    /// The state the next `add_state` will be diffed against.
+    pub fn current_state(&self) -> &Value {
+        &self.current_state
+    }
 }
 
 /// Generate default output filename from input filename
@@ -317,19 +663,87 @@ pub fn default_output_filename<P: AsRef<Path>>(input_path: P) -> PathBuf {
     output
 }
 
-pub fn create_archive_from_files<P: AsRef<Path>>(
-    input_files: &[P],
-    output_path: P,
-    source: Option<String>,
-    snapshot_interval: Option<usize>,
-) -> Result<(), Vec<Diagnostic>> {
-    let mut builder = ArchiveBuilder::new();
-    if let Some(source) = source {
-        builder = builder.with_source(source);
+/// How to write an archive, for the functions that create or append to one.
+/// Settings that only apply when creating are ignored when appending.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveOptions {
+    /// The header's `source`, when creating.
+    pub source: Option<String>,
+    /// The header's `metadata`, when creating.
+    pub metadata: Option<Value>,
+    pub snapshot_interval: Option<usize>,
+    pub snapshot_mode: SnapshotMode,
+    /// Metadata added to every new observation, next to its provenance.
+    pub observation_metadata: Map<String, Value>,
+    /// Hash every new state. An archive that is already hashed stays hashed
+    /// when appended to either way.
+    pub hash: bool,
+    /// Store the initial state and snapshots with their keys sorted.
+    pub canonical: bool,
+    /// Record the formatting profile of each input file. Not for collections.
+    pub keep_format: bool,
+    /// When appending, skip damaged observations instead of refusing.
+    pub recover: bool,
+}
+
+impl ArchiveOptions {
+    fn header(&self, initial: Value) -> Header {
+        let mut header = Header::new(initial, self.source.clone());
+        header.metadata = self.metadata.clone();
+        header
+    }
+
+    fn builder(&self) -> ArchiveBuilder {
+        let mut builder = ArchiveBuilder::new()
+            .with_snapshot_mode(self.snapshot_mode)
+            .with_observation_metadata(self.observation_metadata.clone())
+            .with_hashes(self.hash)
+            .with_canonical_keys(self.canonical);
+        if let Some(source) = &self.source {
+            builder = builder.with_source(source.clone());
+        }
+        if let Some(interval) = self.snapshot_interval {
+            builder = builder.with_snapshot_interval(interval);
+        }
+        builder
     }
-    if let Some(interval) = snapshot_interval {
-        builder = builder.with_snapshot_interval(interval);
+}
+
+/// Write `header` to a new archive at `output_path`, hashing its initial
+/// state if `options` asks, and return the writer for what comes after it.
+fn start_archive<P: AsRef<Path>>(
+    output_path: P,
+    mut header: Header,
+    options: &ArchiveOptions,
+) -> Result<ArchiveWriter, Vec<Diagnostic>> {
+    let mut writer = ArchiveWriter::new(output_path, options.snapshot_interval)?
+        .with_snapshot_mode(options.snapshot_mode)
+        .with_canonical_keys(options.canonical);
+    if options.hash {
+        let hash = state_hash(&header.initial);
+        writer = writer.with_hash_chain(hash.clone());
+        header.hash = Some(hash);
     }
+    writer.write_header(&header)?;
+    Ok(writer)
+}
+
+/// Start an archive at `output_path` whose initial state is `initial`, with
+/// no observations yet. Add them with `open_append`.
+pub fn create_archive<P: AsRef<Path>>(
+    initial: Value,
+    output_path: P,
+    options: &ArchiveOptions,
+) -> Result<(), Vec<Diagnostic>> {
+    start_archive(output_path, options.header(initial), options)?.finish()
+}
+
+pub fn create_archive_from_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_files: &[P],
+    output_path: Q,
+    options: &ArchiveOptions,
+) -> Result<(), Vec<Diagnostic>> {
+    let mut builder = options.builder();
 
     let first_content = std::fs::read_to_string(&input_files[0]).map_err(|e| {
         vec![Diagnostic::new(
@@ -350,9 +764,11 @@ pub fn create_archive_from_files<P: AsRef<Path>>(
 
     let _ = builder.add_state(first_state.clone());
 
-    let header = Header::new(first_state, builder.source.clone());
-    let mut writer = ArchiveWriter::new(&output_path, builder.snapshot_interval)?;
-    writer.write_header(&header)?;
+    let mut header = options.header(first_state);
+    if options.keep_format {
+        header.format = Some(format_profile(input_files[0].as_ref(), &first_content, &header.initial)?);
+    }
+    let mut writer = start_archive(output_path, header, options)?;
 
     for file_path in input_files[1..].iter() {
         writer.write_comment(&format!("Processing file: {:?}", file_path.as_ref()))?;
@@ -374,140 +790,637 @@ pub fn create_archive_from_files<P: AsRef<Path>>(
             .with_advice("Make sure the file contains valid JSON.".to_string())]
         })?;
 
-        if let Some(observation) = builder.add_state(state.clone()) {
-            writer.write_observation(observation)?;
+        let mut provenance = file_provenance(file_path.as_ref());
+        if options.keep_format {
+            let profile = format_profile(file_path.as_ref(), &content, &state)?;
+            provenance.insert(format::METADATA_KEY.to_string(), format_metadata(profile));
+        }
+        if let Some(observation) = builder.add_state_with_metadata(state.clone(), provenance) {
+            writer.write_observation_with_state(observation, &state)?;
+        }
+    }
 
-            if writer.should_write_snapshot() {
-                writer.write_snapshot(&state)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// The formatting profile that reproduces `content`, the text of the file at
+/// `path` that `state` was parsed from. A file it can't reproduce is refused
+/// rather than recorded without one, since `--keep-format` asked for it.
+pub fn format_profile(path: &Path, content: &str, state: &Value) -> Result<FormatProfile, Vec<Diagnostic>> {
+    verify_roundtrip(content, state).map_err(|mismatch| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::FormatNotReproducible,
+            format!(
+                "I can't reproduce {} byte for byte: rendering its JSON differs from the file at line {}, column {}.",
+                path.display(),
+                mismatch.line,
+                mismatch.column
+            ),
+        )
+        .with_advice(
+            "--keep-format works for files written by a JSON serializer: compact, or indented \
+             with spaces or tabs, without escapes it wouldn't need. Archive this file without \
+             --keep-format to keep its content but not its layout."
+                .to_string(),
+        )]
+    })
+}
+
+fn format_metadata(profile: FormatProfile) -> Value {
+    serde_json::to_value(profile).expect("a format profile always serializes")
+}
+
+/// Observation metadata recording which file a state was read from.
+pub fn file_provenance(path: &Path) -> Map<String, Value> {
+    let mut metadata = Map::new();
+    metadata.insert("file".to_string(), json!(path.display().to_string()));
+    metadata
+}
+
+/// Parses `--meta key=value` arguments into observation metadata. Values are
+/// kept as strings; a later key replaces an earlier one. The `format` key is
+/// reserved for formatting profiles, see `format::METADATA_KEY`.
+pub fn parse_observation_metadata(pairs: &[String]) -> Result<Map<String, Value>, Vec<Diagnostic>> {
+    let mut metadata = Map::new();
+    let mut diagnostics = Vec::new();
+    for pair in pairs {
+        match pair.split_once('=') {
+            Some((format::METADATA_KEY, _)) => diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::WrongFieldType,
+                    format!("I can't record '{}': the {} key is reserved.", pair, format::METADATA_KEY),
+                )
+                .with_advice(
+                    "json-archive records the file's formatting profile under that key with --keep-format. \
+                     Pick another name for your own metadata."
+                        .to_string(),
+                ),
+            ),
+            Some((key, value)) if !key.is_empty() => {
+                metadata.insert(key.to_string(), Value::String(value.to_string()));
             }
+            _ => diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::WrongFieldType,
+                    format!("I expected --meta to look like key=value, but got '{}'.", pair),
+                )
+                .with_advice("Give each piece of metadata a name, for example:\n--meta host=web-1 --meta etag=abc123".to_string()),
+            ),
         }
     }
+    if diagnostics.is_empty() {
+        Ok(metadata)
+    } else {
+        Err(diagnostics)
+    }
+}
 
-    writer.finish()?;
+/// Start a collection archive from the JSON files in `dir`. The header's
+/// initial state holds every document; later runs add observations with
+/// `append_collection_to_archive`.
+pub fn create_collection_archive<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir: P,
+    output_path: Q,
+    options: &ArchiveOptions,
+) -> Result<(), Vec<Diagnostic>> {
+    let mut header = options.header(read_collection(dir)?);
+    header.collection = true;
+    start_archive(output_path, header, options)?.finish()
+}
+
+/// A hidden sibling of `path` to write a replacement into before renaming it
+/// over the original.
+pub(crate) fn temp_path_for(path: &Path) -> PathBuf {
+    path.with_file_name(format!(
+        ".{}.{}.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        Uuid::new_v4()
+    ))
+}
+
+/// Write a replacement for the archive at `path` with `write`: to
+/// `output_path` if given, otherwise to a temporary sibling that is renamed
+/// over the archive once `write` succeeds. A failure leaves the archive as it
+/// was.
+pub(crate) fn write_replacement(
+    path: &Path,
+    output_path: Option<&Path>,
+    write: impl FnOnce(&Path) -> Result<(), Vec<Diagnostic>>,
+) -> Result<(), Vec<Diagnostic>> {
+    if let Some(output_path) = output_path {
+        return write(output_path);
+    }
+
+    let temp_path = temp_path_for(path);
+    let replace = || -> std::io::Result<()> {
+        std::fs::set_permissions(&temp_path, std::fs::metadata(path)?.permissions())?;
+        std::fs::rename(&temp_path, path)
+    };
+    write(&temp_path)
+        .and_then(|()| {
+            replace().map_err(|e| {
+                vec![Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::PathNotFound,
+                    format!("I couldn't replace {} with the rewritten archive: {}", path.display(), e),
+                )]
+            })
+        })
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp_path);
+        })
+}
+
+/// Change the header of an existing archive, leaving every event as it is.
+///
+/// If the new header line is exactly as long as the old one it is written
+/// over it in place. Otherwise the archive is rewritten into a temporary file
+/// next to it, which is then renamed over the original, so an interrupted
+/// rewrite never leaves a half-written archive behind.
+pub fn update_header<P: AsRef<Path>>(
+    archive_path: P,
+    update: impl FnOnce(&mut Header),
+) -> Result<(), Vec<Diagnostic>> {
+    let path = archive_path.as_ref();
+    let filename = path.display().to_string();
+    let io_error = |action: &str, e: std::io::Error| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't {} {}: {}", action, filename, e),
+        )]
+    };
+
+    match crate::reader::is_compressed(path) {
+        Ok(false) => {}
+        Ok(true) => {
+            return Err(vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::UnsupportedVersion,
+                format!("I can't change the header of {} because it is compressed.", filename),
+            )
+            .with_advice(
+                "Decompress the archive, change the header, and compress it again.".to_string(),
+            )]);
+        }
+        Err(e) => return Err(io_error("open", e)),
+    }
+
+    let file = File::open(path).map_err(|e| io_error("open", e))?;
+    let mut reader = BufReader::new(file);
+    let mut old_line = Vec::new();
+    reader
+        .read_until(b'\n', &mut old_line)
+        .map_err(|e| io_error("read", e))?;
+    let has_newline = old_line.last() == Some(&b'\n');
+    if has_newline {
+        old_line.pop();
+    }
+
+    if old_line.is_empty() {
+        return Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::EmptyFile,
+            "I found an empty archive, so there's no header to change.".to_string(),
+        )
+        .with_location(filename.clone(), 1)]);
+    }
+
+    let mut header: Header = serde_json::from_slice(&old_line).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::MissingHeader,
+            format!("I couldn't parse the first line as an archive header: {}", e),
+        )
+        .with_location(filename.clone(), 1)
+        .with_advice("Run `json-archive validate` on the archive to see what's wrong with it.".to_string())]
+    })?;
+
+    update(&mut header);
+
+    let new_line = serde_json::to_string(&header).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::InvalidEventJson,
+            format!("I couldn't serialize the header to JSON: {}", e),
+        )]
+    })?;
+
+    if new_line.len() == old_line.len() {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(|e| io_error("open for writing", e))?;
+        return file
+            .write_all(new_line.as_bytes())
+            .and_then(|()| file.sync_all())
+            .map_err(|e| io_error("write", e));
+    }
+
+    let temp_path = temp_path_for(path);
+    let rewrite = |mut reader: BufReader<File>| -> std::io::Result<()> {
+        let mut temp = BufWriter::new(File::create(&temp_path)?);
+        temp.write_all(new_line.as_bytes())?;
+        if has_newline {
+            temp.write_all(b"\n")?;
+        }
+        std::io::copy(&mut reader, &mut temp)?;
+        drop(reader);
+        let temp = temp.into_inner().map_err(|e| e.into_error())?;
+        temp.set_permissions(std::fs::metadata(path)?.permissions())?;
+        temp.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    };
+    rewrite(reader).map_err(|e| {
+        let _ = std::fs::remove_file(&temp_path);
+        io_error("rewrite", e)
+    })
+}
+
+/// Read a JSON file, and with `keep_format` the profile that reproduces it.
+fn read_json_file(path: &Path, keep_format: bool) -> Result<(Value, Option<FormatProfile>), Vec<Diagnostic>> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't read the input file: {}", e),
+        )]
+    })?;
+
+    let state = serde_json::from_str(&content).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::InvalidEventJson,
+            format!("I couldn't parse the input file as JSON: {}", e),
+        )
+        .with_advice("Make sure the file contains valid JSON.".to_string())]
+    })?;
+
+    let profile = match keep_format {
+        true => Some(format_profile(path, &content, &state)?),
+        false => None,
+    };
+    Ok((state, profile))
+}
+
+fn terminate_last_line(file: &mut File) -> std::io::Result<()> {
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(());
+    }
+    let mut last = [0u8; 1];
+    file.seek(SeekFrom::Start(len - 1))?;
+    file.read_exact(&mut last)?;
+    if last[0] != b'\n' {
+        file.write_all(b"\n")?;
+    }
     Ok(())
 }
 
+/// What `append` adds to an archive: one observation per JSON file, or the
+/// whole directory of a collection archive as one observation.
+enum AppendInput<'a, Q> {
+    Files(&'a [Q]),
+    Collection(&'a Path),
+}
+
+/// A state to append, with the comment that introduces it and the metadata
+/// saying where it came from.
+struct NewState {
+    comment: String,
+    provenance: Map<String, Value>,
+    state: Result<Value, Vec<Diagnostic>>,
+}
+
 pub fn append_to_archive<P: AsRef<Path>, Q: AsRef<Path>>(
     archive_path: P,
     new_files: &[Q],
     output_path: P,
-    source: Option<String>,
-    snapshot_interval: Option<usize>,
+    options: &ArchiveOptions,
+) -> Vec<Diagnostic> {
+    append(archive_path, AppendInput::Files(new_files), output_path, options)
+}
+
+/// Add the current contents of `dir` to a collection archive as one
+/// observation. Files that appeared since the last run are added, files that
+/// are gone are removed, and the rest are diffed as usual.
+pub fn append_collection_to_archive<P: AsRef<Path>, Q: AsRef<Path>>(
+    archive_path: P,
+    dir: Q,
+    output_path: P,
+    options: &ArchiveOptions,
 ) -> Vec<Diagnostic> {
+    append(
+        archive_path,
+        AppendInput::<&Path>::Collection(dir.as_ref()),
+        output_path,
+        options,
+    )
+}
+
+/// An archive read and opened for appending by `open_append`.
+pub struct OpenedArchive {
+    pub header: Header,
+    /// Positioned at the end of the output archive.
+    pub writer: ArchiveWriter,
+    /// Holds the archive's final state, so `add_state` diffs against it.
+    pub builder: ArchiveBuilder,
+    /// In recover mode, warnings about the observations that were dropped.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Read the archive at `archive_path` and open `output_path` for appending,
+/// copying the archive there first if the paths differ. `collection` says
+/// whether the caller appends whole directories or single files; an archive
+/// of the other kind is refused. Callers that keep appending, like watch mode,
+/// hold on to the result instead of re-reading the archive for every state.
+///
+/// New observations are hashed if the archive already hashes them, or if
+/// `options.hash` asks to start.
+pub fn open_append<P: AsRef<Path>, Q: AsRef<Path>>(
+    archive_path: P,
+    output_path: Q,
+    options: &ArchiveOptions,
+    collection: bool,
+) -> Result<OpenedArchive, Vec<Diagnostic>> {
+    let recover = options.recover;
+    let read_mode = if recover { ReadMode::Recover } else { ReadMode::AppendSeek };
+
     // Read the existing archive to get the final state
-    let reader = match ArchiveReader::new(&archive_path, ReadMode::AppendSeek) {
+    let reader = match ArchiveReader::new(&archive_path, read_mode) {
         Ok(r) => r,
         Err(e) => {
-            return vec![Diagnostic::new(
+            return Err(vec![Diagnostic::new(
                 DiagnosticLevel::Fatal,
                 DiagnosticCode::PathNotFound,
                 format!("I couldn't open the archive for reading: {}", e),
-            )];
+            )]);
         }
     };
 
     let read_result = match reader.read(&archive_path) {
         Ok(result) => result,
         Err(e) => {
-            return vec![Diagnostic::new(
+            return Err(vec![Diagnostic::new(
                 DiagnosticLevel::Fatal,
                 DiagnosticCode::PathNotFound,
                 format!("I couldn't read the archive: {}", e),
-            )];
+            )]);
         }
     };
 
     // Check for fatal diagnostics in the archive
     if read_result.diagnostics.has_fatal() {
-        let mut diagnostics = vec![Diagnostic::new(
+        let mut corrupt = Diagnostic::new(
             DiagnosticLevel::Fatal,
             DiagnosticCode::InvalidEventJson,
             "The existing archive contains fatal errors. Cannot append to a corrupt archive.".to_string(),
-        )];
+        );
+        if !recover {
+            corrupt = corrupt.with_advice(
+                "Pass --recover to skip the damaged observations and append to what is intact."
+                    .to_string(),
+            );
+        }
+        let mut diagnostics = vec![corrupt];
         diagnostics.extend(read_result.diagnostics.into_diagnostics());
-        return diagnostics;
+        return Err(diagnostics);
+    }
+
+    // Diffing a directory against a single document, or the other way
+    // round, would replace everything in one observation
+    if read_result.header.collection && !collection {
+        return Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::MissingHeaderField,
+            format!(
+                "I found that {} is a collection archive, but you gave me individual files to append.",
+                archive_path.as_ref().display()
+            ),
+        )
+        .with_advice(format!(
+            "A collection archive tracks a whole directory. Pass the directory instead:\n\
+             json-archive --dir <directory> -o {}",
+            archive_path.as_ref().display()
+        ))]);
+    }
+    if !read_result.header.collection && collection {
+        return Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::MissingHeaderField,
+            format!(
+                "I found that {} tracks a single JSON file, so I can't append a directory to it.",
+                archive_path.as_ref().display()
+            ),
+        )
+        .with_advice(
+            "Start a collection archive for the directory with a different output path:\n\
+             json-archive --dir <directory> -o <directory>.json.archive"
+                .to_string(),
+        )]);
     }
 
+    // In recover mode these are warnings about what was dropped; pass them on
+    let diagnostics = if recover {
+        read_result.diagnostics.into_diagnostics()
+    } else {
+        Vec::new()
+    };
+
     // If output path is different from archive path, copy the archive first
     if archive_path.as_ref() != output_path.as_ref() {
         if let Err(e) = std::fs::copy(&archive_path, &output_path) {
-            return vec![Diagnostic::new(
+            return Err(vec![Diagnostic::new(
                 DiagnosticLevel::Fatal,
                 DiagnosticCode::PathNotFound,
                 format!("I couldn't copy the archive to the output location: {}", e),
-            )];
+            )]);
         }
     }
 
     // Create an append writer
-    let mut writer = match ArchiveWriter::new_append(&output_path, snapshot_interval, read_result.observation_count) {
-        Ok(w) => w,
-        Err(diagnostics) => return diagnostics,
+    let mut writer = match ArchiveWriter::new_append(&output_path, options.snapshot_interval, read_result.observation_count)
+    {
+        Ok(w) => w.with_snapshot_mode(options.snapshot_mode).with_canonical_keys(options.canonical),
+        Err(diagnostics) => return Err(diagnostics),
     };
 
-    // Create a builder to track state changes
-    let mut builder = ArchiveBuilder::new();
-    if let Some(source) = source {
-        builder = builder.with_source(source);
-    }
-    if let Some(interval) = snapshot_interval {
-        builder = builder.with_snapshot_interval(interval);
+    // Continue the hash chain from its last link, or start it from the
+    // initial state like a verifier would
+    let chain = read_result
+        .chain
+        .or_else(|| read_result.header.hash.clone())
+        .or_else(|| options.hash.then(|| state_hash(&read_result.header.initial)));
+    if let Some(chain) = chain {
+        writer = writer.with_hash_chain(chain);
     }
 
+    // Create a builder to track state changes
+    let mut builder = options.builder();
+
     // Initialize builder with the final state from the archive
     let current_state = read_result.final_state;
     builder.current_state = current_state.clone();
-    builder.initial_state = Some(current_state.clone());
+    builder.initial_state = Some(current_state);
+
+    Ok(OpenedArchive {
+        header: read_result.header,
+        writer,
+        builder,
+        diagnostics,
+    })
+}
 
-    // Process each new file
-    for file_path in new_files.iter() {
-        if let Err(diagnostics) = writer.write_comment(&format!("Processing file: {:?}", file_path.as_ref())) {
-            return diagnostics;
-        }
+fn append<P: AsRef<Path>, Q: AsRef<Path>>(
+    archive_path: P,
+    input: AppendInput<Q>,
+    output_path: P,
+    options: &ArchiveOptions,
+) -> Vec<Diagnostic> {
+    let collection = matches!(input, AppendInput::Collection(_));
+    let OpenedArchive {
+        mut writer,
+        mut builder,
+        diagnostics: mut read_diagnostics,
+        ..
+    } = match open_append(&archive_path, &output_path, options, collection) {
+        Ok(opened) => opened,
+        Err(diagnostics) => return diagnostics,
+    };
 
-        let content = match std::fs::read_to_string(file_path) {
-            Ok(content) => content,
-            Err(e) => {
-                return vec![Diagnostic::new(
-                    DiagnosticLevel::Fatal,
-                    DiagnosticCode::PathNotFound,
-                    format!("I couldn't read the input file: {}", e),
-                )];
+    let states: Box<dyn Iterator<Item = NewState>> = match input {
+        AppendInput::Files(files) => Box::new(files.iter().map(|file_path| {
+            let mut provenance = file_provenance(file_path.as_ref());
+            let state = read_json_file(file_path.as_ref(), options.keep_format).map(|(state, profile)| {
+                if let Some(profile) = profile {
+                    provenance.insert(format::METADATA_KEY.to_string(), format_metadata(profile));
+                }
+                state
+            });
+            NewState {
+                comment: format!("Processing file: {:?}", file_path.as_ref()),
+                provenance,
+                state,
             }
-        };
+        })),
+        AppendInput::Collection(dir) => Box::new(std::iter::once(NewState {
+            comment: format!("Processing directory: {:?}", dir),
+            provenance: Map::from_iter([("directory".to_string(), json!(dir.display().to_string()))]),
+            state: read_collection(dir),
+        })),
+    };
+
+    for NewState { comment, provenance, state } in states {
+        if let Err(diagnostics) = writer.write_comment(&comment) {
+            return diagnostics;
+        }
 
-        let state: Value = match serde_json::from_str(&content) {
+        let state = match state {
             Ok(state) => state,
-            Err(e) => {
-                return vec![Diagnostic::new(
-                    DiagnosticLevel::Fatal,
-                    DiagnosticCode::InvalidEventJson,
-                    format!("I couldn't parse the input file as JSON: {}", e),
-                )
-                .with_advice("Make sure the file contains valid JSON.".to_string())];
-            }
+            Err(diagnostics) => return diagnostics,
         };
 
-        if let Some(observation) = builder.add_state(state.clone()) {
-            if let Err(diagnostics) = writer.write_observation(observation) {
+        if let Some(observation) = builder.add_state_with_metadata(state.clone(), provenance) {
+            if let Err(diagnostics) = writer.write_observation_with_state(observation, &state) {
                 return diagnostics;
             }
-
-            if writer.should_write_snapshot() {
-                if let Err(diagnostics) = writer.write_snapshot(&state) {
-                    return diagnostics;
-                }
-            }
         }
     }
 
     // Finish writing
     match writer.finish() {
-        Ok(()) => Vec::new(),
-        Err(diagnostics) => diagnostics,
+        Ok(()) => read_diagnostics,
+        Err(diagnostics) => {
+            read_diagnostics.extend(diagnostics);
+            read_diagnostics
+        }
     }
 }
 
+/// Write `contents` to `path`, compressed the way the file's extension asks:
+/// `.gz`, `.zlib`, `.deflate`, `.zst` or `.br`, the same formats archives can
+/// be read from.
+pub fn write_output_file(path: &Path, contents: &[u8]) -> Result<(), Vec<Diagnostic>> {
+    let filename = path.display().to_string();
+    let compression = compression_from_extension(path);
+
+    #[cfg(not(feature = "compression"))]
+    if compression != CompressionFormat::None {
+        return Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::UnsupportedVersion,
+            format!(
+                "I can't write {} {}-compressed because this build doesn't support compression.",
+                filename,
+                compression.name()
+            ),
+        )
+        .with_advice(
+            "Install the version with compression support: cargo install json-archive --features compression\n\
+             Or write to a file without a compression extension and compress it yourself."
+                .to_string(),
+        )]);
+    }
+
+    let io_error = |action: &str, e: std::io::Error| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't {} {}: {}", action, filename, e),
+        )]
+    };
+
+    let file = File::create(path).map_err(|e| io_error("create", e))?;
+    encode(file, compression, contents).map_err(|e| io_error("write", e))
+}
+
+#[cfg(feature = "compression")]
+fn encode(mut file: File, compression: CompressionFormat, contents: &[u8]) -> std::io::Result<()> {
+    let level = flate2::Compression::default();
+    let file = match compression {
+        CompressionFormat::Gzip => {
+            let mut encoder = GzEncoder::new(file, level);
+            encoder.write_all(contents)?;
+            encoder.finish()?
+        }
+        CompressionFormat::Zlib => {
+            let mut encoder = ZlibEncoder::new(file, level);
+            encoder.write_all(contents)?;
+            encoder.finish()?
+        }
+        CompressionFormat::Deflate => {
+            let mut encoder = DeflateEncoder::new(file, level);
+            encoder.write_all(contents)?;
+            encoder.finish()?
+        }
+        CompressionFormat::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(file, 0)?;
+            encoder.write_all(contents)?;
+            encoder.finish()?
+        }
+        CompressionFormat::Brotli => {
+            let params = brotli::enc::BrotliEncoderParams::default();
+            brotli::BrotliCompress(&mut &contents[..], &mut file, &params)?;
+            file
+        }
+        CompressionFormat::None => {
+            file.write_all(contents)?;
+            file
+        }
+    };
+    file.sync_all()
+}
+
+#[cfg(not(feature = "compression"))]
+fn encode(mut file: File, _compression: CompressionFormat, contents: &[u8]) -> std::io::Result<()> {
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -558,6 +1471,54 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_in_memory_archive() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::replay::ObservationStates;
+        use std::io::Cursor;
+
+        let mut builder = ArchiveBuilder::new().with_hashes(true).with_snapshot_interval(2);
+        for count in 0..3 {
+            builder.push_state(json!({"count": count}));
+        }
+        let bytes = builder.write_to(Vec::new()).map_err(|d| format!("{:?}", d))?;
+
+        let reader = ArchiveReader::new("memory", ReadMode::FullValidation)?;
+        let result = reader.read_from(Cursor::new(bytes.clone()))?;
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+        assert_eq!(result.final_state, json!({"count": 2}));
+        assert_eq!(result.snapshot_count, 1);
+
+        // Append to the bytes, continuing the hash chain
+        let mut builder = ArchiveBuilder::continuing(&result)
+            .map_err(|d| format!("{:?}", d))?
+            .with_snapshot_interval(2);
+        builder.push_state(json!({"count": 3, "done": true}));
+
+        // Not on top of a damaged archive
+        let mut damaged = bytes.clone();
+        damaged.extend_from_slice(b"[\"change\", \"/missing/key\", 1, \"obs-x\"]\n");
+        let damaged = reader.read_from(Cursor::new(damaged))?;
+        assert!(damaged.diagnostics.has_fatal());
+        assert!(ArchiveBuilder::continuing(&damaged).is_err());
+
+        let mut appended = bytes;
+        builder.write_to(&mut appended).map_err(|d| format!("{:?}", d))?;
+
+        let result = reader.read_from(Cursor::new(appended.clone()))?;
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+        assert_eq!(result.final_state, json!({"count": 3, "done": true}));
+        assert!(result.chain.is_some());
+
+        let (state, events) = reader.events_from(Cursor::new(appended))?;
+        let counts: Vec<_> = ObservationStates::from_events(state, events)
+            .map_err(|d| format!("{:?}", d))?
+            .map(|observation| observation.state["count"].clone())
+            .collect();
+        assert_eq!(counts, [json!(0), json!(1), json!(2), json!(3)]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_create_archive_from_files() -> Result<(), Box<dyn std::error::Error>> {
         // Create temporary input files
@@ -573,8 +1534,11 @@ mod tests {
         create_archive_from_files(
             &input_files,
             output_file.path(),
-            Some("test-source".to_string()),
-            None,
+            &ArchiveOptions {
+                source: Some("test-source".to_string()),
+                observation_metadata: Map::from_iter([("host".to_string(), json!("web-1"))]),
+                ..Default::default()
+            },
         )
         .map_err(|_| "Failed to create archive")?;
 
@@ -589,6 +1553,47 @@ mod tests {
         assert_eq!(header.version, 1);
         assert_eq!(header.initial, json!({"count": 0, "name": "test"}));
 
+        // The observation says which file it came from, plus the extra metadata
+        let observe = lines.iter().find(|line| line.starts_with(r#"["observe""#)).ok_or("no observe line")?;
+        let observe: Value = serde_json::from_str(observe)?;
+        assert_eq!(
+            observe[4],
+            json!({"file": file2.path().display().to_string(), "host": "web-1"})
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_header() -> Result<(), Box<dyn std::error::Error>> {
+        let mut archive = NamedTempFile::new()?;
+        writeln!(archive, r#"{{"type":"@peoplesgrocers/json-archive","version":1,"created":"2025-01-01T00:00:00Z","initial":{{"count":0}},"metadata":{{"owner":"a"}}}}"#)?;
+        writeln!(archive, r#"["observe","obs-1","2025-01-02T00:00:00Z",1]"#)?;
+        writeln!(archive, r#"["change","/count",1,"obs-1"]"#)?;
+        archive.flush()?;
+        let events = std::fs::read_to_string(archive.path())?.lines().skip(1).collect::<Vec<_>>().join("\n");
+
+        // Same length: written over the old header
+        update_header(archive.path(), |header| header.metadata = Some(json!({"owner": "b"})))
+            .map_err(|d| format!("{:?}", d))?;
+        // Longer: rewritten through a temporary file
+        update_header(archive.path(), |header| {
+            header.metadata = Some(json!({"owner": "b", "project": "demo"}))
+        })
+        .map_err(|d| format!("{:?}", d))?;
+
+        let content = std::fs::read_to_string(archive.path())?;
+        let (header, rest) = content.split_once('\n').ok_or("no header line")?;
+        let header: Header = serde_json::from_str(header)?;
+        assert_eq!(header.metadata, Some(json!({"owner": "b", "project": "demo"})));
+        assert_eq!(header.initial, json!({"count": 0}));
+        assert_eq!(rest.trim_end(), events);
+
+        let reader = ArchiveReader::new(archive.path(), ReadMode::FullValidation)?;
+        let result = reader.read(archive.path())?;
+        assert!(result.diagnostics.is_empty());
+        assert_eq!(result.final_state, json!({"count": 1}));
+
         Ok(())
     }
 
@@ -615,6 +1620,391 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_replace_snapshot_mode() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_file = NamedTempFile::new()?;
+        let mut writer = ArchiveWriter::new(temp_file.path(), Some(2))
+            .map_err(|_| "Failed to create writer")?
+            .with_snapshot_mode(SnapshotMode::Replace);
+        writer
+            .write_header(&Header::new(json!({"count": 0}), None))
+            .map_err(|_| "Failed to write header")?;
+
+        for (i, id) in ["obs-1", "obs-2"].iter().enumerate() {
+            let mut observation = Observation::new(id.to_string(), Utc::now());
+            observation.metadata = Some(Map::from_iter([("source".to_string(), json!(id))]));
+            observation.add_event(Event::Change {
+                path: "/count".to_string(),
+                new_value: json!(i + 1),
+                observation_id: id.to_string(),
+            });
+            writer
+                .write_observation_with_state(observation, &json!({"count": i + 1}))
+                .map_err(|_| "Failed to write observation")?;
+        }
+        writer.finish().map_err(|_| "Failed to finish")?;
+
+        let content = std::fs::read_to_string(temp_file.path())?;
+        let lines: Vec<&str> = content.lines().collect();
+        // header, observe + change for obs-1, then obs-2 written as a snapshot
+        assert_eq!(lines.len(), 4);
+        let snapshot: Value = serde_json::from_str(lines[3])?;
+        assert_eq!(snapshot[0], json!("snapshot"));
+        assert_eq!(snapshot[1], json!("obs-2"));
+        assert_eq!(snapshot[3], json!({"count": 2}));
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::FullValidation)?;
+        let result = reader.read(temp_file.path())?;
+        assert!(!result.diagnostics.has_fatal());
+        assert_eq!(result.observation_count, 2);
+        assert_eq!(result.final_state, json!({"count": 2}));
+
+        // The snapshot keeps the metadata the observation was recorded with
+        let states: Vec<_> = crate::replay::observation_states(temp_file.path(), ReadMode::FullValidation)
+            .map_err(|_| "Failed to replay")?
+            .collect();
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[2].observation_id, "obs-2");
+        assert_eq!(states[2].metadata, Some(Map::from_iter([("source".to_string(), json!("obs-2"))])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_snapshot_reuses_observation_id() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_file = NamedTempFile::new()?;
+        let mut writer =
+            ArchiveWriter::new(temp_file.path(), Some(1)).map_err(|_| "Failed to create writer")?;
+        writer
+            .write_header(&Header::new(json!({"count": 0}), None))
+            .map_err(|_| "Failed to write header")?;
+
+        let mut observation = Observation::new("obs-1".to_string(), Utc::now());
+        observation.add_event(Event::Change {
+            path: "/count".to_string(),
+            new_value: json!(1),
+            observation_id: "obs-1".to_string(),
+        });
+        writer
+            .write_observation_with_state(observation, &json!({"count": 1}))
+            .map_err(|_| "Failed to write observation")?;
+        writer.finish().map_err(|_| "Failed to finish")?;
+
+        let content = std::fs::read_to_string(temp_file.path())?;
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 4);
+        let snapshot: Value = serde_json::from_str(lines[3])?;
+        assert_eq!(snapshot[1], json!("obs-1"));
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::FullValidation)?;
+        let result = reader.read(temp_file.path())?;
+        assert!(result.diagnostics.is_empty());
+        assert_eq!(result.observation_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_recovers_interrupted_write() -> Result<(), Box<dyn std::error::Error>> {
+        let mut archive = NamedTempFile::new()?;
+        writeln!(archive, "{}", serde_json::to_string(&Header::new(json!({"count": 0}), None))?)?;
+        writeln!(archive, r#"["observe", "obs-1", "2025-01-01T00:00:00Z", 1]"#)?;
+        writeln!(archive, r#"["change", "/count", 1, "obs-1"]"#)?;
+        writeln!(archive, r#"["observe", "obs-2", "2025-01-02T00:00:00Z", 1]"#)?;
+        write!(archive, r#"["change", "/count", 2"#)?;
+        archive.flush()?;
+
+        let mut input = NamedTempFile::new()?;
+        writeln!(input, r#"{{"count": 3}}"#)?;
+        input.flush()?;
+
+        let diagnostics = append_to_archive(
+            archive.path(),
+            &[input.path()],
+            archive.path(),
+            &ArchiveOptions::default(),
+        );
+        assert!(diagnostics.iter().any(|d| d.is_fatal()));
+
+        let diagnostics = append_to_archive(
+            archive.path(),
+            &[input.path()],
+            archive.path(),
+            &ArchiveOptions {
+                recover: true,
+                ..Default::default()
+            },
+        );
+        assert!(!diagnostics.is_empty());
+        assert!(!diagnostics.iter().any(|d| d.is_fatal()));
+
+        // The appended observation starts on its own line and diffs against obs-1
+        let content = std::fs::read_to_string(archive.path())?;
+        assert!(content.contains("[\"change\", \"/count\", 2\n"));
+
+        let reader = ArchiveReader::new(archive.path(), ReadMode::Recover)?;
+        let result = reader.read(archive.path())?;
+        assert_eq!(result.final_state, json!({"count": 3}));
+        assert_eq!(result.observation_count, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_append_keeps_state() -> Result<(), Box<dyn std::error::Error>> {
+        let mut archive = NamedTempFile::new()?;
+        writeln!(archive, "{}", serde_json::to_string(&Header::new(json!({"count": 0}), None))?)?;
+        writeln!(archive, r#"["observe", "obs-1", "2025-01-01T00:00:00Z", 1]"#)?;
+        writeln!(archive, r#"["change", "/count", 1, "obs-1"]"#)?;
+        archive.flush()?;
+
+        let OpenedArchive { mut writer, mut builder, .. } = open_append(
+            archive.path(),
+            archive.path(),
+            &ArchiveOptions {
+                snapshot_interval: Some(2),
+                ..Default::default()
+            },
+            false,
+        )
+        .map_err(|d| format!("{:?}", d))?;
+        assert_eq!(builder.current_state(), &json!({"count": 1}));
+
+        // Each state diffs against the one before, without re-reading
+        for count in [2, 3] {
+            let state = json!({"count": count});
+            let observation = builder.add_state(state.clone()).ok_or("no observation")?;
+            assert_eq!(observation.events.len(), 1);
+            writer.write_observation_with_state(observation, &state).map_err(|d| format!("{:?}", d))?;
+            writer.flush().map_err(|d| format!("{:?}", d))?;
+        }
+
+        let reader = ArchiveReader::new(archive.path(), ReadMode::FullValidation)?;
+        let result = reader.read(archive.path())?;
+        assert!(!result.diagnostics.has_fatal());
+        assert_eq!(result.final_state, json!({"count": 3}));
+        assert_eq!(result.observation_count, 3);
+        assert_eq!(result.snapshot_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_chain() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let inputs: Vec<PathBuf> = (0..4)
+            .map(|count| {
+                let path = dir.path().join(format!("{}.json", count));
+                std::fs::write(&path, json!({"count": count, "name": "test"}).to_string())?;
+                Ok(path)
+            })
+            .collect::<std::io::Result<_>>()?;
+        let archive = dir.path().join("test.json.archive");
+
+        create_archive_from_files(
+            &inputs[..2],
+            archive.clone(),
+            &ArchiveOptions {
+                snapshot_interval: Some(2),
+                hash: true,
+                ..Default::default()
+            },
+        )
+        .map_err(|d| format!("{:?}", d))?;
+
+        // Appending picks the chain up from the last hashed event
+        let diagnostics = append_to_archive(
+            &archive,
+            &inputs[2..],
+            &archive,
+            &ArchiveOptions {
+                snapshot_interval: Some(2),
+                ..Default::default()
+            },
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let reader = ArchiveReader::new(&archive, ReadMode::FullValidation)?;
+        let result = reader.read(&archive)?;
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics.diagnostics());
+        assert_eq!(result.final_state, json!({"count": 3, "name": "test"}));
+        assert!(result.chain.is_some());
+
+        // Editing a recorded change no longer matches the state hash
+        let content = std::fs::read_to_string(&archive)?;
+        let tampered = dir.path().join("tampered.json.archive");
+        std::fs::write(&tampered, content.replacen(r#"["change","/count",1,"#, r#"["change","/count",7,"#, 1))?;
+        let result = reader.read(&tampered)?;
+        assert!(result
+            .diagnostics
+            .diagnostics()
+            .iter()
+            .any(|d| d.code == DiagnosticCode::StateHashMismatch));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "preserve-order")]
+    fn test_keep_format() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::resolve::{resolve_state, StateSelector};
+
+        let versions = [
+            "{\n  \"name\": \"test\",\n  \"count\": 0\n}\n",
+            // Different indent, a key added in the middle, no trailing newline
+            "{\n\t\"name\": \"test\",\n\t\"tags\": [],\n\t\"count\": 1\n}",
+            "{\"count\":2,\"name\":\"test\",\"tags\":[\"a\"]}\n",
+        ];
+        let dir = tempfile::tempdir()?;
+        let inputs = versions
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let path = dir.path().join(format!("{}.json", i));
+                std::fs::write(&path, text)?;
+                Ok(path)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let archive = dir.path().join("test.json.archive");
+
+        create_archive_from_files(
+            &inputs[..2],
+            archive.clone(),
+            &ArchiveOptions {
+                keep_format: true,
+                ..Default::default()
+            },
+        )
+        .map_err(|d| format!("{:?}", d))?;
+        let diagnostics = append_to_archive(
+            &archive,
+            &inputs[2..],
+            &archive,
+            &ArchiveOptions {
+                keep_format: true,
+                ..Default::default()
+            },
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        for (index, expected) in versions.iter().enumerate() {
+            let resolved = resolve_state(&archive, &StateSelector::Index(index), ReadMode::AppendSeek)
+                .map_err(|d| format!("{:?}", d))?;
+            let profile = if index == 0 {
+                resolved.header.format.clone()
+            } else {
+                FormatProfile::from_metadata(resolved.metadata.as_ref()).transpose()?
+            };
+            assert_eq!(profile.ok_or("no format recorded")?.render(&resolved.state), *expected);
+        }
+
+        // A file that can't be reproduced is refused, not archived without its format
+        let irregular = dir.path().join("irregular.json");
+        std::fs::write(&irregular, "{ \"count\": 3 }\n")?;
+        let diagnostics = append_to_archive(
+            &archive,
+            &[&irregular],
+            &archive,
+            &ArchiveOptions {
+                keep_format: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(diagnostics[0].code, DiagnosticCode::FormatNotReproducible);
+        assert!(diagnostics[0].description.contains("line 1, column 2"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_write_output_file_compression() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::resolve::{resolve_state, StateSelector};
+
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("source.json.archive");
+        let mut writer = ArchiveWriter::new(&source, None).map_err(|e| format!("{:?}", e))?;
+        writer
+            .write_header(&Header::new(json!({"count": 1}), None))
+            .map_err(|e| format!("{:?}", e))?;
+        writer.finish().map_err(|e| format!("{:?}", e))?;
+        let contents = std::fs::read(&source)?;
+
+        // Whatever extension it was written with, the reader must find its way back
+        for ext in ["gz", "zlib", "deflate", "zst", "br"] {
+            let path = dir.path().join(format!("test.json.archive.{}", ext));
+            write_output_file(&path, &contents).map_err(|e| format!("{:?}", e))?;
+            assert_ne!(std::fs::read(&path)?, contents, "{} output wasn't compressed", ext);
+
+            let resolved = resolve_state(&path, &StateSelector::Latest, ReadMode::FullValidation)
+                .map_err(|e| format!("{}: {:?}", ext, e))?;
+            assert_eq!(resolved.state, json!({"count": 1}), "{}", ext);
+        }
+
+        let plain = dir.path().join("state.json");
+        write_output_file(&plain, b"{}\n").map_err(|e| format!("{:?}", e))?;
+        assert_eq!(std::fs::read(&plain)?, b"{}\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let docs = dir.path().join("docs");
+        std::fs::create_dir(&docs)?;
+        let archive = dir.path().join("docs.json.archive");
+
+        std::fs::write(docs.join("a.json"), r#"{"size": 1}"#)?;
+        std::fs::write(docs.join("b.json"), r#"{"size": 2}"#)?;
+        create_collection_archive(&docs, &archive, &ArchiveOptions::default()).map_err(|d| format!("{:?}", d))?;
+
+        std::fs::remove_file(docs.join("b.json"))?;
+        std::fs::write(docs.join("c.json"), r#"{"size": 3}"#)?;
+        let diagnostics = append_collection_to_archive(
+            &archive,
+            &docs,
+            &archive,
+            &ArchiveOptions::default(),
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let reader = ArchiveReader::new(&archive, ReadMode::FullValidation)?;
+        let result = reader.read(&archive)?;
+        assert!(result.header.collection);
+        assert_eq!(result.final_state, json!({"a.json": {"size": 1}, "c.json": {"size": 3}}));
+        assert_eq!(result.observation_count, 1);
+
+        // Whole files come and go as adds and removes of their key
+        let content = std::fs::read_to_string(&archive)?;
+        assert!(content.contains(r#"["remove","/b.json","#));
+        assert!(content.contains(r#"["add","/c.json",{"size":3},"#));
+
+        // A plain file can't be appended to a collection
+        let single = dir.path().join("single.json");
+        std::fs::write(&single, r#"{"size": 4}"#)?;
+        let diagnostics = append_to_archive(
+            &archive,
+            &[&single],
+            &archive,
+            &ArchiveOptions::default(),
+        );
+        assert!(diagnostics.iter().any(|d| d.is_fatal()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_observation_metadata() {
+        let metadata = parse_observation_metadata(&["host=web-1".to_string(), "note=a=b".to_string()]).unwrap();
+        assert_eq!(Value::Object(metadata), json!({"host": "web-1", "note": "a=b"}));
+
+        let errors = parse_observation_metadata(&["format=yaml".to_string()]).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].description.contains("reserved"), "{:?}", errors);
+        assert!(parse_observation_metadata(&["=x".to_string()]).is_err());
+    }
+
     #[test]
     fn test_default_output_filename() {
         assert_eq!(
@@ -0,0 +1,263 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Canonical JSON serialization, following RFC 8785 (JSON Canonicalization
+//! Scheme).
+//!
+//! serde_json's output depends on how a value was built: object keys come out
+//! in map order, and `1.0` and `1` are different numbers. Two equal documents
+//! need to serialize to the same bytes before their hashes can be compared,
+//! so here object keys are sorted by their UTF-16 code units, numbers are
+//! written the way JavaScript's `Number.prototype.toString` writes them, and
+//! there is no whitespace. Where RFC 8785 would round a number to a double,
+//! its exact digits are kept instead.
+
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+
+/// The canonical serialization of `value`.
+pub fn to_canonical_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, &mut out);
+    out
+}
+
+/// A copy of `value` with every object's keys in canonical order, for
+/// writing states that read the same however their input was laid out.
+/// Numbers are left alone, so they keep the text they were read with.
+pub fn canonicalize(value: &Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.iter().map(canonicalize).collect()),
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| compare_keys(a, b));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), canonicalize(value)))
+                    .collect(),
+            )
+        }
+        other => other.clone(),
+    }
+}
+
+/// RFC 8785 orders object keys by their UTF-16 code units.
+fn compare_keys(a: &str, b: &str) -> Ordering {
+    a.encode_utf16().cmp(b.encode_utf16())
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => write_number(n, out),
+        Value::String(s) => write_string(s, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| compare_keys(a, b));
+
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_value(value, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// serde_json escapes exactly the characters RFC 8785 asks for: quote,
+/// backslash, and control characters, using the short forms where JSON has
+/// them and lowercase `\u00xx` otherwise.
+fn write_string(s: &str, out: &mut String) {
+    out.push_str(&serde_json::to_string(s).expect("a string always serializes"));
+}
+
+/// Numbers are written from their exact decimal value, placed the way
+/// ECMAScript's `Number.prototype.toString` places digits. For a number
+/// written as a double's shortest form, which is every float serde_json
+/// holds without `arbitrary-precision`, that is what RFC 8785 writes. Digits
+/// a double can't hold are kept rather than rounded away, so a state hash
+/// changes when any digit does.
+fn write_number(n: &Number, out: &mut String) {
+    let text = n.to_string();
+    match decimal_parts(&text) {
+        Some((negative, digits, exponent)) => write_decimal(negative, &digits, exponent, out),
+        // An exponent too large to place; no double comes near it
+        None => out.push_str(&text),
+    }
+}
+
+/// The value of a JSON number's text as its sign, its significant digits
+/// without leading or trailing zeros, and `n` such that the value is
+/// 0.digits × 10ⁿ. Zero has no digits.
+fn decimal_parts(text: &str) -> Option<(bool, String, i64)> {
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.trim_start_matches('+').parse::<i64>().ok()?),
+        None => (unsigned, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let all_digits = format!("{}{}", integer, fraction);
+    let leading_zeros = all_digits.len() - all_digits.trim_start_matches('0').len();
+    let digits = all_digits.trim_matches('0').to_string();
+    if digits.is_empty() {
+        return Some((false, digits, 0));
+    }
+    let n = exponent.checked_add(integer.len() as i64)?.checked_sub(leading_zeros as i64)?;
+    Some((negative, digits, n))
+}
+
+/// ECMAScript's Number::toString placement of `digits` with value
+/// 0.digits × 10ⁿ.
+fn write_decimal(negative: bool, digits: &str, n: i64, out: &mut String) {
+    if digits.is_empty() {
+        out.push('0');
+        return;
+    }
+    if negative {
+        out.push('-');
+    }
+
+    let k = digits.len() as i64;
+    if k <= n && n <= 21 {
+        out.push_str(digits);
+        out.extend(std::iter::repeat_n('0', (n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        out.push_str(&digits[..n as usize]);
+        out.push('.');
+        out.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.extend(std::iter::repeat_n('0', (-n) as usize));
+        out.push_str(digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if k > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        out.push('e');
+        out.push(if n > 0 { '+' } else { '-' });
+        out.push_str(&(n - 1).abs().to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_canonical_object_order_and_whitespace() {
+        let value: Value = serde_json::from_str(r#"{ "b": [1, {"z": null, "a": true}], "a": "x" }"#).unwrap();
+        assert_eq!(to_canonical_string(&value), r#"{"a":"x","b":[1,{"a":true,"z":null}]}"#);
+
+        // Sorted by UTF-16 code units, so the astral character sorts before U+FB33
+        let value = json!({"\u{fb33}": 1, "\u{1f600}": 2, "\r": 3, "1": 4});
+        assert_eq!(to_canonical_string(&value), "{\"\\r\":3,\"1\":4,\"\u{1f600}\":2,\"\u{fb33}\":1}");
+    }
+
+    #[test]
+    fn test_canonicalize_keeps_values() {
+        let value: Value = serde_json::from_str(r#"{"b": {"y": 1.0, "x": 18446744073709551615}, "a": [{"d": 1, "c": 2}]}"#).unwrap();
+        let canonical = canonicalize(&value);
+        assert_eq!(canonical, value);
+        assert_eq!(
+            serde_json::to_string(&canonical).unwrap(),
+            r#"{"a":[{"c":2,"d":1}],"b":{"x":18446744073709551615,"y":1.0}}"#
+        );
+    }
+
+    #[test]
+    fn test_canonical_numbers() {
+        let cases = [
+            ("0", "0"),
+            ("-0.0", "0"),
+            ("1.0", "1"),
+            ("-1", "-1"),
+            ("100", "100"),
+            ("1e21", "1e+21"),
+            ("1e20", "100000000000000000000"),
+            ("0.000001", "0.000001"),
+            ("0.0000001", "1e-7"),
+            ("123.456", "123.456"),
+            ("-1.5e-10", "-1.5e-10"),
+            ("9007199254740993", "9007199254740993"),
+            ("-12.50e1", "-125"),
+            ("0.00012300", "0.000123"),
+            ("1.7976931348623157e308", "1.7976931348623157e+308"),
+            ("5e-324", "5e-324"),
+        ];
+        for (input, expected) in cases {
+            let value: Value = serde_json::from_str(input).unwrap();
+            assert_eq!(to_canonical_string(&value), expected, "canonical form of {}", input);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary-precision")]
+    fn test_canonical_numbers_keep_every_digit() {
+        let cases = [
+            ("123456789012345678901234567890", "1.2345678901234567890123456789e+29"),
+            ("12345678901234567890123", "1.2345678901234567890123e+22"),
+            ("0.1000000000000000055", "0.1000000000000000055"),
+            ("1.50000000000000000001e30", "1.50000000000000000001e+30"),
+        ];
+        for (input, expected) in cases {
+            let value: Value = serde_json::from_str(input).unwrap();
+            assert_eq!(to_canonical_string(&value), expected, "canonical form of {}", input);
+        }
+
+        // Past a double's precision, a changed digit still changes the hash
+        let a: Value = serde_json::from_str("123456789012345678901234567890").unwrap();
+        let b: Value = serde_json::from_str("123456789012345678901234567891").unwrap();
+        assert_ne!(crate::hash::state_hash(&a), crate::hash::state_hash(&b));
+    }
+
+    #[test]
+    fn test_canonical_strings() {
+        let value = json!("quote \" backslash \\ tab \t bell \u{7} euro \u{20ac} slash /");
+        assert_eq!(
+            to_canonical_string(&value),
+            "\"quote \\\" backslash \\\\ tab \\t bell \\u0007 euro \u{20ac} slash /\""
+        );
+    }
+}
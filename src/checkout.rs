@@ -0,0 +1,285 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Writing every version in an archive back out as plain files.
+//!
+//! This is the inverse of `create_archive_from_files`: the archive is
+//! replayed once, observation by observation, and each state is written to
+//! its own file as soon as it is complete. Files archived with
+//! `--keep-format` come back byte for byte; the rest are pretty-printed.
+
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+use crate::archive::write_output_file;
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticLevel};
+use crate::format::FormatProfile;
+use crate::reader::ReadMode;
+use crate::replay::{observation_states, ObservationState};
+
+/// Filename template used when none is given.
+pub const DEFAULT_TEMPLATE: &str = "{index}-{timestamp}-{id}.json";
+
+/// How each observation's file is named, e.g. `{index}-{timestamp}-{id}.json`.
+///
+/// `{index}` is the observation's position in file order, counting the
+/// initial state as 0, `{timestamp}` its timestamp as `2025-01-15T10-05-00Z`
+/// (no colons, so it is a valid filename everywhere), and `{id}` its
+/// observation id. The template must contain `{index}` or `{id}` so that no
+/// two observations get the same file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Index,
+    Timestamp,
+    Id,
+}
+
+impl FilenameTemplate {
+    #[allow(clippy::result_large_err)]
+    pub fn parse(template: &str) -> Result<FilenameTemplate, Diagnostic> {
+        let invalid = |problem: String| {
+            Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::WrongFieldType,
+                format!("I can't use '{}' as a filename template: {}.", template, problem),
+            )
+            .with_advice(format!(
+                "Templates name one file in the output directory, using {{index}}, {{timestamp}} and {{id}}. \
+                 The default is '{}'.",
+                DEFAULT_TEMPLATE
+            ))
+        };
+
+        if template.contains(['/', '\\']) {
+            return Err(invalid("it contains a path separator".to_string()));
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(length) = rest[start..].find('}') else {
+                return Err(invalid("a '{' is never closed".to_string()));
+            };
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            parts.push(match &rest[start + 1..start + length] {
+                "index" => Part::Index,
+                "timestamp" => Part::Timestamp,
+                "id" => Part::Id,
+                other => return Err(invalid(format!("I don't know the placeholder {{{}}}", other))),
+            });
+            rest = &rest[start + length + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        if !parts.iter().any(|part| matches!(part, Part::Index | Part::Id)) {
+            return Err(invalid(
+                "it needs {index} or {id}, or every observation would get the same file".to_string(),
+            ));
+        }
+
+        Ok(FilenameTemplate { parts })
+    }
+
+    /// The filename for the observation at `index`.
+    pub fn render(&self, index: usize, timestamp: DateTime<Utc>, observation_id: &str) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Index => index.to_string(),
+                Part::Timestamp => timestamp.format("%Y-%m-%dT%H-%M-%SZ").to_string(),
+                // Ids come from the archive, so keep them from naming another directory
+                Part::Id => observation_id.replace(['/', '\\'], "_"),
+            })
+            .collect()
+    }
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        FilenameTemplate::parse(DEFAULT_TEMPLATE).expect("the default template is valid")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckoutSummary {
+    /// Files written, one per observation, counting the initial state.
+    pub files: usize,
+    /// Files written byte for byte from a recorded formatting profile.
+    pub original: usize,
+}
+
+/// Write the state after every observation in `archive_path` to its own file
+/// in `dir`, named by `template`. The directory is created if needed, and
+/// files already there with the same names are replaced. The compression
+/// extensions `write_output_file` knows, like `.json.gz`, work in templates too.
+pub fn checkout_all<P: AsRef<Path>, Q: AsRef<Path>>(
+    archive_path: P,
+    dir: Q,
+    template: &FilenameTemplate,
+) -> Result<CheckoutSummary, Vec<Diagnostic>> {
+    let dir = dir.as_ref();
+    let mut states = observation_states(archive_path, ReadMode::AppendSeek)?;
+    let initial_format = states.header().format.clone();
+
+    std::fs::create_dir_all(dir).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't create the directory {}: {}", dir.display(), e),
+        )]
+    })?;
+
+    let mut summary = CheckoutSummary { files: 0, original: 0 };
+    while let Some(observation) = states.next() {
+        // An observation is only yielded once the next one starts, so any
+        // damage in its lines has been reported by now
+        if states.diagnostics().has_fatal() {
+            return Err(states.into_diagnostics().into_diagnostics());
+        }
+
+        let profile = recorded_profile(&observation, initial_format.as_ref());
+        let contents = match &profile {
+            Some(profile) => profile.render(&observation.state),
+            None => FormatProfile { indent: Some("  ".to_string()), newline: true }.render(&observation.state),
+        };
+
+        let path = dir.join(template.render(summary.files, observation.timestamp, &observation.observation_id));
+        write_output_file(&path, contents.as_bytes())?;
+
+        summary.files += 1;
+        if profile.is_some() {
+            summary.original += 1;
+        }
+    }
+
+    if states.diagnostics().has_fatal() {
+        return Err(states.into_diagnostics().into_diagnostics());
+    }
+
+    Ok(summary)
+}
+
+/// The valid formatting profile recorded for `observation`, if there is one.
+fn recorded_profile(observation: &ObservationState, initial: Option<&FormatProfile>) -> Option<FormatProfile> {
+    let recorded = if observation.observation_id == "initial" {
+        initial.cloned().map(Ok)
+    } else {
+        FormatProfile::from_metadata(observation.metadata.as_ref())
+    };
+    recorded
+        .and_then(Result::ok)
+        .filter(|profile| profile.validate().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::{append_to_archive, create_archive_from_files, ArchiveOptions};
+
+    #[test]
+    fn test_filename_template() {
+        let timestamp = "2025-01-15T10:05:00Z".parse().unwrap();
+        assert_eq!(
+            FilenameTemplate::default().render(3, timestamp, "obs-1"),
+            "3-2025-01-15T10-05-00Z-obs-1.json"
+        );
+        assert_eq!(
+            FilenameTemplate::parse("v{index}.json.gz").unwrap().render(0, timestamp, "initial"),
+            "v0.json.gz"
+        );
+        assert_eq!(
+            FilenameTemplate::parse("{id}").unwrap().render(0, timestamp, "a/../b"),
+            "a_.._b"
+        );
+
+        for template in ["{timestamp}.json", "{index", "{name}.json", "out/{index}.json"] {
+            assert!(FilenameTemplate::parse(template).is_err(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn test_checkout_all() -> Result<(), Box<dyn std::error::Error>> {
+        let versions = [
+            "{\n    \"count\": 0\n}\n",
+            "{\"count\":1,\"name\":\"test\"}",
+            "{\n  \"count\": 2,\n  \"name\": \"test\"\n}\n",
+        ];
+        let dir = tempfile::tempdir()?;
+        let inputs = versions
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let path = dir.path().join(format!("{}.json", i));
+                std::fs::write(&path, text)?;
+                Ok(path)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let archive = dir.path().join("test.json.archive");
+
+        create_archive_from_files(
+            &inputs[..2],
+            archive.clone(),
+            &ArchiveOptions {
+                snapshot_interval: Some(1),
+                keep_format: true,
+                ..Default::default()
+            },
+        )
+        .map_err(|d| format!("{:?}", d))?;
+        // Appended without --keep-format, so it comes back pretty-printed
+        let diagnostics = append_to_archive(
+            &archive,
+            &inputs[2..],
+            &archive,
+            &ArchiveOptions::default(),
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let out = dir.path().join("out");
+        let template = FilenameTemplate::parse("{index}.json").map_err(|d| format!("{:?}", d))?;
+        let summary = checkout_all(&archive, &out, &template).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(summary, CheckoutSummary { files: 3, original: 2 });
+
+        // The snapshot after the first observation isn't a version of its own
+        let mut names: Vec<_> = std::fs::read_dir(&out)?
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect::<std::io::Result<_>>()?;
+        names.sort();
+        assert_eq!(names, ["0.json", "1.json", "2.json"]);
+
+        for (i, expected) in versions.iter().enumerate() {
+            assert_eq!(std::fs::read_to_string(out.join(format!("{}.json", i)))?, *expected);
+        }
+
+        Ok(())
+    }
+}
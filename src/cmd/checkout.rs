@@ -0,0 +1,72 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+use crate::flags;
+use json_archive::{checkout_all, Diagnostic, DiagnosticCode, DiagnosticLevel, FilenameTemplate};
+
+pub fn run(flags: &flags::Checkout) -> Vec<Diagnostic> {
+    if !flags.file.exists() {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't find the archive file: {}", flags.file.display()),
+        )
+        .with_advice(
+            "Make sure the file path is correct and the file exists. \
+                 Check for typos in the filename."
+                .to_string(),
+        )];
+    }
+
+    if !flags.all {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::WrongFieldCount,
+            "I can only check out every version at once, so please pass --all.".to_string(),
+        )
+        .with_advice(
+            "To get a single version back as a file, use state with -o:\n\
+             json-archive state --id obs-123 -o data.json file.archive"
+                .to_string(),
+        )];
+    }
+
+    let template = match &flags.template {
+        Some(template) => match FilenameTemplate::parse(template) {
+            Ok(template) => template,
+            Err(diagnostic) => return vec![diagnostic],
+        },
+        None => FilenameTemplate::default(),
+    };
+
+    match checkout_all(&flags.file, &flags.dir, &template) {
+        Ok(summary) => {
+            println!(
+                "Wrote {} versions to {} ({} byte for byte as archived)",
+                summary.files,
+                flags.dir.display(),
+                summary.original
+            );
+            Vec::new()
+        }
+        Err(diagnostics) => diagnostics,
+    }
+}
@@ -0,0 +1,344 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+use crate::flags;
+use json_archive::{
+    create_archive, open_append, parse_observation_metadata, ArchiveBuilder, ArchiveOptions, ArchiveWriter, Diagnostic,
+    DiagnosticCode, DiagnosticLevel, OpenedArchive, SnapshotMode,
+};
+use serde_json::{json, Map, Value};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Diagnostics from runs before the last are passed to `report` as they come,
+/// since `--every` keeps going until it's stopped.
+pub fn run(flags: &flags::Exec, report: &dyn Fn(&[Diagnostic])) -> Vec<Diagnostic> {
+    let Some((program, args)) = flags.command.split_first() else {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::MissingHeaderField,
+            "I need a command to run, but you didn't give me one.".to_string(),
+        )
+        .with_advice(
+            "Put the command after --, for example:\n\
+             json-archive exec --archive video.json.archive -- yt-dlp -j <url>"
+                .to_string(),
+        )];
+    };
+
+    let every = match flags.every.as_deref().map(parse_duration) {
+        None => None,
+        Some(Some(every)) if !every.is_zero() => Some(every),
+        Some(_) => {
+            return vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::InvalidTimestamp,
+                format!("I couldn't understand the duration '{}'.", flags.every.as_deref().unwrap_or_default()),
+            )
+            .with_advice(
+                "Use a whole number followed by a unit: ms, s, m, h, or d. For example: 30s, 5m, 1h."
+                    .to_string(),
+            )];
+        }
+    };
+
+    let observation_metadata = match parse_observation_metadata(&flags.meta) {
+        Ok(metadata) => metadata,
+        Err(diagnostics) => return diagnostics,
+    };
+
+    let command_line = quote_command_line(&flags.command);
+    let mut recorder = Recorder::new(flags, observation_metadata);
+
+    loop {
+        let started = Instant::now();
+
+        match run_command(program, args, &command_line) {
+            Ok(state) => {
+                if let Err(diagnostics) = recorder.record(&command_line, state) {
+                    return recorder.fail(diagnostics);
+                }
+            }
+            // A sampler only loses this one sample; a single run reports it
+            Err(mut diagnostics) if every.is_some() => {
+                for diagnostic in &mut diagnostics {
+                    diagnostic.level = DiagnosticLevel::Warning;
+                }
+                report(&diagnostics);
+            }
+            Err(diagnostics) => return recorder.fail(diagnostics),
+        }
+
+        let Some(every) = every else {
+            return recorder.finish();
+        };
+
+        report(&std::mem::take(&mut recorder.warnings));
+        thread::sleep(every.saturating_sub(started.elapsed()));
+    }
+}
+
+/// Holds the archive open between runs, so `--every` reads it only once.
+struct Recorder<'a> {
+    flags: &'a flags::Exec,
+    options: ArchiveOptions,
+    open: Option<(ArchiveWriter, ArchiveBuilder)>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Recorder<'a> {
+    fn new(flags: &'a flags::Exec, observation_metadata: Map<String, Value>) -> Self {
+        let options = ArchiveOptions {
+            source: flags.source.clone(),
+            snapshot_interval: flags.snapshot_interval,
+            snapshot_mode: if flags.replace_snapshots {
+                SnapshotMode::Replace
+            } else {
+                SnapshotMode::Append
+            },
+            observation_metadata,
+            hash: flags.hash,
+            recover: flags.recover,
+            ..ArchiveOptions::default()
+        };
+        Self {
+            flags,
+            options,
+            open: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Observation metadata recording the command that produced a state.
+    fn provenance(&self) -> Map<String, Value> {
+        Map::from_iter([("command".to_string(), json!(self.flags.command))])
+    }
+
+    /// Append `state` as the next observation, creating the archive with it
+    /// as the initial state if there isn't one yet.
+    fn record(&mut self, command_line: &str, state: Value) -> Result<(), Vec<Diagnostic>> {
+        let provenance = self.provenance();
+        let Some((writer, builder)) = self.open.as_mut() else {
+            return self.open_or_create(command_line, state);
+        };
+
+        writer.write_comment(&format!("Command: {}", command_line))?;
+        if let Some(observation) = builder.add_state_with_metadata(state.clone(), provenance) {
+            writer.write_observation_with_state(observation, &state)?;
+        }
+        writer.flush()
+    }
+
+    fn open_or_create(&mut self, command_line: &str, state: Value) -> Result<(), Vec<Diagnostic>> {
+        let path = &self.flags.archive;
+
+        if path.exists() {
+            self.open_archive(path)?;
+            return self.record(command_line, state);
+        }
+
+        create_archive(state, path, &self.options)?;
+        println!("Created archive: {}", path.display());
+        let (writer, _) = self.open_archive(path)?;
+        writer.write_comment(&format!("Command: {}", command_line))?;
+        writer.flush()
+    }
+
+    fn open_archive(&mut self, path: &Path) -> Result<&mut (ArchiveWriter, ArchiveBuilder), Vec<Diagnostic>> {
+        let OpenedArchive {
+            writer,
+            builder,
+            diagnostics,
+            ..
+        } = open_append(path, path, &self.options, false)?;
+        self.warnings.extend(diagnostics);
+        Ok(self.open.insert((writer, builder)))
+    }
+
+    fn fail(mut self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        self.warnings.extend(diagnostics);
+        self.finish()
+    }
+
+    fn finish(self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.warnings;
+        if let Some((writer, _)) = self.open {
+            if let Err(errors) = writer.finish() {
+                diagnostics.extend(errors);
+            }
+        }
+        diagnostics
+    }
+}
+
+/// Run the command and parse its stdout as the next state. Its stderr goes
+/// straight to ours, so progress output and errors stay visible.
+fn run_command(program: &str, args: &[String], command_line: &str) -> Result<Value, Vec<Diagnostic>> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| {
+            vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                format!("I couldn't run `{}`: {}", command_line, e),
+            )
+            .with_advice("Make sure the program is installed and on your PATH.".to_string())]
+        })?;
+
+    if !output.status.success() {
+        return Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!(
+                "I ran `{}`, but it failed ({}), so I didn't record its output.",
+                command_line, output.status
+            ),
+        )]);
+    }
+
+    serde_json::from_slice(&output.stdout).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::InvalidEventJson,
+            format!("I ran `{}`, but its output isn't JSON: {}", command_line, e),
+        )
+        .with_advice(
+            "The command must print exactly one JSON value on stdout. Check for a flag that \
+             selects JSON output, like `-j` or `-o json`, and that nothing else is printed."
+                .to_string(),
+        )]
+    })
+}
+
+/// Parse a duration like `500ms`, `30s`, `5m`, `1h`, or `1d`.
+fn parse_duration(text: &str) -> Option<Duration> {
+    let split = text.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let seconds = match unit {
+        "ms" => return Some(Duration::from_millis(number)),
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    number.checked_mul(seconds).map(Duration::from_secs)
+}
+
+/// The command as someone would type it into a shell, for the archive's
+/// comment and for messages.
+fn quote_command_line(command: &[String]) -> String {
+    command
+        .iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c));
+            if plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json_archive::{ArchiveReader, ReadMode};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("2d"), Some(Duration::from_secs(2 * 86400)));
+        assert_eq!(parse_duration("0s"), Some(Duration::ZERO));
+
+        for text in ["", "30", "s", "1.5s", "-1s", "5 m", "5M", "1w", &format!("{}d", u64::MAX)] {
+            assert_eq!(parse_duration(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_quote_command_line() {
+        let command = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            quote_command_line(&command(&["yt-dlp", "-j", "https://example.com/v?id=1"])),
+            "yt-dlp -j 'https://example.com/v?id=1'"
+        );
+        assert_eq!(quote_command_line(&command(&["curl", "-s", "a.b/c:8080"])), "curl -s a.b/c:8080");
+        assert_eq!(quote_command_line(&command(&["echo", ""])), "echo ''");
+        assert_eq!(quote_command_line(&command(&["echo", "it's here"])), r"echo 'it'\''s here'");
+    }
+
+    #[test]
+    fn test_recorder_creates_then_appends() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new()?;
+        let flags = flags::Exec {
+            command: vec!["echo".to_string(), r#"{"a":1}"#.to_string()],
+            archive: dir.path().join("out.json.archive"),
+            every: None,
+            snapshot_interval: None,
+            replace_snapshots: false,
+            source: None,
+            meta: Vec::new(),
+            recover: false,
+            hash: true,
+        };
+        let command_line = quote_command_line(&flags.command);
+
+        // The first run creates the archive, the second appends to it
+        let mut recorder = Recorder::new(&flags, Map::new());
+        let state = run_command("echo", &flags.command[1..], &command_line).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(state, json!({"a": 1}));
+        recorder.record(&command_line, state).map_err(|d| format!("{:?}", d))?;
+        recorder.record(&command_line, json!({"a": 2})).map_err(|d| format!("{:?}", d))?;
+        assert!(recorder.finish().is_empty());
+
+        // A later invocation opens the archive it left behind
+        let mut recorder = Recorder::new(&flags, Map::new());
+        recorder.record(&command_line, json!({"a": 3})).map_err(|d| format!("{:?}", d))?;
+        assert!(recorder.finish().is_empty());
+
+        let reader = ArchiveReader::new(&flags.archive, ReadMode::FullValidation)?;
+        let result = reader.read(&flags.archive)?;
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+        assert_eq!(result.header.initial, json!({"a": 1}));
+        assert!(result.header.hash.is_some());
+        assert_eq!(result.observation_count, 2);
+        assert_eq!(result.final_state, json!({"a": 3}));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+use crate::flags;
+use crate::usage_error;
+use json_archive::{explain, Diagnostic, DiagnosticCode};
+
+pub fn run(flags: &flags::Explain) -> Vec<Diagnostic> {
+    let code = match DiagnosticCode::from_code(&flags.code) {
+        Some(code) => code,
+        None => {
+            let known: Vec<&str> = DiagnosticCode::ALL.iter().map(|c| c.as_str()).collect();
+            usage_error(&format!(
+                "unknown diagnostic code: `{}`, expected one of {}",
+                flags.code,
+                known.join(", ")
+            ));
+        }
+    };
+
+    let explanation = explain(code.clone());
+
+    println!("{}: {}", code.as_str(), code.title());
+    println!();
+    println!("{}", explanation.meaning);
+    println!();
+    println!("Example:");
+    println!();
+    if explanation.example.is_empty() {
+        println!("    (an empty file)");
+    } else {
+        for line in String::from_utf8_lossy(explanation.example).lines() {
+            println!("    {}", line);
+        }
+    }
+    println!();
+    println!("How to fix:");
+    println!();
+    println!("{}", explanation.fix);
+
+    Vec::new()
+}
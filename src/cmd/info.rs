@@ -21,8 +21,10 @@
 
 use crate::flags;
 use chrono::{DateTime, Utc};
-use json_archive::{Diagnostic, DiagnosticCode, DiagnosticLevel, Event};
+use json_archive::{is_observation_snapshot, Diagnostic, DiagnosticCode, DiagnosticLevel, Event, HeaderSummary};
 use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
 use std::path::Path;
 
 #[derive(Debug)]
@@ -31,7 +33,9 @@ struct ObservationInfo {
     timestamp: DateTime<Utc>,
     created: DateTime<Utc>, // For initial state, this is the archive creation time
     change_count: usize,
-    json_size: usize,
+    /// `None` with `--fast`, which doesn't replay states
+    json_size: Option<usize>,
+    metadata: Option<Map<String, Value>>,
 }
 
 #[derive(Serialize)]
@@ -40,18 +44,30 @@ struct JsonObservation {
     id: String,
     timestamp: String,
     changes: usize,
-    json_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Map<String, Value>>,
 }
 
+/// The observations, the number of snapshot lines, and the header.
+type Collected = (Vec<ObservationInfo>, usize, HeaderSummary);
+
 #[derive(Serialize)]
 struct JsonInfoOutput {
     archive: String,
     created: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Value>,
     file_size: u64,
     snapshot_count: usize,
     observations: Vec<JsonObservation>,
-    total_json_size: u64,
-    efficiency_percent: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_json_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    efficiency_percent: Option<f64>,
 }
 
 pub fn run(flags: &flags::Info) -> Vec<Diagnostic> {
@@ -68,8 +84,13 @@ pub fn run(flags: &flags::Info) -> Vec<Diagnostic> {
         )];
     }
 
-    let (observations, snapshot_count) = match collect_observations(&flags.file) {
-        Ok((obs, count)) => (obs, count),
+    let collected = if flags.fast {
+        collect_observation_heads(&flags.file)
+    } else {
+        collect_observations(&flags.file)
+    };
+    let (observations, snapshot_count, header) = match collected {
+        Ok(collected) => collected,
         Err(diagnostics) => return diagnostics,
     };
 
@@ -78,15 +99,21 @@ pub fn run(flags: &flags::Info) -> Vec<Diagnostic> {
         Err(_) => 0,
     };
 
-    // Calculate total JSON size (sum of all observations + newline separators)
-    let total_json_size: u64 = observations.iter().map(|obs| obs.json_size as u64).sum::<u64>()
-        + (observations.len() as u64).saturating_sub(1); // Add newlines between observations
-
-    let efficiency_percent = if total_json_size > 0 {
-        (file_size as f64 / total_json_size as f64) * 100.0
-    } else {
-        0.0
-    };
+    // Calculate total JSON size (sum of all observations + newline separators),
+    // unless --fast left the sizes out
+    let total_json_size: Option<u64> = observations
+        .iter()
+        .map(|obs| obs.json_size.map(|size| size as u64))
+        .sum::<Option<u64>>()
+        .map(|sum| sum + (observations.len() as u64).saturating_sub(1)); // Add newlines between observations
+
+    let efficiency_percent = total_json_size.map(|total_json_size| {
+        if total_json_size > 0 {
+            (file_size as f64 / total_json_size as f64) * 100.0
+        } else {
+            0.0
+        }
+    });
 
     // Check output format
     let is_json_output = flags.output.as_ref().map(|s| s == "json").unwrap_or(false);
@@ -97,11 +124,13 @@ pub fn run(flags: &flags::Info) -> Vec<Diagnostic> {
             let empty_output = JsonInfoOutput {
                 archive: flags.file.display().to_string(),
                 created: "".to_string(),
+                source: header.source,
+                metadata: header.metadata,
                 file_size,
                 snapshot_count,
                 observations: Vec::new(),
-                total_json_size: 0,
-                efficiency_percent: 0.0,
+                total_json_size: Some(0),
+                efficiency_percent: Some(0.0),
             };
             println!(
                 "{}",
@@ -123,12 +152,15 @@ pub fn run(flags: &flags::Info) -> Vec<Diagnostic> {
                 timestamp: obs.timestamp.to_rfc3339(),
                 changes: obs.change_count,
                 json_size: obs.json_size,
+                metadata: obs.metadata.clone(),
             })
             .collect();
 
         let json_output = JsonInfoOutput {
             archive: flags.file.display().to_string(),
             created: observations[0].created.to_rfc3339(),
+            source: header.source,
+            metadata: header.metadata,
             file_size,
             snapshot_count,
             observations: json_observations,
@@ -157,6 +189,12 @@ pub fn run(flags: &flags::Info) -> Vec<Diagnostic> {
         };
 
         println!("Created: {}", format_timestamp(first_timestamp));
+        if let Some(source) = &header.source {
+            println!("Source: {}", source);
+        }
+        if let Some(metadata) = &header.metadata {
+            println!("Metadata: {}", metadata);
+        }
         println!();
 
         if observations.len() == 1 {
@@ -188,13 +226,18 @@ pub fn run(flags: &flags::Info) -> Vec<Diagnostic> {
                 obs.change_count.to_string()
             };
 
+            let size_display = match obs.json_size {
+                Some(size) => format_size(size as u64),
+                None => "-".to_string(),
+            };
+
             println!(
                 "  {:2}  {:32}  {:25}  {:7}  {:9}",
                 index,
                 id_display,
                 format_timestamp(&obs.timestamp),
                 changes_display,
-                format_size(obs.json_size as u64)
+                size_display
             );
         }
 
@@ -205,22 +248,33 @@ pub fn run(flags: &flags::Info) -> Vec<Diagnostic> {
             format!("{} snapshots", snapshot_count)
         };
 
-        let comparison = if efficiency_percent < 100.0 {
-            format!("{:.1}% smaller", 100.0 - efficiency_percent)
-        } else {
-            format!("{:.1}% larger", efficiency_percent - 100.0)
-        };
-
-        println!(
-            "Archive size: {} ({}, {} than JSON Lines)",
-            format_size(file_size),
-            snapshot_text,
-            comparison
-        );
-        println!(
-            "Data size: {}",
-            format_size(total_json_size)
-        );
+        match (total_json_size, efficiency_percent) {
+            (Some(total_json_size), Some(efficiency_percent)) => {
+                let comparison = if efficiency_percent < 100.0 {
+                    format!("{:.1}% smaller", 100.0 - efficiency_percent)
+                } else {
+                    format!("{:.1}% larger", efficiency_percent - 100.0)
+                };
+
+                println!(
+                    "Archive size: {} ({}, {} than JSON Lines)",
+                    format_size(file_size),
+                    snapshot_text,
+                    comparison
+                );
+                println!(
+                    "Data size: {}",
+                    format_size(total_json_size)
+                );
+            }
+            _ => {
+                println!(
+                    "Archive size: {} ({})",
+                    format_size(file_size),
+                    snapshot_text
+                );
+            }
+        }
 
         // Add usage instructions
         println!();
@@ -245,7 +299,7 @@ pub fn run(flags: &flags::Info) -> Vec<Diagnostic> {
     Vec::new()
 }
 
-fn collect_observations(file_path: &Path) -> Result<(Vec<ObservationInfo>, usize), Vec<Diagnostic>> {
+fn collect_observations(file_path: &Path) -> Result<Collected, Vec<Diagnostic>> {
     let reader = match json_archive::ArchiveReader::new(file_path, json_archive::ReadMode::AppendSeek) {
         Ok(r) => r,
         Err(e) => {
@@ -257,7 +311,7 @@ fn collect_observations(file_path: &Path) -> Result<(Vec<ObservationInfo>, usize
         }
     };
 
-    let (initial_state, mut event_iter) = match reader.events(file_path) {
+    let (initial_state, event_iter) = match reader.events(file_path) {
         Ok(r) => r,
         Err(e) => {
             return Err(vec![Diagnostic::new(
@@ -282,6 +336,7 @@ fn collect_observations(file_path: &Path) -> Result<(Vec<ObservationInfo>, usize
         .len();
 
     let created = event_iter.header.created;
+    let header = HeaderSummary::from(&event_iter.header);
 
     // Add initial state as observation 0
     observations.push(ObservationInfo {
@@ -289,19 +344,23 @@ fn collect_observations(file_path: &Path) -> Result<(Vec<ObservationInfo>, usize
         timestamp: created,
         created,
         change_count: 0,
-        json_size: initial_size,
+        json_size: Some(initial_size),
+        metadata: None,
     });
 
     // Iterate through events
-    while let Some(event) = event_iter.next() {
+    let mut seen: HashSet<String> = HashSet::new();
+    for event in event_iter {
         match event {
-            Event::Observe { observation_id, timestamp, change_count } => {
+            Event::Observe { observation_id, timestamp, change_count, metadata, .. } => {
+                seen.insert(observation_id.clone());
                 observations.push(ObservationInfo {
                     id: observation_id,
                     timestamp,
                     created,
                     change_count,
-                    json_size: 0, // Will be calculated after applying events
+                    json_size: Some(0), // Will be calculated after applying events
+                    metadata,
                 });
             }
             Event::Add { path, value, .. } => {
@@ -310,9 +369,11 @@ fn collect_observations(file_path: &Path) -> Result<(Vec<ObservationInfo>, usize
                 // Update the JSON size of the last observation
                 if let Some(last_obs) = observations.last_mut() {
                     if last_obs.id != "initial" {
-                        last_obs.json_size = serde_json::to_string(&current_state)
-                            .unwrap_or_default()
-                            .len();
+                        last_obs.json_size = Some(
+                            serde_json::to_string(&current_state)
+                                .unwrap_or_default()
+                                .len(),
+                        );
                     }
                 }
             }
@@ -322,9 +383,11 @@ fn collect_observations(file_path: &Path) -> Result<(Vec<ObservationInfo>, usize
                 // Update the JSON size of the last observation
                 if let Some(last_obs) = observations.last_mut() {
                     if last_obs.id != "initial" {
-                        last_obs.json_size = serde_json::to_string(&current_state)
-                            .unwrap_or_default()
-                            .len();
+                        last_obs.json_size = Some(
+                            serde_json::to_string(&current_state)
+                                .unwrap_or_default()
+                                .len(),
+                        );
                     }
                 }
             }
@@ -334,9 +397,11 @@ fn collect_observations(file_path: &Path) -> Result<(Vec<ObservationInfo>, usize
                 // Update the JSON size of the last observation
                 if let Some(last_obs) = observations.last_mut() {
                     if last_obs.id != "initial" {
-                        last_obs.json_size = serde_json::to_string(&current_state)
-                            .unwrap_or_default()
-                            .len();
+                        last_obs.json_size = Some(
+                            serde_json::to_string(&current_state)
+                                .unwrap_or_default()
+                                .len(),
+                        );
                     }
                 }
             }
@@ -346,31 +411,110 @@ fn collect_observations(file_path: &Path) -> Result<(Vec<ObservationInfo>, usize
                 // Update the JSON size of the last observation
                 if let Some(last_obs) = observations.last_mut() {
                     if last_obs.id != "initial" {
-                        last_obs.json_size = serde_json::to_string(&current_state)
-                            .unwrap_or_default()
-                            .len();
+                        last_obs.json_size = Some(
+                            serde_json::to_string(&current_state)
+                                .unwrap_or_default()
+                                .len(),
+                        );
                     }
                 }
             }
-            Event::Snapshot { object, .. } => {
+            Event::Snapshot { observation_id, timestamp, object, metadata, .. } => {
                 current_state = object;
                 snapshot_count += 1;
 
+                // A snapshot that starts an observation gets its own row
+                let current = observations.last().map(|obs| obs.id.as_str());
+                if is_observation_snapshot(&observation_id, current, &seen) {
+                    seen.insert(observation_id.clone());
+                    observations.push(ObservationInfo {
+                        id: observation_id,
+                        timestamp,
+                        created,
+                        change_count: 0,
+                        json_size: Some(
+                            serde_json::to_string(&current_state)
+                                .unwrap_or_default()
+                                .len(),
+                        ),
+                        metadata,
+                    });
+                    continue;
+                }
+
                 // Update the JSON size of the last observation
                 if let Some(last_obs) = observations.last_mut() {
                     if last_obs.id != "initial" {
-                        last_obs.json_size = serde_json::to_string(&current_state)
-                            .unwrap_or_default()
-                            .len();
+                        last_obs.json_size = Some(
+                            serde_json::to_string(&current_state)
+                                .unwrap_or_default()
+                                .len(),
+                        );
                     }
                 }
             }
         }
     }
 
-    Ok((observations, snapshot_count))
+    Ok((observations, snapshot_count, header))
 }
 
+/// The `--fast` version of `collect_observations`: the header is read without
+/// its initial state and observations come from their observe and snapshot
+/// lines alone, so nothing is replayed and JSON sizes are unknown.
+fn collect_observation_heads(file_path: &Path) -> Result<Collected, Vec<Diagnostic>> {
+    let reader = match json_archive::ArchiveReader::new(file_path, json_archive::ReadMode::AppendSeek) {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                format!("I couldn't open the archive file: {}", e),
+            )]);
+        }
+    };
+
+    let mut heads = match reader.observation_heads(file_path) {
+        Ok(heads) => heads,
+        Err(e) => {
+            return Err(vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                format!("I couldn't read the archive file: {}", e),
+            )]);
+        }
+    };
+
+    if heads.diagnostics.has_fatal() {
+        return Err(heads.diagnostics.diagnostics().to_vec());
+    }
+
+    let created = heads.header.created;
+    let mut observations = vec![ObservationInfo {
+        id: "initial".to_string(),
+        timestamp: created,
+        created,
+        change_count: 0,
+        json_size: None,
+        metadata: None,
+    }];
+
+    for head in heads.by_ref() {
+        observations.push(ObservationInfo {
+            id: head.observation_id,
+            timestamp: head.timestamp,
+            created,
+            change_count: head.change_count,
+            json_size: None,
+            metadata: head.metadata,
+        });
+    }
+    if heads.diagnostics.has_fatal() {
+        return Err(heads.diagnostics.diagnostics().to_vec());
+    }
+
+    Ok((observations, heads.snapshot_count, heads.header))
+}
 
 fn format_timestamp(dt: &DateTime<Utc>) -> String {
     dt.format("%a %H:%M:%S %d-%b-%Y").to_string()
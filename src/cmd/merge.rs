@@ -0,0 +1,86 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+use crate::flags;
+use json_archive::{merge_archives, Diagnostic, DiagnosticCode, DiagnosticLevel};
+
+pub fn run(flags: &flags::Merge) -> Vec<Diagnostic> {
+    if flags.archives.len() < 2 {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::MissingHeaderField,
+            format!("I need at least two archives to merge, but you gave me {}.", flags.archives.len()),
+        )
+        .with_advice("Usage: json-archive merge a.json.archive b.json.archive -o merged.json.archive".to_string())];
+    }
+
+    let mut diagnostics = Vec::new();
+    for archive in &flags.archives {
+        if !archive.exists() {
+            diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::PathNotFound,
+                    format!("I couldn't find the archive file: {}", archive.display()),
+                )
+                .with_advice(
+                    "Make sure the file path is correct and the file exists. \
+                     Check for typos in the filename."
+                        .to_string(),
+                ),
+            );
+        }
+    }
+    if flags.archives.iter().any(|archive| same_file(archive, &flags.output)) {
+        diagnostics.push(
+            Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                format!("The output {} is also one of the archives to merge.", flags.output.display()),
+            )
+            .with_advice("Write the merged archive to a new file with -o.".to_string()),
+        );
+    }
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+
+    match merge_archives(&flags.archives, &flags.output, flags.snapshot_interval, flags.drop_hashes) {
+        Ok(summary) => {
+            println!(
+                "Merged {} archives into {}: {} observations, {} duplicates dropped",
+                flags.archives.len(),
+                flags.output.display(),
+                summary.observations,
+                summary.duplicates
+            );
+            Vec::new()
+        }
+        Err(diagnostics) => diagnostics,
+    }
+}
+
+fn same_file(a: &std::path::Path, b: &std::path::Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
@@ -0,0 +1,148 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+use crate::flags;
+use json_archive::{update_header, ArchiveReader, Diagnostic, DiagnosticCode, DiagnosticLevel, ReadMode};
+use serde_json::Value;
+use std::path::Path;
+
+pub fn run(flags: &flags::Meta) -> Vec<Diagnostic> {
+    match &flags.subcommand {
+        flags::MetaCmd::Get(get) => get_metadata(&get.file, get.key.as_deref()),
+        flags::MetaCmd::Set(set) => match parse_metadata(&set.metadata, "metadata", true) {
+            Ok(metadata) => change_metadata(&set.file, |_| metadata),
+            Err(diagnostics) => diagnostics,
+        },
+        flags::MetaCmd::MergePatch(merge) => match parse_metadata(&merge.patch, "patch", false) {
+            Ok(patch) => change_metadata(&merge.file, |metadata| {
+                let mut merged = metadata.unwrap_or(Value::Null);
+                merge_patch(&mut merged, patch);
+                merged
+            }),
+            Err(diagnostics) => diagnostics,
+        },
+    }
+}
+
+/// Parse header metadata given on the command line. It must be a JSON
+/// object, so that later merges have keys to work with; `null` is also
+/// accepted where it means "no metadata".
+pub fn parse_metadata(text: &str, what: &str, allow_null: bool) -> Result<Value, Vec<Diagnostic>> {
+    let metadata: Value = serde_json::from_str(text).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::InvalidEventJson,
+            format!("I couldn't parse the {} as JSON: {}", what, e),
+        )
+        .with_advice(
+            "Quote the whole object for your shell, for example:\n\
+             '{\"project\": \"demo\", \"owner\": \"ops\"}'"
+                .to_string(),
+        )]
+    })?;
+
+    match metadata {
+        Value::Object(_) => Ok(metadata),
+        Value::Null if allow_null => Ok(metadata),
+        other => Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::WrongFieldType,
+            format!("I expected the {} to be a JSON object, but got {}.", what, other),
+        )
+        .with_advice("Header metadata is an object, for example: '{\"project\": \"demo\"}'".to_string())]),
+    }
+}
+
+fn get_metadata(file: &Path, key: Option<&str>) -> Vec<Diagnostic> {
+    // The header alone is enough; the events aren't read
+    let heads = match ArchiveReader::new(file, ReadMode::AppendSeek).and_then(|reader| reader.observation_heads(file)) {
+        Ok(heads) => heads,
+        Err(e) => {
+            return vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                format!("I couldn't read the archive file: {}", e),
+            )];
+        }
+    };
+    if heads.diagnostics.has_fatal() {
+        return heads.diagnostics.diagnostics().to_vec();
+    }
+
+    let metadata = heads.header.metadata.unwrap_or(Value::Null);
+    let value = match key {
+        None => &metadata,
+        Some(key) => match metadata.get(key) {
+            Some(value) => value,
+            None => {
+                return vec![Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::PathNotFound,
+                    format!("I couldn't find the key '{}' in the header metadata.", key),
+                )
+                .with_advice(format!(
+                    "Run `json-archive meta get {}` to see all of it.",
+                    file.display()
+                ))];
+            }
+        },
+    };
+
+    println!("{}", serde_json::to_string_pretty(value).unwrap_or_default());
+    Vec::new()
+}
+
+fn change_metadata(file: &Path, change: impl FnOnce(Option<Value>) -> Value) -> Vec<Diagnostic> {
+    let result = update_header(file, |header| {
+        header.metadata = match change(header.metadata.take()) {
+            Value::Null => None,
+            Value::Object(object) if object.is_empty() => None,
+            metadata => Some(metadata),
+        };
+    });
+
+    match result {
+        Ok(()) => Vec::new(),
+        Err(diagnostics) => diagnostics,
+    }
+}
+
+/// JSON Merge Patch (RFC 7396): objects merge key by key, `null` removes a
+/// key, and anything else replaces what was there.
+fn merge_patch(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(&key);
+            } else {
+                merge_patch(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
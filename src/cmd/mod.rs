@@ -19,5 +19,16 @@
 // marxism@peoplesgrocers.com
 //
 
+pub mod checkout;
+pub mod exec;
+pub mod explain;
 pub mod info;
+pub mod merge;
+pub mod meta;
+pub mod normalize;
+pub mod redact;
+pub mod slice;
 pub mod state;
+pub mod validate;
+pub mod verify_roundtrip;
+pub mod watch;
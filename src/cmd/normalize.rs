@@ -0,0 +1,63 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+
+use crate::flags;
+use json_archive::{normalize_archive, Diagnostic, DiagnosticCode, DiagnosticLevel};
+
+pub fn run(flags: &flags::Normalize) -> Vec<Diagnostic> {
+    if !flags.file.exists() {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't find the archive file: {}", flags.file.display()),
+        )
+        .with_advice(
+            "Make sure the file path is correct and the file exists. \
+                 Check for typos in the filename."
+                .to_string(),
+        )];
+    }
+
+    // -o naming the archive itself is the same as leaving it out
+    let output = flags.output.as_deref().filter(
+        |output| !matches!((flags.file.canonicalize(), output.canonicalize()), (Ok(a), Ok(b)) if a == b),
+    );
+
+    match normalize_archive(&flags.file, output, flags.snapshot_interval, flags.drop_hashes) {
+        Ok(summary) => {
+            let destination = output.unwrap_or(&flags.file).display();
+            if summary.moved == 0 {
+                println!(
+                    "{} observations were already in chronological order; wrote {}",
+                    summary.observations, destination
+                );
+            } else {
+                println!(
+                    "Sorted {} observations into chronological order ({} moved); wrote {}",
+                    summary.observations, summary.moved, destination
+                );
+            }
+            Vec::new()
+        }
+        Err(diagnostics) => diagnostics,
+    }
+}
@@ -0,0 +1,116 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+use crate::flags;
+use chrono::{DateTime, Utc};
+use json_archive::{slice_archive, Diagnostic, DiagnosticCode, DiagnosticLevel, TimeRange};
+
+pub fn run(flags: &flags::Slice) -> Vec<Diagnostic> {
+    if !flags.file.exists() {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't find the archive file: {}", flags.file.display()),
+        )
+        .with_advice(
+            "Make sure the file path is correct and the file exists. \
+                 Check for typos in the filename."
+                .to_string(),
+        )];
+    }
+
+    let range = match parse_range(flags) {
+        Ok(range) => range,
+        Err(diagnostic) => return vec![diagnostic],
+    };
+
+    if matches!((flags.file.canonicalize(), flags.output.canonicalize()), (Ok(a), Ok(b)) if a == b) {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            "I can't write the slice over the archive it is cut from.".to_string(),
+        )
+        .with_advice("Write it to a new file with -o, and replace the original afterwards if you want to.".to_string())];
+    }
+
+    match slice_archive(&flags.file, &flags.output, &range, flags.drop_hashes) {
+        Ok(summary) => {
+            println!(
+                "Wrote {} observations to {} ({} outside the range left out)",
+                summary.observations,
+                flags.output.display(),
+                summary.dropped
+            );
+            Vec::new()
+        }
+        Err(diagnostics) => diagnostics,
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_range(flags: &flags::Slice) -> Result<TimeRange, Diagnostic> {
+    if flags.until.is_some() && flags.before.is_some() {
+        return Err(Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::WrongFieldCount,
+            "Please specify only one end for the range: --until or --before".to_string(),
+        )
+        .with_advice(
+            "--until keeps observations at the timestamp, --before leaves them out.".to_string(),
+        ));
+    }
+
+    let range = TimeRange {
+        since: parse_timestamp(flags.since.as_deref())?,
+        until: parse_timestamp(flags.until.as_deref())?,
+        before: parse_timestamp(flags.before.as_deref())?,
+    };
+
+    if range == TimeRange::default() {
+        return Err(Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::WrongFieldCount,
+            "I need a time range to slice, but you didn't give me one.".to_string(),
+        )
+        .with_advice(
+            "Examples:\n\
+             json-archive slice --since 2025-01-01T00:00:00Z -o recent.json.archive file.archive\n\
+             json-archive slice --before 2025-01-01T00:00:00Z -o 2024.json.archive file.archive"
+                .to_string(),
+        ));
+    }
+
+    Ok(range)
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_timestamp(text: Option<&str>) -> Result<Option<DateTime<Utc>>, Diagnostic> {
+    text.map(|text| {
+        text.parse::<DateTime<Utc>>().map_err(|_| {
+            Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::InvalidTimestamp,
+                format!("I couldn't parse the timestamp '{}'. Please use ISO-8601 format like '2025-01-15T10:05:00Z'", text)
+            )
+        })
+    })
+    .transpose()
+}
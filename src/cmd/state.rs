@@ -20,22 +20,19 @@
 //
 
 use crate::flags;
+use crate::usage_error;
 use chrono::{DateTime, Utc};
-use json_archive::{apply_add, apply_change, apply_move, apply_remove, ArchiveReader, Diagnostic, DiagnosticCode, DiagnosticLevel, Event, ReadMode};
-use serde_json::Value;
-use std::path::Path;
-
-#[derive(Debug)]
-enum AccessMethod {
-    Id(String),
-    Index(usize),
-    AsOf(DateTime<Utc>),
-    RightBefore(DateTime<Utc>),
-    After(DateTime<Utc>),
-    Latest,
-}
+use json_archive::canonical::canonicalize;
+use json_archive::{
+    resolve_state, write_output_file, Diagnostic, DiagnosticCode, DiagnosticLevel, FormatProfile, HeaderSummary,
+    JsonPointer, ReadMode, StateSelector,
+};
+use serde_json::{Map, Value};
 
+#[allow(clippy::result_large_err)]
 pub fn run(flags: &flags::State) -> Vec<Diagnostic> {
+    check_output_flags(flags);
+
     if !flags.file.exists() {
         return vec![Diagnostic::new(
             DiagnosticLevel::Fatal,
@@ -55,41 +52,235 @@ pub fn run(flags: &flags::State) -> Vec<Diagnostic> {
         Err(diagnostic) => return vec![diagnostic],
     };
 
-    // Find and replay to the target observation
-    let target_state = match find_and_replay_to_target(&flags.file, &access_method) {
-        Ok(state) => state,
+    let read_mode = if flags.recover {
+        ReadMode::Recover
+    } else {
+        ReadMode::AppendSeek
+    };
+
+    // Replay up to the target observation only
+    let resolved = match resolve_state(&flags.file, &access_method, read_mode) {
+        Ok(resolved) => resolved,
         Err(diagnostics) => return diagnostics,
     };
 
-    // Output the JSON state
-    match serde_json::to_string_pretty(&target_state) {
-        Ok(json) => println!("{}", json),
-        Err(e) => {
-            return vec![Diagnostic::new(
-                DiagnosticLevel::Fatal,
-                DiagnosticCode::InvalidEventJson,
-                format!("I couldn't serialize the state to JSON: {}", e),
-            )];
+    // Damaged lines are only reported when recovering, where they are warnings
+    let mut diagnostics = if read_mode == ReadMode::Recover {
+        resolved.diagnostics.into_diagnostics()
+    } else {
+        Vec::new()
+    };
+
+    let output = if flags.original {
+        original_profile(&resolved.header, &resolved.observation_id, resolved.metadata.as_ref())
+            .map(|profile| profile.render(&resolved.state))
+    } else {
+        let target_state = match &flags.doc {
+            Some(name) => select_document(flags, &resolved.header, &resolved.observation_id, resolved.state, name),
+            None => Ok(resolved.state),
+        };
+        target_state
+            .and_then(|state| select_pointer(flags, &resolved.observation_id, state))
+            .map(|state| render(flags, &state))
+    };
+
+    let output = match output {
+        Ok(output) => output,
+        Err(diagnostic) => {
+            diagnostics.push(diagnostic);
+            return diagnostics;
+        }
+    };
+
+    match &flags.output {
+        Some(path) => {
+            if let Err(errors) = write_output_file(path, output.as_bytes()) {
+                diagnostics.extend(errors);
+            }
         }
+        None => print!("{}", output),
+    }
+
+    diagnostics
+}
+
+/// Formatting flags that can't be used together.
+fn check_output_flags(flags: &flags::State) {
+    if flags.compact && flags.indent.is_some() {
+        usage_error("`--compact` can't be combined with `--indent`, compact output has no indentation");
+    }
+
+    let reformatting = [
+        ("--compact", flags.compact),
+        ("--indent", flags.indent.is_some()),
+        ("--sort-keys", flags.sort_keys),
+        ("--pointer", flags.pointer.is_some()),
+    ];
+    if let Some((flag, _)) = reformatting.iter().find(|(_, used)| flags.original && *used) {
+        usage_error(&format!(
+            "`--original` can't be combined with `{}`, it prints the file exactly as it was",
+            flag
+        ));
+    }
+    if flags.original && flags.doc.is_some() {
+        usage_error("`--original` can't be combined with `--doc`, collection archives don't record formatting");
+    }
+}
+
+/// Narrow the state down to the value at `--pointer`, if one was given.
+#[allow(clippy::result_large_err)]
+fn select_pointer(flags: &flags::State, observation_id: &str, state: Value) -> Result<Value, Diagnostic> {
+    let Some(path) = &flags.pointer else {
+        return Ok(state);
+    };
+    let pointer = JsonPointer::new(path)?;
+    pointer.get(&state).cloned().map_err(|diagnostic| {
+        Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!(
+                "{} while following {} in the state at observation '{}'.",
+                diagnostic.description, pointer, observation_id
+            ),
+        )
+        .with_advice(
+            "Pointers look like /users/0/name: keys and array indices separated by '/'. \
+             Print the whole state to see what's there."
+                .to_string(),
+        )
+    })
+}
+
+/// Render the state with the formatting flags: pretty-printed with two
+/// spaces unless told otherwise, always ending in a newline.
+fn render(flags: &flags::State, state: &Value) -> String {
+    let profile = FormatProfile {
+        indent: if flags.compact {
+            None
+        } else {
+            Some(" ".repeat(flags.indent.unwrap_or(2)))
+        },
+        newline: true,
+    };
+    if flags.sort_keys {
+        profile.render(&canonicalize(state))
+    } else {
+        profile.render(state)
+    }
+}
+
+/// The formatting profile recorded for the resolved observation: the header's
+/// for the initial state, the observe event's metadata for the rest.
+#[allow(clippy::result_large_err)]
+fn original_profile(
+    header: &HeaderSummary,
+    observation_id: &str,
+    metadata: Option<&Map<String, Value>>,
+) -> Result<FormatProfile, Diagnostic> {
+    let recorded = if observation_id == "initial" {
+        header.format.clone().map(|profile| profile.validate().map(|()| profile))
+    } else {
+        FormatProfile::from_metadata(metadata)
+    };
+
+    match recorded {
+        Some(Ok(profile)) => Ok(profile),
+        Some(Err(problem)) => Err(Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::FormatNotReproducible,
+            format!(
+                "The format profile of observation '{}' is invalid: {}.",
+                observation_id, problem
+            ),
+        )),
+        None => Err(Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::FormatNotReproducible,
+            format!(
+                "Observation '{}' has no recorded format, so I can't print its original bytes.",
+                observation_id
+            ),
+        )
+        .with_advice(
+            "Only files archived with `json-archive --keep-format` can be printed with --original. \
+             Leave out --original to print the state as formatted JSON."
+                .to_string(),
+        )),
+    }
+}
+
+/// Pick one document out of a collection archive's state.
+#[allow(clippy::result_large_err)]
+fn select_document(
+    flags: &flags::State,
+    header: &HeaderSummary,
+    observation_id: &str,
+    state: Value,
+    name: &str,
+) -> Result<Value, Diagnostic> {
+    if !header.collection {
+        return Err(Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::MissingHeaderField,
+            format!(
+                "I can't pick out the document '{}' because {} isn't a collection archive.",
+                name,
+                flags.file.display()
+            ),
+        )
+        .with_advice(
+            "--doc only works on archives created with --dir. This archive tracks a single \
+             JSON file, so leave out --doc to get its state."
+                .to_string(),
+        ));
     }
 
-    Vec::new()
+    let Value::Object(mut documents) = state else {
+        return Err(Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::TypeMismatch,
+            format!(
+                "I expected the state at observation '{}' to be an object of documents, but it isn't.",
+                observation_id
+            ),
+        )
+        .with_advice("Run `json-archive validate` to look for damage in the archive.".to_string()));
+    };
+
+    documents.remove(name).ok_or_else(|| {
+        let names: Vec<&str> = documents.keys().map(String::as_str).collect();
+        let advice = if names.is_empty() {
+            "The collection had no documents at this observation.".to_string()
+        } else {
+            format!("Documents at this observation: {}", names.join(", "))
+        };
+        Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!(
+                "I couldn't find the document '{}' at observation '{}'.",
+                name, observation_id
+            ),
+        )
+        .with_advice(advice)
+    })
 }
 
-fn parse_access_method(flags: &flags::State) -> Result<AccessMethod, Diagnostic> {
+#[allow(clippy::result_large_err)]
+fn parse_access_method(flags: &flags::State) -> Result<StateSelector, Diagnostic> {
     let mut methods = Vec::new();
 
     if let Some(ref id) = flags.id {
-        methods.push(AccessMethod::Id(id.clone()));
+        methods.push(StateSelector::Id(id.clone()));
     }
 
     if let Some(index) = flags.index {
-        methods.push(AccessMethod::Index(index));
+        methods.push(StateSelector::Index(index));
     }
 
     if let Some(ref as_of_str) = flags.as_of {
         match as_of_str.parse::<DateTime<Utc>>() {
-            Ok(dt) => methods.push(AccessMethod::AsOf(dt)),
+            Ok(dt) => methods.push(StateSelector::AsOf(dt)),
             Err(_) => {
                 return Err(Diagnostic::new(
                     DiagnosticLevel::Fatal,
@@ -102,7 +293,7 @@ fn parse_access_method(flags: &flags::State) -> Result<AccessMethod, Diagnostic>
 
     if let Some(ref right_before_str) = flags.before {
         match right_before_str.parse::<DateTime<Utc>>() {
-            Ok(dt) => methods.push(AccessMethod::RightBefore(dt)),
+            Ok(dt) => methods.push(StateSelector::Before(dt)),
             Err(_) => {
                 return Err(Diagnostic::new(
                     DiagnosticLevel::Fatal,
@@ -115,7 +306,7 @@ fn parse_access_method(flags: &flags::State) -> Result<AccessMethod, Diagnostic>
 
     if let Some(ref after_str) = flags.after {
         match after_str.parse::<DateTime<Utc>>() {
-            Ok(dt) => methods.push(AccessMethod::After(dt)),
+            Ok(dt) => methods.push(StateSelector::After(dt)),
             Err(_) => {
                 return Err(Diagnostic::new(
                     DiagnosticLevel::Fatal,
@@ -127,11 +318,11 @@ fn parse_access_method(flags: &flags::State) -> Result<AccessMethod, Diagnostic>
     }
 
     if flags.latest.unwrap_or(false) {
-        methods.push(AccessMethod::Latest);
+        methods.push(StateSelector::Latest);
     }
 
     match methods.len() {
-        0 => Ok(AccessMethod::Latest), // Default to latest if no flags specified
+        0 => Ok(StateSelector::Latest), // Default to latest if no flags specified
         1 => Ok(methods.into_iter().next().unwrap()),
         _ => Err(Diagnostic::new(
             DiagnosticLevel::Fatal,
@@ -146,222 +337,3 @@ fn parse_access_method(flags: &flags::State) -> Result<AccessMethod, Diagnostic>
         ))
     }
 }
-
-fn find_and_replay_to_target(
-    file_path: &Path,
-    access_method: &AccessMethod,
-) -> Result<Value, Vec<Diagnostic>> {
-    let reader = match ArchiveReader::new(file_path, ReadMode::AppendSeek) {
-        Ok(r) => r,
-        Err(e) => {
-            return Err(vec![Diagnostic::new(
-                DiagnosticLevel::Fatal,
-                DiagnosticCode::PathNotFound,
-                format!("I couldn't open the archive file: {}", e),
-            )]);
-        }
-    };
-
-    let (initial_state, mut event_iter) = match reader.events(file_path) {
-        Ok(r) => r,
-        Err(e) => {
-            return Err(vec![Diagnostic::new(
-                DiagnosticLevel::Fatal,
-                DiagnosticCode::PathNotFound,
-                format!("I couldn't read the archive file: {}", e),
-            )]);
-        }
-    };
-
-    // Check for fatal diagnostics from initial parsing
-    if event_iter.diagnostics.has_fatal() {
-        return Err(event_iter.diagnostics.diagnostics().to_vec());
-    }
-
-    // Collect observations while replaying events
-    let mut observations = Vec::new();
-    let mut current_state = initial_state.clone();
-    let created = event_iter.header.created;
-
-    // Add initial state as observation 0
-    observations.push(ObservationWithEvents {
-        id: "initial".to_string(),
-        timestamp: created,
-        final_state: initial_state,
-    });
-
-    // Process events and track state at each observation
-    while let Some(event) = event_iter.next() {
-        match event {
-            Event::Observe { observation_id, timestamp, change_count: _ } => {
-                observations.push(ObservationWithEvents {
-                    id: observation_id,
-                    timestamp,
-                    final_state: current_state.clone(),
-                });
-            }
-            Event::Add { path, value, .. } => {
-                let _ = apply_add(&mut current_state, &path, value);
-
-                // Update the final state of the last observation
-                if let Some(last_obs) = observations.last_mut() {
-                    if last_obs.id != "initial" {
-                        last_obs.final_state = current_state.clone();
-                    }
-                }
-            }
-            Event::Change { path, new_value, .. } => {
-                let _ = apply_change(&mut current_state, &path, new_value);
-
-                // Update the final state of the last observation
-                if let Some(last_obs) = observations.last_mut() {
-                    if last_obs.id != "initial" {
-                        last_obs.final_state = current_state.clone();
-                    }
-                }
-            }
-            Event::Remove { path, .. } => {
-                let _ = apply_remove(&mut current_state, &path);
-
-                // Update the final state of the last observation
-                if let Some(last_obs) = observations.last_mut() {
-                    if last_obs.id != "initial" {
-                        last_obs.final_state = current_state.clone();
-                    }
-                }
-            }
-            Event::Move { path, moves, .. } => {
-                let _ = apply_move(&mut current_state, &path, moves);
-
-                // Update the final state of the last observation
-                if let Some(last_obs) = observations.last_mut() {
-                    if last_obs.id != "initial" {
-                        last_obs.final_state = current_state.clone();
-                    }
-                }
-            }
-            Event::Snapshot { object, .. } => {
-                current_state = object;
-
-                // Update the final state of the last observation
-                if let Some(last_obs) = observations.last_mut() {
-                    if last_obs.id != "initial" {
-                        last_obs.final_state = current_state.clone();
-                    }
-                }
-            }
-        }
-    }
-
-    if observations.is_empty() {
-        return Err(vec![Diagnostic::new(
-            DiagnosticLevel::Fatal,
-            DiagnosticCode::EmptyFile,
-            "No observations found in the archive".to_string(),
-        )]);
-    }
-
-    // Find the target observation based on access method
-    let target_observation = match access_method {
-        AccessMethod::Id(id) => observations
-            .iter()
-            .find(|obs| obs.id == *id)
-            .ok_or_else(|| {
-                vec![Diagnostic::new(
-                    DiagnosticLevel::Fatal,
-                    DiagnosticCode::NonExistentObservationId,
-                    format!("I couldn't find an observation with ID '{}'", id),
-                )
-                .with_advice(
-                    "Use 'json-archive info' to see available observation IDs".to_string(),
-                )]
-            })?,
-        AccessMethod::Index(index) => {
-            if *index >= observations.len() {
-                return Err(vec![Diagnostic::new(
-                    DiagnosticLevel::Fatal,
-                    DiagnosticCode::ArrayIndexOutOfBounds,
-                    format!(
-                        "Index {} is out of bounds. The archive has {} observations (0-{})",
-                        index,
-                        observations.len(),
-                        observations.len() - 1
-                    ),
-                )
-                .with_advice(
-                    "Use 'json-archive info' to see available observation indices".to_string(),
-                )]);
-            }
-            &observations[*index]
-        }
-        AccessMethod::AsOf(timestamp) => {
-            // Find most recent observation with timestamp <= given timestamp
-            observations
-                .iter()
-                .filter(|obs| obs.timestamp <= *timestamp)
-                .max_by_key(|obs| obs.timestamp)
-                .ok_or_else(|| {
-                    vec![Diagnostic::new(
-                        DiagnosticLevel::Fatal,
-                        DiagnosticCode::PathNotFound,
-                        format!(
-                            "No observations found as of {}",
-                            timestamp.format("%Y-%m-%d %H:%M:%S UTC")
-                        ),
-                    )
-                    .with_advice(
-                        "Try using --after to find the first observation after this time"
-                            .to_string(),
-                    )]
-                })?
-        }
-        AccessMethod::RightBefore(timestamp) => {
-            // Find most recent observation with timestamp < given timestamp (strictly before)
-            observations
-                .iter()
-                .filter(|obs| obs.timestamp < *timestamp)
-                .max_by_key(|obs| obs.timestamp)
-                .ok_or_else(|| {
-                    vec![Diagnostic::new(
-                        DiagnosticLevel::Fatal,
-                        DiagnosticCode::PathNotFound,
-                        format!(
-                            "No observations found before {}",
-                            timestamp.format("%Y-%m-%d %H:%M:%S UTC")
-                        ),
-                    )
-                    .with_advice(
-                        "Try using --as-of to include observations at exactly this time"
-                            .to_string(),
-                    )]
-                })?
-        }
-        AccessMethod::After(timestamp) => {
-            // Find earliest observation with timestamp > given timestamp
-            observations.iter()
-                .filter(|obs| obs.timestamp > *timestamp)
-                .min_by_key(|obs| obs.timestamp)
-                .ok_or_else(|| vec![
-                    Diagnostic::new(
-                        DiagnosticLevel::Fatal,
-                        DiagnosticCode::PathNotFound,
-                        format!("No observations found after {}", timestamp.format("%Y-%m-%d %H:%M:%S UTC"))
-                    )
-                    .with_advice("Try using --as-of to find the most recent observation before or at this time".to_string())
-                ])?
-        }
-        AccessMethod::Latest => {
-            // Find observation with latest timestamp
-            observations.iter().max_by_key(|obs| obs.timestamp).unwrap() // Safe because we checked observations is not empty
-        }
-    };
-
-    Ok(target_observation.final_state.clone())
-}
-
-#[derive(Debug)]
-struct ObservationWithEvents {
-    id: String,
-    timestamp: DateTime<Utc>,
-    final_state: Value,
-}
@@ -0,0 +1,136 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+use crate::flags;
+use json_archive::{ArchiveReader, Diagnostic, DiagnosticCode, DiagnosticLevel, ReadMode};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct JsonValidateOutput<'a> {
+    archive: String,
+    valid: bool,
+    strict: bool,
+    observation_count: usize,
+    snapshot_count: usize,
+    error_count: usize,
+    warning_count: usize,
+    diagnostics: &'a [Diagnostic],
+}
+
+pub fn run(flags: &flags::Validate) -> Vec<Diagnostic> {
+    if !flags.file.exists() {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't find the archive file: {}", flags.file.display()),
+        )
+        .with_advice(
+            "Make sure the file path is correct and the file exists. \
+                 Check for typos in the filename."
+                .to_string(),
+        )];
+    }
+
+    let reader = match ArchiveReader::new(&flags.file, ReadMode::FullValidation) {
+        Ok(r) => r,
+        Err(e) => {
+            return vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                format!("I couldn't open the archive file: {}", e),
+            )];
+        }
+    };
+
+    let read_result = match reader.read(&flags.file) {
+        Ok(result) => result,
+        Err(e) => {
+            return vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                format!("I couldn't read the archive file: {}", e),
+            )];
+        }
+    };
+
+    let strict = flags.strict;
+    let observation_count = read_result.observation_count;
+    let snapshot_count = read_result.snapshot_count;
+
+    // In strict mode every warning is treated as an error
+    let diagnostics: Vec<Diagnostic> = read_result
+        .diagnostics
+        .into_diagnostics()
+        .into_iter()
+        .map(|mut diagnostic| {
+            if strict && diagnostic.level == DiagnosticLevel::Warning {
+                diagnostic.level = DiagnosticLevel::Fatal;
+            }
+            diagnostic
+        })
+        .collect();
+
+    let error_count = diagnostics.iter().filter(|d| d.is_fatal()).count();
+    let warning_count = diagnostics
+        .iter()
+        .filter(|d| d.level == DiagnosticLevel::Warning)
+        .count();
+    let valid = error_count == 0;
+
+    let is_json_output = flags.output.as_ref().map(|s| s == "json").unwrap_or(false);
+
+    if is_json_output {
+        let json_output = JsonValidateOutput {
+            archive: flags.file.display().to_string(),
+            valid,
+            strict,
+            observation_count,
+            snapshot_count,
+            error_count,
+            warning_count,
+            diagnostics: &diagnostics,
+        };
+
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json_output).unwrap_or_default()
+        );
+    } else {
+        println!("Archive: {}", flags.file.display());
+        println!(
+            "{} observations, {} snapshots",
+            observation_count, snapshot_count
+        );
+
+        if valid && warning_count == 0 {
+            println!("Result: valid");
+        } else if valid {
+            println!("Result: valid with {} warnings", warning_count);
+        } else {
+            println!(
+                "Result: invalid ({} errors, {} warnings)",
+                error_count, warning_count
+            );
+        }
+    }
+
+    diagnostics
+}
@@ -0,0 +1,82 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+
+use crate::flags;
+use json_archive::{format_profile, Diagnostic, DiagnosticCode, DiagnosticLevel};
+
+pub fn run(flags: &flags::VerifyRoundtrip) -> Vec<Diagnostic> {
+    if flags.files.is_empty() {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::MissingHeaderField,
+            "Please tell me which JSON files to check.".to_string(),
+        )
+        .with_advice("Usage: json-archive verify-roundtrip data.json".to_string())];
+    }
+
+    let mut diagnostics = Vec::new();
+    for path in &flags.files {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::PathNotFound,
+                    format!("I couldn't read {}: {}", path.display(), e),
+                ));
+                continue;
+            }
+        };
+        let value = match serde_json::from_str(&content) {
+            Ok(value) => value,
+            Err(e) => {
+                diagnostics.push(
+                    Diagnostic::new(
+                        DiagnosticLevel::Fatal,
+                        DiagnosticCode::InvalidEventJson,
+                        format!("I couldn't parse {} as JSON: {}", path.display(), e),
+                    )
+                    .with_advice("Make sure the file contains valid JSON.".to_string()),
+                );
+                continue;
+            }
+        };
+
+        match format_profile(path, &content, &value) {
+            Ok(profile) => {
+                let layout = match profile.indent.as_deref() {
+                    None => "compact".to_string(),
+                    Some(indent) => format!("indented with {:?}", indent),
+                };
+                let newline = if profile.newline { "a" } else { "no" };
+                println!(
+                    "{}: reproducible byte for byte ({}, {} trailing newline)",
+                    path.display(),
+                    layout,
+                    newline
+                );
+            }
+            Err(errors) => diagnostics.extend(errors),
+        }
+    }
+    diagnostics
+}
@@ -0,0 +1,203 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+use crate::flags;
+use json_archive::{
+    create_archive_from_files, default_output_filename, file_provenance, open_append, parse_observation_metadata,
+    ArchiveOptions, Diagnostic, DiagnosticCode, DiagnosticLevel, OpenedArchive, SnapshotMode,
+};
+use serde_json::Value;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
+const DEFAULT_DEBOUNCE_MS: u64 = 500;
+
+/// What a poll can see of the file without reading it. A write changes at
+/// least one of these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileVersion {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileVersion {
+    /// `None` while the file is missing, which happens for a moment when an
+    /// editor or tool replaces it by renaming a new file over it.
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Watching only ends when it's stopped, so warnings along the way are passed
+/// to `report` as they come.
+pub fn run(flags: &flags::Watch, report: &dyn Fn(&[Diagnostic])) -> Vec<Diagnostic> {
+    if !flags.file.exists() {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't find the file to watch: {}", flags.file.display()),
+        )
+        .with_advice(
+            "Make sure the file path is correct and the file exists. \
+                 Check for typos in the filename."
+                .to_string(),
+        )];
+    }
+
+    let output_path = match &flags.output {
+        Some(path) => path.clone(),
+        None => default_output_filename(&flags.file),
+    };
+
+    let observation_metadata = match parse_observation_metadata(&flags.meta) {
+        Ok(metadata) => metadata,
+        Err(diagnostics) => return diagnostics,
+    };
+
+    let options = ArchiveOptions {
+        source: flags.source.clone(),
+        snapshot_interval: flags.snapshot_interval,
+        snapshot_mode: if flags.replace_snapshots {
+            SnapshotMode::Replace
+        } else {
+            SnapshotMode::Append
+        },
+        observation_metadata,
+        hash: flags.hash,
+        recover: flags.recover,
+        ..ArchiveOptions::default()
+    };
+
+    if !output_path.exists() {
+        if let Err(diagnostics) = create_archive_from_files(std::slice::from_ref(&flags.file), &output_path, &options) {
+            return diagnostics;
+        }
+        println!("Created archive: {}", output_path.display());
+    }
+
+    // Read the archive once; from here on the builder holds the current state
+    let OpenedArchive {
+        mut writer,
+        mut builder,
+        diagnostics,
+        ..
+    } = match open_append(&output_path, &output_path, &options, false) {
+        Ok(opened) => opened,
+        Err(diagnostics) => return diagnostics,
+    };
+    report(&diagnostics);
+
+    let poll_interval = Duration::from_millis(flags.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL_MS));
+    let debounce = Duration::from_millis(flags.debounce.unwrap_or(DEFAULT_DEBOUNCE_MS));
+
+    println!(
+        "Watching {} and appending to {} (Ctrl-C to stop)",
+        flags.file.display(),
+        output_path.display()
+    );
+
+    // The version last read, and a version waiting to settle. The first poll
+    // always reads the file, so edits made while nothing was watching are
+    // picked up too.
+    let mut recorded: Option<FileVersion> = None;
+    let mut pending: Option<(FileVersion, Instant)> = None;
+
+    loop {
+        let version = FileVersion::of(&flags.file);
+
+        match (version, pending) {
+            (None, _) => pending = None,
+            (Some(version), _) if Some(version) == recorded => pending = None,
+            // Unchanged for a whole debounce period: the writer is done
+            (Some(version), Some((seen, since))) if version == seen && since.elapsed() >= debounce => {
+                recorded = Some(version);
+                pending = None;
+
+                let state = match read_state(&flags.file) {
+                    Ok(state) => state,
+                    Err(diagnostic) => {
+                        report(&[diagnostic]);
+                        thread::sleep(poll_interval);
+                        continue;
+                    }
+                };
+
+                // Touching the file or rewriting the same content isn't a change
+                if state == *builder.current_state() {
+                    thread::sleep(poll_interval);
+                    continue;
+                }
+
+                if let Err(diagnostics) = writer.write_comment(&format!("Processing file: {:?}", flags.file)) {
+                    return diagnostics;
+                }
+                if let Some(observation) = builder.add_state_with_metadata(state.clone(), file_provenance(&flags.file)) {
+                    let id = observation.id.clone();
+                    let changes = observation.events.len();
+                    if let Err(diagnostics) = writer.write_observation_with_state(observation, &state) {
+                        return diagnostics;
+                    }
+                    println!("Recorded {} ({} changes)", id, changes);
+                }
+                if let Err(diagnostics) = writer.flush() {
+                    return diagnostics;
+                }
+            }
+            (Some(version), Some((seen, _))) if version == seen => {}
+            (Some(version), _) => pending = Some((version, Instant::now())),
+        }
+
+        thread::sleep(poll_interval);
+    }
+}
+
+/// Read the watched file. Failing here is a warning, not fatal: the file may
+/// have been caught halfway through a slow write, and the next change to it
+/// gets another try.
+#[allow(clippy::result_large_err)]
+fn read_state(path: &Path) -> Result<Value, Diagnostic> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        Diagnostic::new(
+            DiagnosticLevel::Warning,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't read {}, so I skipped this version of it: {}", path.display(), e),
+        )
+    })?;
+
+    serde_json::from_str(&content).map_err(|e| {
+        Diagnostic::new(
+            DiagnosticLevel::Warning,
+            DiagnosticCode::InvalidEventJson,
+            format!("I couldn't parse {} as JSON, so I skipped this version of it: {}", path.display(), e),
+        )
+        .with_advice(
+            "If the file was still being written, I'll try again the next time it changes. \
+             Increase --debounce if your writer pauses mid-write."
+                .to_string(),
+        )
+    })
+}
@@ -0,0 +1,145 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Collection archives: one archive for a whole directory of JSON files.
+//!
+//! Tracking hundreds of small files with one archive each gets unwieldy. A
+//! collection archive instead keys its state by file name, `{"a.json": {...},
+//! "b.json": {...}}`, and says so with `"collection": true` in the header.
+//! Nothing else about the format changes: the differ and the reader already
+//! handle an object root, and a file that appears or disappears between runs
+//! is just an add or remove of `/<name>`.
+
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticLevel};
+
+/// Read every `*.json` file directly inside `dir` into a collection state.
+/// Subdirectories are not descended into. All the files that can't be read
+/// are reported together, so one run shows everything that needs fixing.
+pub fn read_collection<P: AsRef<Path>>(dir: P) -> Result<Value, Vec<Diagnostic>> {
+    let dir = dir.as_ref();
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't read the directory {}: {}", dir.display(), e),
+        )
+        .with_advice("Make sure the directory exists and you have permission to read it.".to_string())]
+    })?;
+
+    let mut documents = Map::new();
+    let mut diagnostics = Vec::new();
+
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(e) => {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::PathNotFound,
+                    format!("I couldn't list the directory {}: {}", dir.display(), e),
+                ));
+                continue;
+            }
+        };
+
+        if !path.is_file() || path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::InvalidUtf8,
+                    format!("I can't use {} as a document name because it isn't valid UTF-8.", path.display()),
+                )
+                .with_advice("Document names become keys in the archive's JSON, so rename the file.".to_string()),
+            );
+            continue;
+        };
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::PathNotFound,
+                    format!("I couldn't read the input file {}: {}", path.display(), e),
+                ));
+                continue;
+            }
+        };
+
+        match serde_json::from_str::<Value>(&content) {
+            Ok(document) => {
+                documents.insert(name.to_string(), document);
+            }
+            Err(e) => diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::InvalidEventJson,
+                    format!("I couldn't parse the input file {} as JSON: {}", path.display(), e),
+                )
+                .with_advice("Make sure the file contains valid JSON.".to_string()),
+            ),
+        }
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    // Directory listings come in no particular order
+    documents.sort_keys();
+    Ok(Value::Object(documents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_collection() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("a.info.json"), r#"{"size": 1}"#)?;
+        std::fs::write(dir.path().join("b.info.json"), r#"[1, 2]"#)?;
+        std::fs::write(dir.path().join("notes.txt"), "not json")?;
+        std::fs::write(dir.path().join("old.json.archive"), "{}")?;
+        std::fs::create_dir(dir.path().join("nested.json"))?;
+
+        let state = read_collection(dir.path()).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(
+            state,
+            json!({"a.info.json": {"size": 1}, "b.info.json": [1, 2]})
+        );
+
+        std::fs::write(dir.path().join("broken.json"), "{")?;
+        let diagnostics = read_collection(dir.path()).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, DiagnosticCode::InvalidEventJson);
+
+        Ok(())
+    }
+}
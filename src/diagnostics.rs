@@ -19,7 +19,10 @@
 // marxism@peoplesgrocers.com
 //
 
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_json::{json, Value};
 use std::fmt;
+use std::ops::Range;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DiagnosticLevel {
@@ -28,18 +31,55 @@ pub enum DiagnosticLevel {
     Info,
 }
 
-impl fmt::Display for DiagnosticLevel {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl DiagnosticLevel {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            DiagnosticLevel::Fatal => write!(f, "error"),
-            DiagnosticLevel::Warning => write!(f, "warning"),
-            DiagnosticLevel::Info => write!(f, "info"),
+            DiagnosticLevel::Fatal => "error",
+            DiagnosticLevel::Warning => "warning",
+            DiagnosticLevel::Info => "info",
+        }
+    }
+
+    /// The matching SARIF `level` value.
+    fn sarif_level(&self) -> &'static str {
+        match self {
+            DiagnosticLevel::Fatal => "error",
+            DiagnosticLevel::Warning => "warning",
+            DiagnosticLevel::Info => "note",
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum DiagnosticCode {
+impl fmt::Display for DiagnosticLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for DiagnosticLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Declares `DiagnosticCode` along with `DiagnosticCode::ALL`, so the list
+/// of every code can't fall out of step with the enum.
+macro_rules! diagnostic_codes {
+    ($($variant:ident,)*) => {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum DiagnosticCode {
+            $($variant,)*
+        }
+
+        impl DiagnosticCode {
+            /// Every code, in the order declared, which is numeric order.
+            pub const ALL: [DiagnosticCode; [$(stringify!($variant)),*].len()] =
+                [$(DiagnosticCode::$variant),*];
+        }
+    };
+}
+
+diagnostic_codes! {
     EmptyFile,
     MissingHeader,
     InvalidUtf8,
@@ -76,9 +116,23 @@ pub enum DiagnosticCode {
 
     SnapshotStateMismatch,
     SnapshotTimestampOrder,
+    ObservationTimestampOrder,
+
+    StateHashMismatch,
+    HashChainBroken,
+    MissingStateHash,
+
+    FormatNotReproducible,
 }
 
 impl DiagnosticCode {
+    /// Look up a code by its short form, e.g. "E051". Case-insensitive.
+    pub fn from_code(code: &str) -> Option<DiagnosticCode> {
+        DiagnosticCode::ALL
+            .into_iter()
+            .find(|c| c.as_str().eq_ignore_ascii_case(code))
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             DiagnosticCode::EmptyFile => "E001",
@@ -117,6 +171,13 @@ impl DiagnosticCode {
 
             DiagnosticCode::SnapshotStateMismatch => "W080",
             DiagnosticCode::SnapshotTimestampOrder => "W081",
+            DiagnosticCode::ObservationTimestampOrder => "W082",
+
+            DiagnosticCode::StateHashMismatch => "E090",
+            DiagnosticCode::HashChainBroken => "E091",
+            DiagnosticCode::MissingStateHash => "W092",
+
+            DiagnosticCode::FormatNotReproducible => "E100",
         }
     }
 
@@ -158,10 +219,23 @@ impl DiagnosticCode {
 
             DiagnosticCode::SnapshotStateMismatch => "Snapshot state mismatch",
             DiagnosticCode::SnapshotTimestampOrder => "Snapshot timestamp out of order",
+            DiagnosticCode::ObservationTimestampOrder => "Observation timestamp out of order",
+
+            DiagnosticCode::StateHashMismatch => "State hash mismatch",
+            DiagnosticCode::HashChainBroken => "Hash chain broken",
+            DiagnosticCode::MissingStateHash => "Missing state hash",
+
+            DiagnosticCode::FormatNotReproducible => "Format not reproducible",
         }
     }
 }
 
+impl Serialize for DiagnosticCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Diagnostic {
     pub filename: Option<String>,
@@ -204,6 +278,37 @@ impl Diagnostic {
         self
     }
 
+    /// Point at a byte range of a source line: sets the column and renders the
+    /// line with carets under the range, like rustc and Elm do.
+    ///
+    /// ```text
+    /// 3 | ["add", 42, 1, "obs-1"]
+    ///   |         ^^
+    /// ```
+    ///
+    /// Call after `with_location` so the snippet can show the line number.
+    pub fn with_caret(mut self, line: &str, span: Range<usize>) -> Self {
+        let start = floor_char_boundary(line, span.start);
+        let end = floor_char_boundary(line, span.end.max(span.start));
+        let column = line[..start].chars().count() + 1;
+        let width = line[start..end].chars().count().max(1);
+
+        let gutter = self
+            .line_number
+            .map(|n| n.to_string())
+            .unwrap_or_default();
+        self.column = Some(column);
+        self.code_snippet = Some(format!(
+            "{} | {}\n{} | {}{}",
+            gutter,
+            line,
+            " ".repeat(gutter.len()),
+            " ".repeat(column - 1),
+            "^".repeat(width)
+        ));
+        self
+    }
+
     pub fn with_advice(mut self, advice: String) -> Self {
         self.advice = Some(advice);
         self
@@ -214,6 +319,14 @@ impl Diagnostic {
     }
 }
 
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
 impl fmt::Display for Diagnostic {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let (Some(filename), Some(line)) = (&self.filename, self.line_number) {
@@ -248,6 +361,99 @@ impl fmt::Display for Diagnostic {
     }
 }
 
+/// Machine-readable form of a diagnostic. Every field is always present (null
+/// when unknown) so consumers can rely on the shape:
+///
+/// ```json
+/// {"level": "error", "code": "E051", "title": "Path not found",
+///  "message": "...", "file": "a.json.archive", "line": 3, "column": null,
+///  "snippet": "3 | [...]", "advice": "..."}
+/// ```
+impl Serialize for Diagnostic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Diagnostic", 9)?;
+        state.serialize_field("level", &self.level)?;
+        state.serialize_field("code", &self.code)?;
+        state.serialize_field("title", self.code.title())?;
+        state.serialize_field("message", &self.description)?;
+        state.serialize_field("file", &self.filename)?;
+        state.serialize_field("line", &self.line_number)?;
+        state.serialize_field("column", &self.column)?;
+        state.serialize_field("snippet", &self.code_snippet)?;
+        state.serialize_field("advice", &self.advice)?;
+        state.end()
+    }
+}
+
+/// Render diagnostics as a SARIF 2.1.0 log, the format code scanning
+/// dashboards understand.
+pub fn to_sarif(diagnostics: &[Diagnostic]) -> Value {
+    let mut rules: Vec<Value> = Vec::new();
+    let mut rule_ids: Vec<&'static str> = Vec::new();
+    for diagnostic in diagnostics {
+        let id = diagnostic.code.as_str();
+        if !rule_ids.contains(&id) {
+            rule_ids.push(id);
+            rules.push(json!({
+                "id": id,
+                "shortDescription": { "text": diagnostic.code.title() },
+            }));
+        }
+    }
+
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let mut text = diagnostic.description.clone();
+            if let Some(advice) = &diagnostic.advice {
+                text.push_str("\n\n");
+                text.push_str(advice);
+            }
+
+            let mut result = json!({
+                "ruleId": diagnostic.code.as_str(),
+                "level": diagnostic.level.sarif_level(),
+                "message": { "text": text },
+            });
+
+            if let Some(filename) = &diagnostic.filename {
+                let mut region = serde_json::Map::new();
+                if let Some(line) = diagnostic.line_number {
+                    region.insert("startLine".to_string(), json!(line));
+                }
+                if let Some(column) = diagnostic.column {
+                    region.insert("startColumn".to_string(), json!(column));
+                }
+
+                let mut physical_location = json!({
+                    "artifactLocation": { "uri": filename },
+                });
+                if !region.is_empty() {
+                    physical_location["region"] = Value::Object(region);
+                }
+                result["locations"] = json!([{ "physicalLocation": physical_location }]);
+            }
+
+            result
+        })
+        .collect();
+
+    json!({
+        "version": "2.1.0",
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "json-archive",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
 #[derive(Debug, Default)]
 pub struct DiagnosticCollector {
     diagnostics: Vec<Diagnostic>,
@@ -283,4 +489,112 @@ impl DiagnosticCollector {
     pub fn len(&self) -> usize {
         self.diagnostics.len()
     }
+
+    /// Turn the fatal diagnostics added since `start` into warnings. Returns
+    /// the code of the first one, if there were any.
+    pub fn downgrade_since(&mut self, start: usize) -> Option<DiagnosticCode> {
+        let mut first = None;
+        for diagnostic in self.diagnostics.iter_mut().skip(start) {
+            if diagnostic.is_fatal() {
+                diagnostic.level = DiagnosticLevel::Warning;
+                first.get_or_insert_with(|| diagnostic.code.clone());
+            }
+        }
+        first
+    }
+}
+
+impl Serialize for DiagnosticCollector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.diagnostics.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_json_schema() {
+        let diagnostic = Diagnostic::new(
+            DiagnosticLevel::Warning,
+            DiagnosticCode::ChangeCountMismatch,
+            "The observe event at line 2 declared 2 changes, but I found 1.".to_string(),
+        )
+        .with_location("data.json.archive".to_string(), 2);
+
+        let value = serde_json::to_value(&diagnostic).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "level": "warning",
+                "code": "W040",
+                "title": "Change count mismatch",
+                "message": "The observe event at line 2 declared 2 changes, but I found 1.",
+                "file": "data.json.archive",
+                "line": 2,
+                "column": null,
+                "snippet": null,
+                "advice": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_all_codes_in_numeric_order() {
+        let numbers: Vec<&str> = DiagnosticCode::ALL.iter().map(|code| &code.as_str()[1..]).collect();
+        let mut sorted = numbers.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(numbers, sorted);
+    }
+
+    #[test]
+    fn test_caret_snippet() {
+        let line = r#"["add", 42, 1, "obs-1"]"#;
+        let diagnostic = Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::WrongFieldType,
+            "I expected the path to be a string.".to_string(),
+        )
+        .with_location("data.json.archive".to_string(), 12)
+        .with_caret(line, 8..10);
+
+        assert_eq!(diagnostic.column, Some(9));
+        assert_eq!(
+            diagnostic.code_snippet.as_deref(),
+            Some("12 | [\"add\", 42, 1, \"obs-1\"]\n   |         ^^")
+        );
+    }
+
+    #[test]
+    fn test_sarif_output() {
+        let diagnostics = vec![
+            Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                "I couldn't find the key 'a'".to_string(),
+            )
+            .with_location("data.json.archive".to_string(), 3)
+            .with_column(7),
+            Diagnostic::new(
+                DiagnosticLevel::Info,
+                DiagnosticCode::PathNotFound,
+                "Another one".to_string(),
+            ),
+        ];
+
+        let sarif = to_sarif(&diagnostics);
+        let run = &sarif["runs"][0];
+        assert_eq!(sarif["version"], json!("2.1.0"));
+        assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), 1);
+        assert_eq!(run["results"][0]["ruleId"], json!("E051"));
+        assert_eq!(run["results"][0]["level"], json!("error"));
+        assert_eq!(
+            run["results"][0]["locations"][0]["physicalLocation"]["region"],
+            json!({"startLine": 3, "startColumn": 7})
+        );
+        assert_eq!(run["results"][1]["level"], json!("note"));
+        assert!(run["results"][1].get("locations").is_none());
+    }
 }
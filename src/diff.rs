@@ -20,8 +20,9 @@
 //
 
 use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
+use crate::canonical::canonicalize;
 use crate::events::Event;
 
 pub fn diff(old: &Value, new: &Value, base_path: &str, observation_id: &str) -> Vec<Event> {
@@ -63,11 +64,27 @@ fn diff_objects(
     observation_id: &str,
     result: &mut Vec<Event>,
 ) {
-    let old_keys: HashSet<&String> = old.keys().collect();
-    let new_keys: HashSet<&String> = new.keys().collect();
+    // Replaying keeps the remaining keys where they were and adds new ones at
+    // the end. If that isn't the new order, replace the object as a whole.
+    // Without `preserve-order` keys are always sorted, so order can't change.
+    #[cfg(feature = "preserve-order")]
+    {
+        let replayed_order = old
+            .keys()
+            .filter(|key| new.contains_key(*key))
+            .chain(new.keys().filter(|key| !old.contains_key(*key)));
+        if !replayed_order.eq(new.keys()) {
+            result.push(Event::Change {
+                path: base_path.to_string(),
+                new_value: Value::Object(new.clone()),
+                observation_id: observation_id.to_string(),
+            });
+            return;
+        }
+    }
 
     // Removed keys
-    for key in old_keys.difference(&new_keys) {
+    for key in old.keys().filter(|key| !new.contains_key(*key)) {
         let path = format_path(base_path, key);
         result.push(Event::Remove {
             path,
@@ -76,22 +93,22 @@ fn diff_objects(
     }
 
     // Added keys
-    for key in new_keys.difference(&old_keys) {
+    for (key, value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
         let path = format_path(base_path, key);
         result.push(Event::Add {
             path,
-            value: new[*key].clone(),
+            value: value.clone(),
             observation_id: observation_id.to_string(),
         });
     }
 
     // Changed keys
-    for key in old_keys.intersection(&new_keys) {
-        let path = format_path(base_path, key);
-        let old_value = &old[*key];
-        let new_value = &new[*key];
-
-        if old_value != new_value {
+    for (key, new_value) in new {
+        let Some(old_value) = old.get(key) else {
+            continue;
+        };
+        if !identical(old_value, new_value) {
+            let path = format_path(base_path, key);
             diff_recursive(old_value, new_value, &path, observation_id, result);
         }
     }
@@ -104,57 +121,55 @@ fn diff_arrays(
     observation_id: &str,
     result: &mut Vec<Event>,
 ) {
-    // Simple implementation: we'll use a more sophisticated approach for move detection
-    let mut old_items: HashMap<String, (usize, &Value)> = HashMap::new();
-    let mut new_items: HashMap<String, (usize, &Value)> = HashMap::new();
-
-    // Create content-based indices for move detection
+    // Pair each new element with an equal old one, first with first, so
+    // repeated elements are matched too
+    let mut unmatched: HashMap<String, VecDeque<usize>> = HashMap::new();
     for (i, value) in old.iter().enumerate() {
-        let key = value_hash(value);
-        old_items.insert(key, (i, value));
+        unmatched.entry(value_hash(value)).or_default().push_back(i);
     }
-
-    for (i, value) in new.iter().enumerate() {
-        let key = value_hash(value);
-        new_items.insert(key, (i, value));
+    let mut matched: HashMap<usize, usize> = HashMap::new();
+    for (new_idx, value) in new.iter().enumerate() {
+        if let Some(old_idx) = unmatched.get_mut(&value_hash(value)).and_then(VecDeque::pop_front) {
+            matched.insert(old_idx, new_idx);
+        }
     }
 
-    let old_keys: HashSet<&String> = old_items.keys().collect();
-    let new_keys: HashSet<&String> = new_items.keys().collect();
-    let common_keys: HashSet<&String> = old_keys.intersection(&new_keys).cloned().collect();
-
-    // Track which items have been processed
-    let mut processed_old: HashSet<usize> = HashSet::new();
-    let mut processed_new: HashSet<usize> = HashSet::new();
-
-    // Handle items that exist in both arrays (potential moves or unchanged)
-    let mut moves: Vec<(usize, usize)> = Vec::new();
-
-    for key in &common_keys {
-        let (old_idx, old_val) = old_items[*key];
-        let (new_idx, new_val) = new_items[*key];
-
-        processed_old.insert(old_idx);
-        processed_new.insert(new_idx);
+    // Remove from highest index to lowest to avoid index shifting issues
+    for idx in (0..old.len()).rev().filter(|idx| !matched.contains_key(idx)) {
+        result.push(Event::Remove {
+            path: format!("{}/{}", base_path, idx),
+            observation_id: observation_id.to_string(),
+        });
+    }
 
-        if old_val != new_val {
-            // Value changed
-            let path = format!("{}/{}", base_path, new_idx);
-            result.push(Event::Change {
-                path,
-                new_value: new_val.clone(),
+    // What is left is the matched elements in their old order; added ones
+    // go on the end. `order` holds the new index of each element as it
+    // stands after these events.
+    let mut order: Vec<usize> = (0..old.len()).filter_map(|idx| matched.get(&idx).copied()).collect();
+    let matched_new: HashSet<usize> = order.iter().copied().collect();
+    for (new_idx, value) in new.iter().enumerate() {
+        if !matched_new.contains(&new_idx) {
+            result.push(Event::Add {
+                path: format!("{}/{}", base_path, order.len()),
+                value: value.clone(),
                 observation_id: observation_id.to_string(),
             });
-        } else if old_idx != new_idx {
-            // Same value, different position - this is a move
-            moves.push((old_idx, new_idx));
+            order.push(new_idx);
         }
     }
 
-    // Generate move events if any
+    // Bring each position's element forward into place. Everything before
+    // `target` is already in place, so every move is to an earlier index.
+    let mut moves: Vec<(usize, usize)> = Vec::new();
+    for target in 0..order.len() {
+        let current = target + order[target..].iter().position(|idx| *idx == target).expect("every new index is in order");
+        if current != target {
+            let element = order.remove(current);
+            order.insert(target, element);
+            moves.push((current, target));
+        }
+    }
     if !moves.is_empty() {
-        // Sort moves by original position to ensure consistent ordering
-        moves.sort_by_key(|(old_idx, _)| *old_idx);
         result.push(Event::Move {
             path: base_path.to_string(),
             moves,
@@ -162,32 +177,37 @@ fn diff_arrays(
         });
     }
 
-    // Handle removed items (in old but not in new)
-    let mut removed_indices: Vec<usize> = (0..old.len())
-        .filter(|i| !processed_old.contains(i))
+    // Equal elements whose objects list their keys in another order
+    let mut changed: Vec<(usize, usize)> = matched
+        .into_iter()
+        .filter(|(old_idx, new_idx)| !identical(&old[*old_idx], &new[*new_idx]))
         .collect();
-
-    // Remove from highest index to lowest to avoid index shifting issues
-    removed_indices.sort_by(|a, b| b.cmp(a));
-
-    for idx in removed_indices {
-        let path = format!("{}/{}", base_path, idx);
-        result.push(Event::Remove {
-            path,
+    changed.sort_by_key(|(_, new_idx)| *new_idx);
+    for (_, new_idx) in changed {
+        result.push(Event::Change {
+            path: format!("{}/{}", base_path, new_idx),
+            new_value: new[new_idx].clone(),
             observation_id: observation_id.to_string(),
         });
     }
+}
 
-    // Handle added items (in new but not in old)
-    for i in 0..new.len() {
-        if !processed_new.contains(&i) {
-            let path = format!("{}/{}", base_path, i);
-            result.push(Event::Add {
-                path,
-                value: new[i].clone(),
-                observation_id: observation_id.to_string(),
-            });
+/// Equal, and with every object's keys in the same order. Objects compare
+/// equal whatever their key order, but with `preserve-order` the order is
+/// part of what gets recorded.
+fn identical(old: &Value, new: &Value) -> bool {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            old.len() == new.len()
+                && old
+                    .iter()
+                    .zip(new)
+                    .all(|((old_key, old_value), (new_key, new_value))| old_key == new_key && identical(old_value, new_value))
         }
+        (Value::Array(old), Value::Array(new)) => {
+            old.len() == new.len() && old.iter().zip(new).all(|(old, new)| identical(old, new))
+        }
+        _ => old == new,
     }
 }
 
@@ -200,10 +220,13 @@ fn format_path(base: &str, segment: &str) -> String {
     }
 }
 
+/// Identity of an array element for move detection. Equal values get the
+/// same key however their objects were built. Numbers keep the text they
+/// were read with, so `1.0` and `1`, or integers too large for a double,
+/// stay apart the way `Value` equality keeps them apart; the canonical
+/// serialization would merge them.
 fn value_hash(value: &Value) -> String {
-    // Simple content-based hash for move detection
-    // In a real implementation, you might want a more sophisticated hash
-    format!("{:?}", value)
+    serde_json::to_string(&canonicalize(value)).expect("a Value always serializes")
 }
 
 #[cfg(test)]
@@ -323,6 +346,89 @@ mod tests {
         assert!(has_move, "Expected at least one Move event");
     }
 
+    #[test]
+    fn test_array_move_of_objects() {
+        // Elements are matched by their content
+        let old = json!([{"id": 1, "name": "a"}, {"id": 2, "name": "b"}]);
+        let new = json!([{"id": 2, "name": "b"}, {"id": 1, "name": "a"}]);
+        let result = diff(&old, &new, "", "obs-1");
+
+        assert_eq!(result.len(), 1);
+        match &result[0] {
+            Event::Move { path, moves, .. } => {
+                assert_eq!(path, "");
+                assert_eq!(moves, &vec![(1, 0)]);
+            }
+            _ => panic!("Expected Move event"),
+        }
+    }
+
+    #[test]
+    fn test_array_diff_replays() {
+        let cases = [
+            (json!(["a", "b", "c"]), json!(["c", "a"])),
+            (json!(["a", "b", "c", "b"]), json!(["c", "x", "b", "a"])),
+            (json!([1, 2, 3]), json!([4, 3, 5, 1])),
+            (json!([{"id": 1}, {"id": 2}]), json!([{"id": 3}, {"id": 2}, {"id": 1}, {"id": 1}])),
+        ];
+        for (old, new) in cases {
+            let mut replayed = old.clone();
+            for event in diff(&old, &new, "", "obs-1") {
+                crate::replay::apply_event(&mut replayed, event);
+            }
+            assert_eq!(replayed, new, "diff of {} and {}", old, new);
+        }
+    }
+
+    #[test]
+    fn test_array_move_keeps_number_text() {
+        // Elements equal as canonical JSON but not as values are not moved
+        // into each other's place
+        for (old, new) in [(r#"[1.0, "x"]"#, r#"["x", 1]"#), ("[12345678901234567890123, 5]", "[5, 12345678901234567890124]")] {
+            let old: Value = serde_json::from_str(old).unwrap();
+            let new: Value = serde_json::from_str(new).unwrap();
+            let mut replayed = old.clone();
+            for event in diff(&old, &new, "", "obs-1") {
+                crate::replay::apply_event(&mut replayed, event);
+            }
+            assert_eq!(replayed, new);
+            assert_eq!(serde_json::to_string(&replayed).unwrap(), serde_json::to_string(&new).unwrap());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "preserve-order")]
+    fn test_key_order_change() {
+        let old: Value = serde_json::from_str(r#"{"a": 1, "b": {"x": 1, "y": 2}, "c": [{"p": 1, "q": 2}]}"#).unwrap();
+
+        // Reordered keys replace their object, so replaying gives the new order
+        let new: Value = serde_json::from_str(r#"{"a": 1, "b": {"y": 2, "x": 1}, "c": [{"q": 2, "p": 1}]}"#).unwrap();
+        let result = diff(&old, &new, "", "obs-1");
+        assert_eq!(result.len(), 2);
+        let mut replayed = old.clone();
+        for event in result {
+            crate::replay::apply_event(&mut replayed, event);
+        }
+        assert_eq!(serde_json::to_string(&replayed).unwrap(), serde_json::to_string(&new).unwrap());
+
+        // Keys added at the end and removed anywhere keep the rest in order
+        let new: Value = serde_json::from_str(r#"{"b": {"x": 1, "y": 2}, "c": [{"p": 1, "q": 2}], "d": 4}"#).unwrap();
+        let result = diff(&old, &new, "", "obs-1");
+        assert!(matches!(&result[..], [Event::Remove { path: removed, .. }, Event::Add { path: added, .. }]
+            if removed == "/a" && added == "/d"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "preserve-order"))]
+    fn test_sorted_keys_add_in_place() {
+        // Keys are kept sorted, so one that sorts first is still just an add
+        let old = json!({"b": 1, "c": 2});
+        let new = json!({"a": 0, "b": 1, "c": 2});
+        let result = diff(&old, &new, "", "obs-1");
+
+        assert!(matches!(&result[..], [Event::Add { path, value, .. }] if path == "/a" && value == &json!(0)));
+    }
+
     #[test]
     fn test_escape_sequences_in_keys() {
         let old = json!({});
@@ -47,9 +47,9 @@
 //! - config/serde_value: Similar issue
 //! - json5: Relaxed JSON syntax, not diagnostic-focused
 //! - miette: a diagnostic library for Rust. It includes a series of
-//! traits/protocols that allow you to hook into its error reporting facilities,
-//! and even write your own error reports. This is better than my home built
-//! Diagnostic struct, but does not help me with deserialization.
+//!   traits/protocols that allow you to hook into its error reporting facilities,
+//!   and even write your own error reports. This is better than my home built
+//!   Diagnostic struct, but does not help me with deserialization.
 //!
 //! Found no library that handles both array deserialization and rich diagnostic collection.
 //! This could probably be automated or turned into a library, but for a simple format it was
@@ -60,21 +60,27 @@
 //!
 //! EventDeserializer wraps Event and collects diagnostics during parsing. It implements
 //! Deserialize with a custom Visitor that validates each array position and populates the
-//! diagnostics vec instead of returning errors. The calling code (reader.rs) attaches
-//! location information (filename, line number) after deserialization.
+//! diagnostics vec instead of returning errors. It also records which array element the
+//! diagnostics are about. The calling code (reader.rs) attaches location information
+//! (filename, line number) after deserialization, and uses `element_spans` to turn the
+//! element index into a column and a caret under the offending element.
 
-use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
-use serde_json::Value;
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, SeqAccess, Visitor};
+use serde_json::{Map, Value};
 use std::fmt;
+use std::ops::Range;
 use chrono::{DateTime, Utc};
 
 use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticLevel};
-use crate::events::Event;
+use crate::events::{Event, StateHash};
 
 #[derive(Debug, Default)]
 pub struct EventDeserializer {
     pub event: Option<Event>,
     pub diagnostics: Vec<Diagnostic>,
+    /// Position in the event array of the element the diagnostics are about.
+    /// `None` when the problem is the array as a whole, like a wrong field count.
+    pub element: Option<usize>,
 }
 
 impl EventDeserializer {
@@ -82,9 +88,196 @@ impl EventDeserializer {
         Self::default()
     }
 
-    fn add_diagnostic(&mut self, level: DiagnosticLevel, code: DiagnosticCode, message: String) {
+    fn add_diagnostic(
+        &mut self,
+        element: Option<usize>,
+        level: DiagnosticLevel,
+        code: DiagnosticCode,
+        message: String,
+    ) {
+        self.element = element;
         self.diagnostics.push(Diagnostic::new(level, code, message));
     }
+
+    fn add_hash_diagnostic(&mut self, element: usize) {
+        self.add_diagnostic(
+            Some(element),
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::WrongFieldType,
+            "I expected the hash to be an object with string \"state\" and \"chain\" fields.".to_string(),
+        );
+    }
+}
+
+fn parse_hash(element: &Value) -> Option<StateHash> {
+    serde_json::from_value(element.clone()).ok()
+}
+
+/// Just enough of an observe or snapshot event to know which observation it
+/// starts: its id, timestamp, metadata, and for an observe, its change count.
+/// A snapshot's object is skipped with `IgnoredAny`, so it is scanned but never
+/// built. Used to find an observation by timestamp before replaying anything,
+/// and to list observations without replaying them.
+#[derive(Debug)]
+pub struct EventHead {
+    pub is_snapshot: bool,
+    pub observation_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Declared change count of an observe; 0 for a snapshot.
+    pub change_count: usize,
+    pub metadata: Option<Map<String, Value>>,
+}
+
+impl EventHead {
+    /// Cheap check on the raw line so delta events, usually the bulk of an
+    /// archive, aren't parsed at all.
+    pub fn is_candidate(line: &str) -> bool {
+        let rest = line.trim_start();
+        let Some(rest) = rest.strip_prefix('[') else {
+            return false;
+        };
+        let rest = rest.trim_start();
+        rest.starts_with("\"observe\"") || rest.starts_with("\"snapshot\"")
+    }
+}
+
+impl<'de> Deserialize<'de> for EventHead {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(EventHeadVisitor)
+    }
+}
+
+struct EventHeadVisitor;
+
+impl<'de> Visitor<'de> for EventHeadVisitor {
+    type Value = EventHead;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an observe or snapshot event")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let event_type: String = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let is_snapshot = match event_type.as_str() {
+            "observe" => false,
+            "snapshot" => true,
+            other => return Err(de::Error::unknown_variant(other, &["observe", "snapshot"])),
+        };
+        let observation_id: String = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let timestamp: String = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let timestamp = timestamp.parse::<DateTime<Utc>>().map_err(de::Error::custom)?;
+        // The fourth element is an observe's change count or a snapshot's
+        // object; metadata and a hash follow in the same places for both
+        let change_count = if is_snapshot {
+            seq.next_element::<IgnoredAny>()?
+                .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+            0
+        } else {
+            seq.next_element::<usize>()?
+                .ok_or_else(|| de::Error::invalid_length(3, &self))?
+        };
+        let metadata = seq.next_element::<Option<Map<String, Value>>>()?;
+        let mut length = if metadata.is_some() { 5 } else { 4 };
+
+        // Same shape rules as EventVisitor, so both agree on what counts
+        while seq.next_element::<IgnoredAny>()?.is_some() {
+            length += 1;
+        }
+        let valid = match &metadata {
+            None => length == 4,
+            Some(Some(_)) => length == 5 || length == 6,
+            Some(None) => length == 6,
+        };
+        if !valid {
+            return Err(de::Error::invalid_length(length, &self));
+        }
+        let metadata = metadata.flatten();
+
+        Ok(EventHead {
+            is_snapshot,
+            observation_id,
+            timestamp,
+            change_count,
+            metadata,
+        })
+    }
+}
+
+/// Byte ranges of the top-level elements of a JSON array written on one line.
+///
+/// serde_json hands the visitor parsed `Value`s, which have forgotten where they
+/// came from, so this rescans the raw line to map an element index back to a
+/// column. It only tracks strings and nesting, which is all it takes to find
+/// the commas that separate elements. Returns an empty vec if the line isn't
+/// an array.
+pub fn element_spans(line: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start: Option<usize> = None;
+    let mut end = 0;
+
+    for (i, c) in line.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            end = i + c.len_utf8();
+            continue;
+        }
+
+        match c {
+            '[' if depth == 0 => depth = 1,
+            ']' if depth == 1 => {
+                if let Some(s) = start.take() {
+                    spans.push(s..end);
+                }
+                return spans;
+            }
+            ',' if depth == 1 => {
+                if let Some(s) = start.take() {
+                    spans.push(s..end);
+                }
+            }
+            c if c.is_whitespace() => {}
+            _ if depth == 0 => return Vec::new(),
+            _ => {
+                if start.is_none() {
+                    start = Some(i);
+                }
+                match c {
+                    '"' => in_string = true,
+                    '[' | '{' => depth += 1,
+                    ']' | '}' => depth -= 1,
+                    _ => {}
+                }
+                end = i + c.len_utf8();
+            }
+        }
+    }
+
+    // Unterminated array: report what we saw so far.
+    if let Some(s) = start {
+        spans.push(s..end);
+    }
+    spans
 }
 
 impl<'de> Deserialize<'de> for EventDeserializer {
@@ -127,6 +320,7 @@ impl<'de> Visitor<'de> for EventVisitor {
 
         if elements.is_empty() {
             self.deserializer.add_diagnostic(
+                None,
                 DiagnosticLevel::Fatal,
                 DiagnosticCode::WrongFieldCount,
                 "I found an empty array, but events must have at least a string type field as first element.".to_string(),
@@ -138,6 +332,7 @@ impl<'de> Visitor<'de> for EventVisitor {
             Some(t) => t,
             None => {
                 self.deserializer.add_diagnostic(
+                    Some(0),
                     DiagnosticLevel::Fatal,
                     DiagnosticCode::WrongFieldType,
                     "I expected the first element of an event to be a string event type.".to_string(),
@@ -148,11 +343,15 @@ impl<'de> Visitor<'de> for EventVisitor {
 
         match event_type {
             "observe" => {
-                if elements.len() != 4 {
+                if !(4..=6).contains(&elements.len()) {
                     self.deserializer.add_diagnostic(
+                        None,
                         DiagnosticLevel::Fatal,
                         DiagnosticCode::WrongFieldCount,
-                        format!("I expected an observe event to have 4 fields, but found {}.", elements.len()),
+                        format!(
+                            "I expected an observe event to have 4 fields, 5 with metadata, or 6 with a hash, but found {}.",
+                            elements.len()
+                        ),
                     );
                     return Ok(self.deserializer);
                 }
@@ -161,6 +360,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(1),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the observation ID to be a string.".to_string(),
@@ -174,8 +374,9 @@ impl<'de> Visitor<'de> for EventVisitor {
                         Ok(dt) => dt,
                         Err(_) => {
                             self.deserializer.add_diagnostic(
+                                Some(2),
                                 DiagnosticLevel::Fatal,
-                                DiagnosticCode::WrongFieldType,
+                                DiagnosticCode::InvalidTimestamp,
                                 "I expected the timestamp to be a valid ISO-8601 datetime string.".to_string(),
                             );
                             return Ok(self.deserializer);
@@ -183,6 +384,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     },
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(2),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the timestamp to be a string.".to_string(),
@@ -195,24 +397,55 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(n) => n as usize,
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(3),
                             DiagnosticLevel::Fatal,
-                            DiagnosticCode::WrongFieldType,
+                            DiagnosticCode::InvalidChangeCount,
                             "I expected the change count to be a non-negative integer.".to_string(),
                         );
                         return Ok(self.deserializer);
                     }
                 };
 
+                let metadata = match elements.get(4) {
+                    None => None,
+                    Some(Value::Object(metadata)) => Some(metadata.clone()),
+                    // Holds the place of absent metadata when a hash follows
+                    Some(Value::Null) if elements.len() == 6 => None,
+                    Some(_) => {
+                        self.deserializer.add_diagnostic(
+                            Some(4),
+                            DiagnosticLevel::Fatal,
+                            DiagnosticCode::WrongFieldType,
+                            "I expected the observation metadata to be an object.".to_string(),
+                        );
+                        return Ok(self.deserializer);
+                    }
+                };
+
+                let hash = match elements.get(5) {
+                    None => None,
+                    Some(element) => match parse_hash(element) {
+                        Some(hash) => Some(hash),
+                        None => {
+                            self.deserializer.add_hash_diagnostic(5);
+                            return Ok(self.deserializer);
+                        }
+                    },
+                };
+
                 self.deserializer.event = Some(Event::Observe {
                     observation_id: id,
                     timestamp,
                     change_count,
+                    metadata,
+                    hash,
                 });
             }
 
             "add" => {
                 if elements.len() != 4 {
                     self.deserializer.add_diagnostic(
+                        None,
                         DiagnosticLevel::Fatal,
                         DiagnosticCode::WrongFieldCount,
                         format!("I expected an add event to have 4 fields, but found {}.", elements.len()),
@@ -224,6 +457,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(1),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the path to be a string.".to_string(),
@@ -238,6 +472,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(3),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the observation ID to be a string.".to_string(),
@@ -256,6 +491,7 @@ impl<'de> Visitor<'de> for EventVisitor {
             "change" => {
                 if elements.len() != 4 {
                     self.deserializer.add_diagnostic(
+                        None,
                         DiagnosticLevel::Fatal,
                         DiagnosticCode::WrongFieldCount,
                         format!("I expected a change event to have 4 fields, but found {}.", elements.len()),
@@ -267,6 +503,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(1),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the path to be a string.".to_string(),
@@ -281,6 +518,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(3),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the observation ID to be a string.".to_string(),
@@ -299,6 +537,7 @@ impl<'de> Visitor<'de> for EventVisitor {
             "remove" => {
                 if elements.len() != 3 {
                     self.deserializer.add_diagnostic(
+                        None,
                         DiagnosticLevel::Fatal,
                         DiagnosticCode::WrongFieldCount,
                         format!("I expected a remove event to have 3 fields, but found {}.", elements.len()),
@@ -310,6 +549,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(1),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the path to be a string.".to_string(),
@@ -322,6 +562,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(2),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the observation ID to be a string.".to_string(),
@@ -339,6 +580,7 @@ impl<'de> Visitor<'de> for EventVisitor {
             "move" => {
                 if elements.len() != 4 {
                     self.deserializer.add_diagnostic(
+                        None,
                         DiagnosticLevel::Fatal,
                         DiagnosticCode::WrongFieldCount,
                         format!("I expected a move event to have 4 fields, but found {}.", elements.len()),
@@ -350,6 +592,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(1),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the path to be a string.".to_string(),
@@ -360,10 +603,11 @@ impl<'de> Visitor<'de> for EventVisitor {
 
                 let moves = match self.parse_moves(&elements[2]) {
                     Ok(moves) => moves,
-                    Err(err_msg) => {
+                    Err((code, err_msg)) => {
                         self.deserializer.add_diagnostic(
+                            Some(2),
                             DiagnosticLevel::Fatal,
-                            DiagnosticCode::WrongFieldType,
+                            code,
                             err_msg,
                         );
                         return Ok(self.deserializer);
@@ -374,6 +618,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(3),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the observation ID to be a string.".to_string(),
@@ -390,11 +635,15 @@ impl<'de> Visitor<'de> for EventVisitor {
             }
 
             "snapshot" => {
-                if elements.len() != 4 {
+                if !(4..=6).contains(&elements.len()) {
                     self.deserializer.add_diagnostic(
+                        None,
                         DiagnosticLevel::Fatal,
                         DiagnosticCode::WrongFieldCount,
-                        format!("I expected a snapshot event to have 4 fields, but found {}.", elements.len()),
+                        format!(
+                            "I expected a snapshot event to have 4 fields, 5 with metadata, or 6 with a hash, but found {}.",
+                            elements.len()
+                        ),
                     );
                     return Ok(self.deserializer);
                 }
@@ -403,6 +652,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     Some(s) => s.to_string(),
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(1),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the observation ID to be a string.".to_string(),
@@ -416,8 +666,9 @@ impl<'de> Visitor<'de> for EventVisitor {
                         Ok(dt) => dt,
                         Err(_) => {
                             self.deserializer.add_diagnostic(
+                                Some(2),
                                 DiagnosticLevel::Fatal,
-                                DiagnosticCode::WrongFieldType,
+                                DiagnosticCode::InvalidTimestamp,
                                 "I expected the timestamp to be a valid ISO-8601 datetime string.".to_string(),
                             );
                             return Ok(self.deserializer);
@@ -425,6 +676,7 @@ impl<'de> Visitor<'de> for EventVisitor {
                     },
                     None => {
                         self.deserializer.add_diagnostic(
+                            Some(2),
                             DiagnosticLevel::Fatal,
                             DiagnosticCode::WrongFieldType,
                             "I expected the timestamp to be a string.".to_string(),
@@ -435,15 +687,45 @@ impl<'de> Visitor<'de> for EventVisitor {
 
                 let object = elements[3].clone();
 
+                let metadata = match elements.get(4) {
+                    None => None,
+                    Some(Value::Object(metadata)) => Some(metadata.clone()),
+                    // Holds the place of absent metadata when a hash follows
+                    Some(Value::Null) if elements.len() == 6 => None,
+                    Some(_) => {
+                        self.deserializer.add_diagnostic(
+                            Some(4),
+                            DiagnosticLevel::Fatal,
+                            DiagnosticCode::WrongFieldType,
+                            "I expected the observation metadata to be an object.".to_string(),
+                        );
+                        return Ok(self.deserializer);
+                    }
+                };
+
+                let hash = match elements.get(5) {
+                    None => None,
+                    Some(element) => match parse_hash(element) {
+                        Some(hash) => Some(hash),
+                        None => {
+                            self.deserializer.add_hash_diagnostic(5);
+                            return Ok(self.deserializer);
+                        }
+                    },
+                };
+
                 self.deserializer.event = Some(Event::Snapshot {
                     observation_id,
                     timestamp,
                     object,
+                    hash,
+                    metadata,
                 });
             }
 
             _ => {
                 self.deserializer.add_diagnostic(
+                    Some(0),
                     DiagnosticLevel::Warning,
                     DiagnosticCode::UnknownEventType,
                     format!("I found an unknown event type: '{}'", event_type),
@@ -456,11 +738,14 @@ impl<'de> Visitor<'de> for EventVisitor {
 }
 
 impl EventVisitor {
-    fn parse_moves(&mut self, moves_value: &Value) -> Result<Vec<(usize, usize)>, String> {
+    fn parse_moves(&mut self, moves_value: &Value) -> Result<Vec<(usize, usize)>, (DiagnosticCode, String)> {
         let moves_array = match moves_value.as_array() {
             Some(arr) => arr,
             None => {
-                return Err("I expected the moves to be an array of [from, to] pairs.".to_string());
+                return Err((
+                    DiagnosticCode::WrongFieldType,
+                    "I expected the moves to be an array of [from, to] pairs.".to_string(),
+                ));
             }
         };
 
@@ -469,21 +754,30 @@ impl EventVisitor {
             let pair = match move_pair.as_array() {
                 Some(p) if p.len() == 2 => p,
                 _ => {
-                    return Err("I expected each move to be a [from, to] pair.".to_string());
+                    return Err((
+                        DiagnosticCode::WrongFieldType,
+                        "I expected each move to be a [from, to] pair.".to_string(),
+                    ));
                 }
             };
 
             let from_idx = match pair[0].as_u64() {
                 Some(i) => i as usize,
                 None => {
-                    return Err("I expected the 'from' index to be a non-negative integer.".to_string());
+                    return Err((
+                        DiagnosticCode::InvalidMoveIndex,
+                        "I expected the 'from' index to be a non-negative integer.".to_string(),
+                    ));
                 }
             };
 
             let to_idx = match pair[1].as_u64() {
                 Some(i) => i as usize,
                 None => {
-                    return Err("I expected the 'to' index to be a non-negative integer.".to_string());
+                    return Err((
+                        DiagnosticCode::InvalidMoveIndex,
+                        "I expected the 'to' index to be a non-negative integer.".to_string(),
+                    ));
                 }
             };
 
@@ -509,11 +803,74 @@ mod tests {
         assert!(deserializer.diagnostics.is_empty());
         assert!(matches!(
             deserializer.event,
-            Some(Event::Observe { observation_id, timestamp: _, change_count })
+            Some(Event::Observe { observation_id, timestamp: _, change_count, metadata: None, hash: None })
             if observation_id == "obs-1" && change_count == 1
         ));
     }
 
+    #[test]
+    fn test_deserialize_observe_event_with_metadata() {
+        let json = json!(["observe", "obs-1", "2025-01-01T00:00:00Z", 1, {"file": "data.json"}]);
+        let deserializer: EventDeserializer = serde_json::from_value(json).unwrap();
+        assert!(deserializer.diagnostics.is_empty());
+        assert!(matches!(
+            deserializer.event,
+            Some(Event::Observe { metadata: Some(metadata), .. })
+            if metadata.get("file") == Some(&json!("data.json"))
+        ));
+
+        let json = json!(["observe", "obs-1", "2025-01-01T00:00:00Z", 1, null, {"state": "sha256:aa", "chain": "sha256:bb"}]);
+        let deserializer: EventDeserializer = serde_json::from_value(json).unwrap();
+        assert!(deserializer.diagnostics.is_empty());
+        assert!(matches!(
+            deserializer.event,
+            Some(Event::Observe { metadata: None, hash: Some(hash), .. })
+            if hash.state == "sha256:aa" && hash.chain == "sha256:bb"
+        ));
+
+        // null only holds the metadata's place in front of a hash
+        let json = json!(["observe", "obs-1", "2025-01-01T00:00:00Z", 1, null]);
+        let deserializer: EventDeserializer = serde_json::from_value(json).unwrap();
+        assert_eq!(deserializer.diagnostics[0].code, DiagnosticCode::WrongFieldType);
+
+        let json = json!(["observe", "obs-1", "2025-01-01T00:00:00Z", 1, "data.json"]);
+        let deserializer: EventDeserializer = serde_json::from_value(json).unwrap();
+        assert_eq!(deserializer.diagnostics.len(), 1);
+        assert_eq!(deserializer.diagnostics[0].code, DiagnosticCode::WrongFieldType);
+        assert!(deserializer.event.is_none());
+    }
+
+    #[test]
+    fn test_deserialize_snapshot_event_trailing_fields() {
+        // Metadata and hash take the same places as on an observe event
+        let json = json!(["snapshot", "obs-1", "2025-01-01T00:00:00Z", {"count": 1}, {"file": "data.json"}]);
+        let deserializer: EventDeserializer = serde_json::from_value(json.clone()).unwrap();
+        assert!(deserializer.diagnostics.is_empty());
+        assert!(matches!(
+            deserializer.event,
+            Some(Event::Snapshot { metadata: Some(metadata), hash: None, .. })
+            if metadata.get("file") == Some(&json!("data.json"))
+        ));
+        let head: EventHead = serde_json::from_value(json).unwrap();
+        assert!(head.is_snapshot);
+        assert_eq!(head.metadata.unwrap().get("file"), Some(&json!("data.json")));
+
+        let json = json!(["snapshot", "obs-1", "2025-01-01T00:00:00Z", {"count": 1}, null, {"state": "sha256:aa", "chain": "sha256:bb"}]);
+        let deserializer: EventDeserializer = serde_json::from_value(json.clone()).unwrap();
+        assert!(deserializer.diagnostics.is_empty());
+        assert!(matches!(
+            deserializer.event,
+            Some(Event::Snapshot { metadata: None, hash: Some(hash), .. }) if hash.chain == "sha256:bb"
+        ));
+        let head: EventHead = serde_json::from_value(json).unwrap();
+        assert!(head.metadata.is_none());
+
+        let json = json!(["snapshot", "obs-1", "2025-01-01T00:00:00Z", {"count": 1}, null]);
+        let deserializer: EventDeserializer = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(deserializer.diagnostics[0].code, DiagnosticCode::WrongFieldType);
+        assert!(serde_json::from_value::<EventHead>(json).is_err());
+    }
+
     #[test]
     fn test_deserialize_add_event() {
         let json = json!(["add", "/count", 42, "obs-1"]);
@@ -567,4 +924,29 @@ mod tests {
             if path == "/items" && moves == vec![(0, 2), (1, 0)] && observation_id == "obs-1"
         ));
     }
+
+    #[test]
+    fn test_diagnostic_records_element() {
+        let json = json!(["add", 42, 1, "obs-1"]);
+        let deserializer: EventDeserializer = serde_json::from_value(json).unwrap();
+        assert_eq!(deserializer.diagnostics[0].code, DiagnosticCode::WrongFieldType);
+        assert_eq!(deserializer.element, Some(1));
+
+        let json = json!(["remove", "/count"]);
+        let deserializer: EventDeserializer = serde_json::from_value(json).unwrap();
+        assert_eq!(deserializer.element, None);
+    }
+
+    #[test]
+    fn test_element_spans() {
+        let line = r#"["add", "/a,b", {"x": [1, "]"]}, "obs-1"]"#;
+        let elements: Vec<&str> = element_spans(line).into_iter().map(|r| &line[r]).collect();
+        assert_eq!(elements, vec![r#""add""#, r#""/a,b""#, r#"{"x": [1, "]"]}"#, r#""obs-1""#]);
+
+        let line = r#"[ "say \"hi\"" , 1 ]"#;
+        let elements: Vec<&str> = element_spans(line).into_iter().map(|r| &line[r]).collect();
+        assert_eq!(elements, vec![r#""say \"hi\"""#, "1"]);
+
+        assert!(element_spans(r#"{"type": "header"}"#).is_empty());
+    }
 }
\ No newline at end of file
@@ -20,9 +20,11 @@
 //
 
 use chrono::{DateTime, Utc};
-use serde::de::{self, SeqAccess, Visitor};
+use serde::de::{self, IgnoredAny, SeqAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use serde_json::Value;
+use serde_json::{Map, Value};
+
+use crate::format::FormatProfile;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Header {
@@ -41,6 +43,19 @@ pub struct Header {
     pub initial: Value,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<Value>,
+    /// Set for a collection archive, which tracks a directory of JSON files.
+    /// The state is then an object keyed by file name, so adding or removing
+    /// a file shows up as an add or remove of `/<name>`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub collection: bool,
+    /// State hash of `initial`, set when the archive hashes its observations.
+    /// The hash chain starts from it, and appends keep hashing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// How the file `initial` was read from was laid out, when archived with
+    /// `--keep-format`. Observations record theirs in their metadata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<FormatProfile>,
 }
 
 impl Header {
@@ -52,16 +67,69 @@ impl Header {
             source,
             initial,
             metadata: None,
+            collection: false,
+            hash: None,
+            format: None,
         }
     }
 }
 
+/// A header read without its initial state, for commands like `info` that only
+/// need the metadata. `initial` must still be there, but it is skipped with
+/// `IgnoredAny`, so a huge initial state is scanned and never built.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeaderSummary {
+    #[serde(rename = "type")]
+    pub file_type: String,
+    pub version: u32,
+    pub created: DateTime<Utc>,
+    pub source: Option<String>,
+    pub metadata: Option<Value>,
+    #[serde(default)]
+    pub collection: bool,
+    #[serde(default)]
+    pub format: Option<FormatProfile>,
+    #[serde(rename = "initial")]
+    _initial: IgnoredAny,
+}
+
+impl From<&Header> for HeaderSummary {
+    fn from(header: &Header) -> Self {
+        Self {
+            file_type: header.file_type.clone(),
+            version: header.version,
+            created: header.created,
+            source: header.source.clone(),
+            metadata: header.metadata.clone(),
+            collection: header.collection,
+            format: header.format.clone(),
+            _initial: IgnoredAny,
+        }
+    }
+}
+
+/// Content hashes an observe or snapshot event can carry: the hash of the
+/// state the observation leaves behind, and a hash linking it to the hashed
+/// observation before it. See the `hash` module for how they are computed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateHash {
+    pub state: String,
+    pub chain: String,
+}
+
 #[derive(Debug, Clone)]
 pub enum Event {
     Observe {
         observation_id: String,
         timestamp: DateTime<Utc>,
         change_count: usize,
+        /// Where the observation came from: the input file, the command that
+        /// produced it, or anything passed with `--meta`. Written as an
+        /// optional fifth element, so older archives still read.
+        metadata: Option<Map<String, Value>>,
+        /// Written as an optional sixth element, after a `null` fifth one if
+        /// there is no metadata.
+        hash: Option<StateHash>,
     },
     Add {
         path: String,
@@ -86,6 +154,12 @@ pub enum Event {
         observation_id: String,
         timestamp: DateTime<Utc>,
         object: Value,
+        /// Metadata of the observation a snapshot stands in for, as on an
+        /// observe event. Written as an optional fifth element.
+        metadata: Option<Map<String, Value>>,
+        /// Written as an optional sixth element, after a `null` fifth one if
+        /// there is no metadata.
+        hash: Option<StateHash>,
     },
 }
 
@@ -102,12 +176,25 @@ impl Serialize for Event {
                 observation_id,
                 timestamp,
                 change_count,
+                metadata,
+                hash,
             } => {
-                let mut seq = serializer.serialize_seq(Some(4))?;
+                let length = match (metadata, hash) {
+                    (_, Some(_)) => 6,
+                    (Some(_), None) => 5,
+                    (None, None) => 4,
+                };
+                let mut seq = serializer.serialize_seq(Some(length))?;
                 seq.serialize_element("observe")?;
                 seq.serialize_element(observation_id)?;
                 seq.serialize_element(timestamp)?;
                 seq.serialize_element(change_count)?;
+                if metadata.is_some() || hash.is_some() {
+                    seq.serialize_element(metadata)?;
+                }
+                if let Some(hash) = hash {
+                    seq.serialize_element(hash)?;
+                }
                 seq.end()
             }
             Event::Add {
@@ -160,12 +247,25 @@ impl Serialize for Event {
                 observation_id,
                 timestamp,
                 object,
+                metadata,
+                hash,
             } => {
-                let mut seq = serializer.serialize_seq(Some(4))?;
+                let length = match (metadata, hash) {
+                    (_, Some(_)) => 6,
+                    (Some(_), None) => 5,
+                    (None, None) => 4,
+                };
+                let mut seq = serializer.serialize_seq(Some(length))?;
                 seq.serialize_element("snapshot")?;
                 seq.serialize_element(observation_id)?;
                 seq.serialize_element(timestamp)?;
                 seq.serialize_element(object)?;
+                if metadata.is_some() || hash.is_some() {
+                    seq.serialize_element(metadata)?;
+                }
+                if let Some(hash) = hash {
+                    seq.serialize_element(hash)?;
+                }
                 seq.end()
             }
         }
@@ -200,10 +300,14 @@ impl<'de> Visitor<'de> for EventVisitor {
                 let change_count: usize = seq
                     .next_element()?
                     .ok_or_else(|| de::Error::missing_field("change_count"))?;
+                let metadata: Option<Map<String, Value>> = seq.next_element()?.flatten();
+                let hash: Option<StateHash> = seq.next_element()?;
                 Ok(Event::Observe {
                     observation_id,
                     timestamp,
                     change_count,
+                    metadata,
+                    hash,
                 })
             }
             "add" => {
@@ -276,10 +380,14 @@ impl<'de> Visitor<'de> for EventVisitor {
                 let object: Value = seq
                     .next_element()?
                     .ok_or_else(|| de::Error::missing_field("object"))?;
+                let metadata: Option<Map<String, Value>> = seq.next_element()?.flatten();
+                let hash: Option<StateHash> = seq.next_element()?;
                 Ok(Event::Snapshot {
                     observation_id,
                     timestamp,
                     object,
+                    hash,
+                    metadata,
                 })
             }
             _ => Err(de::Error::unknown_variant(
@@ -304,6 +412,8 @@ pub struct Observation {
     pub id: String,
     pub timestamp: DateTime<Utc>,
     pub events: Vec<Event>,
+    pub metadata: Option<Map<String, Value>>,
+    pub hash: Option<StateHash>,
 }
 
 impl Observation {
@@ -312,6 +422,8 @@ impl Observation {
             id,
             timestamp,
             events: Vec::new(),
+            metadata: None,
+            hash: None,
         }
     }
 
@@ -324,6 +436,8 @@ impl Observation {
             observation_id: self.id.clone(),
             timestamp: self.timestamp,
             change_count: self.events.len(),
+            metadata: self.metadata,
+            hash: self.hash,
         }];
         result.extend(self.events);
         result
@@ -356,6 +470,8 @@ mod tests {
             observation_id: "obs-1".to_string(),
             timestamp,
             change_count: 2,
+            metadata: None,
+            hash: None,
         };
         let serialized = serde_json::to_string(&observe_event).unwrap();
         let expected_array = json!(["observe", "obs-1", timestamp, 2]);
@@ -364,6 +480,25 @@ mod tests {
             expected_array
         );
 
+        // A hash without metadata keeps the metadata's place with null
+        let hash = StateHash {
+            state: "sha256:aa".to_string(),
+            chain: "sha256:bb".to_string(),
+        };
+        let hashed_event = Event::Observe {
+            observation_id: "obs-1".to_string(),
+            timestamp,
+            change_count: 2,
+            metadata: None,
+            hash: Some(hash),
+        };
+        let serialized = serde_json::to_string(&hashed_event).unwrap();
+        let expected_array = json!(["observe", "obs-1", timestamp, 2, null, {"state": "sha256:aa", "chain": "sha256:bb"}]);
+        assert_eq!(
+            serde_json::from_str::<Value>(&serialized).unwrap(),
+            expected_array
+        );
+
         // Test add event
         let add_event = Event::Add {
             path: "/test".to_string(),
@@ -383,6 +518,25 @@ mod tests {
                 observation_id: "obs-1".to_string(),
                 timestamp,
                 change_count: 2,
+                metadata: None,
+                hash: None,
+            },
+            Event::Observe {
+                observation_id: "obs-2".to_string(),
+                timestamp,
+                change_count: 0,
+                metadata: Some(Map::from_iter([("file".to_string(), json!("data.json"))])),
+                hash: None,
+            },
+            Event::Observe {
+                observation_id: "obs-3".to_string(),
+                timestamp,
+                change_count: 1,
+                metadata: None,
+                hash: Some(StateHash {
+                    state: "sha256:aa".to_string(),
+                    chain: "sha256:bb".to_string(),
+                }),
             },
             Event::Add {
                 path: "/test".to_string(),
@@ -407,6 +561,25 @@ mod tests {
                 observation_id: "snap-1".to_string(),
                 timestamp,
                 object: json!({"test": "state"}),
+                hash: None,
+                metadata: None,
+            },
+            Event::Snapshot {
+                observation_id: "snap-2".to_string(),
+                timestamp,
+                object: json!({"test": "state"}),
+                hash: Some(StateHash {
+                    state: "sha256:aa".to_string(),
+                    chain: "sha256:bb".to_string(),
+                }),
+                metadata: None,
+            },
+            Event::Snapshot {
+                observation_id: "snap-3".to_string(),
+                timestamp,
+                object: json!({"test": "state"}),
+                hash: None,
+                metadata: Some(Map::from_iter([("file".to_string(), json!("data.json"))])),
             },
         ];
 
@@ -0,0 +1,376 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Long-form explanations of diagnostic codes, in the spirit of `rustc --explain`.
+//!
+//! The one-line titles in `diagnostics.rs` are enough once you know the format.
+//! These are for the first time you hit a code: what it means, a tiny archive
+//! that triggers it, and what to do about it. The tests read every example with
+//! `ReadMode::FullValidation` to keep the examples honest.
+
+use crate::diagnostics::DiagnosticCode;
+
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub code: DiagnosticCode,
+    /// What the diagnostic means.
+    pub meaning: &'static str,
+    /// A minimal archive that produces the diagnostic when read. Bytes rather
+    /// than a string so E003 can show invalid UTF-8.
+    pub example: &'static [u8],
+    /// How to fix it.
+    pub fix: &'static str,
+}
+
+macro_rules! header {
+    () => {
+        r#"{"type":"@peoplesgrocers/json-archive","version":1,"created":"2025-01-01T00:00:00Z","initial":{"count":0,"tags":["a","b"]}}"#
+    };
+}
+
+macro_rules! observe {
+    ($count:literal) => {
+        concat!(r#"["observe", "obs-1", "2025-01-01T10:00:00Z", "#, $count, "]")
+    };
+}
+
+pub fn explain(code: DiagnosticCode) -> Explanation {
+    let (meaning, example, fix): (&str, &[u8], &str) = match code {
+        DiagnosticCode::EmptyFile => (
+            "The archive file has no content at all. Every archive needs at least a header line.",
+            b"",
+            "Recreate the archive from the original JSON file, or restore it from a backup. \
+             If the file was emptied while being written, check that the disk wasn't full.",
+        ),
+        DiagnosticCode::MissingHeader => (
+            "The first line of the file isn't a JSON object, so I can't read the archive header. \
+             Comments are allowed anywhere except the first line.",
+            concat!("# archive of data.json\n", header!(), "\n").as_bytes(),
+            "Make sure the header object is the very first line. Move any leading comments below it.",
+        ),
+        DiagnosticCode::InvalidUtf8 => (
+            "The file contains bytes that aren't valid UTF-8. Archives are always UTF-8 text.",
+            b"{\"type\":\"@peoplesgrocers/json-archive\",\"version\":1,\"created\":\"2025-01-01T00:00:00Z\",\"initial\":{\"count\":0}}\n\
+              [\"observe\", \"obs-\xff\", \"2025-01-01T10:00:00Z\", 0]\n",
+            "Find which program wrote the file and make it write UTF-8. If the file was edited by \
+             hand, re-save it with UTF-8 encoding.",
+        ),
+        DiagnosticCode::TruncatedJson => (
+            "A line ends before its JSON value is complete. This is what an interrupted write looks like.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["change", "/count", 1"#, "\n").as_bytes(),
+            "Delete the incomplete line, or restore the archive from a backup. Everything before \
+             the truncated line is still intact.",
+        ),
+        DiagnosticCode::MissingHeaderField => (
+            "The header is a JSON object, but it's missing one of the required fields: \
+             type, version, or created.",
+            concat!(
+                r#"{"type":"@peoplesgrocers/json-archive","version":1,"initial":{"count":0}}"#,
+                "\n"
+            )
+            .as_bytes(),
+            "Add the missing field. `created` is an ISO-8601 timestamp such as \"2025-01-01T00:00:00Z\".",
+        ),
+        DiagnosticCode::UnsupportedVersion => (
+            "The header declares a format version this tool doesn't understand. Only version 1 exists.",
+            concat!(
+                r#"{"type":"@peoplesgrocers/json-archive","version":2,"created":"2025-01-01T00:00:00Z","initial":{"count":0}}"#,
+                "\n"
+            )
+            .as_bytes(),
+            "Use the tool that wrote the archive, or upgrade json-archive.",
+        ),
+        DiagnosticCode::InvalidTimestamp => (
+            "A timestamp isn't a valid ISO-8601 date and time.",
+            concat!(header!(), "\n", r#"["observe", "obs-1", "yesterday", 0]"#, "\n").as_bytes(),
+            "Write timestamps in RFC 3339 form with a timezone, like \"2025-01-15T10:05:00Z\".",
+        ),
+        DiagnosticCode::InvalidInitialState => (
+            "The header has no `initial` field. Every event is a change relative to the initial \
+             state, so without it there is nothing to replay from.",
+            concat!(
+                r#"{"type":"@peoplesgrocers/json-archive","version":1,"created":"2025-01-01T00:00:00Z"}"#,
+                "\n"
+            )
+            .as_bytes(),
+            "Add the contents of the first JSON file you archived as the header's `initial` field.",
+        ),
+        DiagnosticCode::InvalidEventJson => (
+            "A line after the header is neither a comment nor valid JSON.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["change", "/count" 1, "obs-1"]"#, "\n").as_bytes(),
+            "Fix the syntax error on that line. Look for missing commas, quotes, or brackets.",
+        ),
+        DiagnosticCode::UnknownEventType => (
+            "An event's first element names a type I don't know. I skip the line and keep reading.",
+            concat!(header!(), "\n", observe!(0), "\n", r#"["rename", "/count", "/total", "obs-1"]"#, "\n").as_bytes(),
+            "Use one of: observe, add, change, remove, move, snapshot. Unknown events may come from \
+             a newer tool, in which case upgrade json-archive.",
+        ),
+        DiagnosticCode::WrongFieldCount => (
+            "An event array has the wrong number of elements for its type.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["remove", "/count"]"#, "\n").as_bytes(),
+            "Check the event against the file format specification. For example, remove takes \
+             [\"remove\", path, observationId].",
+        ),
+        DiagnosticCode::WrongFieldType => (
+            "An element of an event array has the wrong JSON type, like a number where a path \
+             string belongs.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["change", 42, 1, "obs-1"]"#, "\n").as_bytes(),
+            "Check the event against the file format specification. Paths and observation IDs \
+             are always strings.",
+        ),
+        DiagnosticCode::NonExistentObservationId => (
+            "An add, change, remove, or move event refers to an observation ID that no earlier \
+             observe event introduced.",
+            concat!(header!(), "\n", r#"["change", "/count", 1, "obs-999"]"#, "\n").as_bytes(),
+            "Put an observe event with that ID before the event, or fix the ID so it matches the \
+             observation the event belongs to.",
+        ),
+        DiagnosticCode::DuplicateObservationId => (
+            "Two observations share the same ID, so `state --id` can't tell them apart.",
+            concat!(
+                header!(), "\n",
+                observe!(1), "\n",
+                r#"["change", "/count", 1, "obs-1"]"#, "\n",
+                r#"["observe", "obs-1", "2025-01-02T10:00:00Z", 1]"#, "\n",
+                r#"["change", "/count", 2, "obs-1"]"#, "\n"
+            )
+            .as_bytes(),
+            "Give each observation a unique ID. UUIDs or timestamps work well.",
+        ),
+        DiagnosticCode::ChangeCountMismatch => (
+            "An observe event declared a different number of changes than actually follow it. \
+             Some events may have been lost or added by hand.",
+            concat!(header!(), "\n", observe!(2), "\n", r#"["change", "/count", 1, "obs-1"]"#, "\n").as_bytes(),
+            "Update the count in the observe event to match the events that follow, or find the \
+             missing events.",
+        ),
+        DiagnosticCode::InvalidChangeCount => (
+            "An observe event's change count isn't a non-negative integer.",
+            concat!(header!(), "\n", observe!(-1), "\n").as_bytes(),
+            "Set the change count to the number of add/change/remove/move events that follow.",
+        ),
+        DiagnosticCode::InvalidPointerSyntax => (
+            "A path isn't a valid JSON Pointer. Non-empty pointers must start with '/'.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["change", "count", 1, "obs-1"]"#, "\n").as_bytes(),
+            "Write the path as a JSON Pointer, e.g. \"/count\". Escape '~' as ~0 and '/' inside a \
+             key as ~1.",
+        ),
+        DiagnosticCode::PathNotFound => (
+            "An event refers to a key that doesn't exist in the state at that point.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["remove", "/missing", "obs-1"]"#, "\n").as_bytes(),
+            "Check that the events before this one were applied in order. If the archive was edited \
+             by hand, an earlier event that created the key may be missing.",
+        ),
+        DiagnosticCode::InvalidArrayIndex => (
+            "A path segment that addresses an array isn't a number.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["change", "/tags/first", "z", "obs-1"]"#, "\n").as_bytes(),
+            "Address array elements by zero-based index, e.g. \"/tags/0\".",
+        ),
+        DiagnosticCode::ArrayIndexOutOfBounds => (
+            "A path addresses an array element past the end of the array.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["remove", "/tags/5", "obs-1"]"#, "\n").as_bytes(),
+            "Check that earlier events built the array you expect. Adds may only append at exactly \
+             the array's length.",
+        ),
+        DiagnosticCode::ParentPathNotFound => (
+            "An add or change targets a path whose parent doesn't exist. Events never create \
+             intermediate objects.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["add", "/user/name", "Ada", "obs-1"]"#, "\n").as_bytes(),
+            "Add the parent first, e.g. [\"add\", \"/user\", {\"name\": \"Ada\"}, \"obs-1\"].",
+        ),
+        DiagnosticCode::TypeMismatch => (
+            "A path tries to index into a value that isn't an object or array, like a number.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["add", "/count/total", 1, "obs-1"]"#, "\n").as_bytes(),
+            "Check the path. If the value changed type, use a change event on the parent path.",
+        ),
+        DiagnosticCode::OldValueMismatch => (
+            "An event disagrees with the value already there: an add on a key that already exists, \
+             or a change where there is no value. The event still applies.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["add", "/count", 1, "obs-1"]"#, "\n").as_bytes(),
+            "Use change to replace an existing value, and add to introduce a new one.",
+        ),
+        DiagnosticCode::MoveOnNonArray => (
+            "A move event's path points at something that isn't an array.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["move", "/count", [[0, 1]], "obs-1"]"#, "\n").as_bytes(),
+            "Point the move at the array whose elements are being reordered.",
+        ),
+        DiagnosticCode::MoveIndexOutOfBounds => (
+            "A move refers to an index past the end of the array.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["move", "/tags", [[5, 0]], "obs-1"]"#, "\n").as_bytes(),
+            "Moves are applied one after another, so each index is relative to the array after the \
+             previous move. Recompute the indices, or replace the move with a change of the whole array.",
+        ),
+        DiagnosticCode::InvalidMoveIndex => (
+            "A move's from or to index isn't a non-negative integer.",
+            concat!(header!(), "\n", observe!(1), "\n", r#"["move", "/tags", [[-1, 0]], "obs-1"]"#, "\n").as_bytes(),
+            "Write each move as a [from, to] pair of zero-based indices.",
+        ),
+        DiagnosticCode::SnapshotStateMismatch => (
+            "A checkpoint snapshot doesn't match the state I get by replaying the events before it. \
+             Either the snapshot or the events are wrong.",
+            concat!(
+                header!(), "\n",
+                observe!(1), "\n",
+                r#"["change", "/count", 1, "obs-1"]"#, "\n",
+                r#"["snapshot", "obs-1", "2025-01-01T10:00:00Z", {"count": 2, "tags": ["a", "b"]}]"#, "\n"
+            )
+            .as_bytes(),
+            "Compare `json-archive state --id` for the observation with the snapshot. Readers use the \
+             snapshot from then on, so if the events are right, rewrite the snapshot to match them.",
+        ),
+        DiagnosticCode::SnapshotTimestampOrder => (
+            "A snapshot's timestamp is earlier than an observation before it, though a snapshot \
+             records the state after those events.",
+            concat!(
+                header!(), "\n",
+                r#"["observe", "obs-1", "2025-01-02T10:00:00Z", 1]"#, "\n",
+                r#"["change", "/count", 1, "obs-1"]"#, "\n",
+                r#"["snapshot", "obs-1", "2025-01-01T10:00:00Z", {"count": 1, "tags": ["a", "b"]}]"#, "\n"
+            )
+            .as_bytes(),
+            "Check the clock of the machine that wrote the snapshot, and set its timestamp to that \
+             of the observation it checkpoints.",
+        ),
+        DiagnosticCode::ObservationTimestampOrder => (
+            "An observation is timestamped earlier than one before it in the file. The format allows \
+             this, but each observation's deltas apply to the state of the line before it, not the \
+             state at the time before it, so `state --as-of` and `--index` stop agreeing.",
+            concat!(
+                header!(), "\n",
+                r#"["observe", "obs-1", "2025-01-02T10:00:00Z", 1]"#, "\n",
+                r#"["change", "/count", 1, "obs-1"]"#, "\n",
+                r#"["observe", "obs-2", "2025-01-01T10:00:00Z", 1]"#, "\n",
+                r#"["change", "/count", 2, "obs-2"]"#, "\n"
+            )
+            .as_bytes(),
+            "Run `json-archive normalize` to rewrite the archive in chronological order. If the \
+             order is unexpected, check the clocks of the machines that wrote the archive.",
+        ),
+        DiagnosticCode::StateHashMismatch => (
+            "An observation carries a hash of the state it leaves behind, and replaying the archive \
+             gives a state with a different hash. The events were changed after the hash was written.",
+            concat!(
+                header!(), "\n",
+                r#"["observe", "obs-1", "2025-01-01T10:00:00Z", 1, null, {"state": "sha256:1f0a142dc13cfed6ad61a8eb8b6ccd2ca77f9b7c64dea2aa8455d3b68cb46b92", "chain": "sha256:c11345cc0d3148ad47b3bb6e66b4483448af458ecd80373a9a079c244dc92e65"}]"#, "\n",
+                r#"["change", "/count", 2, "obs-1"]"#, "\n"
+            )
+            .as_bytes(),
+            "Treat the archive as altered from this observation on. Compare it with a copy you \
+             trust, and find out who had write access to it.",
+        ),
+        DiagnosticCode::HashChainBroken => (
+            "A hashed observation's chain hash doesn't follow from the hashed observation before it \
+             (or, for the first one, from the header's initial state). Something between them was \
+             removed, reordered or rewritten.",
+            concat!(
+                header!(), "\n",
+                r#"["observe", "obs-1", "2025-01-01T10:00:00Z", 1, null, {"state": "sha256:1f0a142dc13cfed6ad61a8eb8b6ccd2ca77f9b7c64dea2aa8455d3b68cb46b92", "chain": "sha256:e62a696529d29f4901897ac3f41d854d5933c8eb5e9de6606f3dffcb20945646"}]"#, "\n",
+                r#"["change", "/count", 1, "obs-1"]"#, "\n"
+            )
+            .as_bytes(),
+            "Treat everything before this observation as altered. If you recorded an earlier \
+             chain hash somewhere else, look for it in the archive to find where the history diverges.",
+        ),
+        DiagnosticCode::MissingStateHash => (
+            "An observation has no hash, though observations before it do. Nothing vouches for its \
+             state: it was appended without hashing, or its hash was removed.",
+            concat!(
+                header!(), "\n",
+                r#"["observe", "obs-1", "2025-01-01T10:00:00Z", 1, null, {"state": "sha256:1f0a142dc13cfed6ad61a8eb8b6ccd2ca77f9b7c64dea2aa8455d3b68cb46b92", "chain": "sha256:c11345cc0d3148ad47b3bb6e66b4483448af458ecd80373a9a079c244dc92e65"}]"#, "\n",
+                r#"["change", "/count", 1, "obs-1"]"#, "\n",
+                r#"["observe", "obs-2", "2025-01-01T11:00:00Z", 1]"#, "\n",
+                r#"["change", "/count", 2, "obs-2"]"#, "\n"
+            )
+            .as_bytes(),
+            "Appending to a hashed archive keeps hashing on its own, so check what wrote the \
+             observation. Hashes can't be added afterwards without trusting the state as it is now.",
+        ),
+        DiagnosticCode::FormatNotReproducible => (
+            "A file's original bytes can't be rebuilt. Either an observation records a formatting \
+             profile that couldn't have produced JSON, `state --original` asked for an observation \
+             that has no profile, or `--keep-format` was given a file whose layout json-archive \
+             can't render, such as one with irregular spacing or escaped characters.",
+            concat!(
+                header!(), "\n",
+                r#"["observe", "obs-1", "2025-01-01T10:00:00Z", 1, {"file": "data.json", "format": {"indent": "--", "newline": true}}]"#, "\n",
+                r#"["change", "/count", 1, "obs-1"]"#, "\n"
+            )
+            .as_bytes(),
+            "A profile's indent must be spaces or tabs; fix or remove the `format` entry. For a file \
+             that can't be reproduced, archive it without --keep-format; `state` still prints an \
+             equivalent JSON value.",
+        ),
+    };
+
+    Explanation {
+        code,
+        meaning,
+        example,
+        fix,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{ArchiveReader, ReadMode};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_every_code_has_an_explanation() {
+        for code in DiagnosticCode::ALL {
+            let explanation = explain(code.clone());
+            assert!(!explanation.meaning.is_empty(), "{} has no meaning", code.as_str());
+            assert!(!explanation.fix.is_empty(), "{} has no fix", code.as_str());
+            assert_eq!(DiagnosticCode::from_code(code.as_str()), Some(code));
+        }
+    }
+
+    #[test]
+    fn test_every_example_produces_its_code() -> Result<(), Box<dyn std::error::Error>> {
+        for code in DiagnosticCode::ALL {
+            let explanation = explain(code.clone());
+
+            let mut temp_file = NamedTempFile::new()?;
+            temp_file.write_all(explanation.example)?;
+            temp_file.flush()?;
+
+            let reader = ArchiveReader::new(temp_file.path(), ReadMode::FullValidation)?;
+            let result = reader.read(temp_file.path())?;
+            let produced: Vec<&str> = result
+                .diagnostics
+                .diagnostics()
+                .iter()
+                .map(|d| d.code.as_str())
+                .collect();
+
+            assert!(
+                produced.contains(&code.as_str()),
+                "example for {} produced {:?}",
+                code.as_str(),
+                produced
+            );
+        }
+        Ok(())
+    }
+}
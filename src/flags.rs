@@ -23,6 +23,9 @@ use std::path::PathBuf;
 
 xflags::xflags! {
     cmd json-archive {
+        /// How to report problems on stderr: human (default), json, or sarif
+        optional --diagnostics-format diagnostics_format: String
+
         default cmd create {
             /// Input JSON files in chronological order (first file determines default output name)
             repeated inputs: PathBuf
@@ -33,8 +36,35 @@ xflags::xflags! {
             /// Insert snapshot every N observations (optional)
             optional -s, --snapshot-interval snapshot_interval: usize
 
+            /// Write due snapshots in place of the observation's deltas instead of after them
+            optional --replace-snapshots
+
             /// Source identifier for archive metadata
             optional --source source: String
+
+            /// JSON object to store as the archive's header metadata, when creating it
+            optional --metadata metadata: String
+
+            /// Attach key=value metadata to each new observation (repeatable)
+            repeated --meta meta: String
+
+            /// When appending, skip damaged observations in the archive instead of refusing
+            optional --recover
+
+            /// Record a hash chain over the states, so later edits are detectable.
+            /// Appending to a hashed archive keeps hashing without it
+            optional --hash
+
+            /// Store the initial state and snapshots with object keys in canonical (RFC 8785) order
+            optional --canonicalize
+
+            /// Record each file's formatting, so `state --original` can reproduce it byte for byte.
+            /// Refuses files it couldn't reproduce
+            optional --keep-format
+
+            /// Track every JSON file in this directory as one collection archive.
+            /// Each run adds one observation if the output archive already exists
+            optional --dir dir: PathBuf
         }
 
         cmd info {
@@ -43,6 +73,10 @@ xflags::xflags! {
 
             /// Output format: human-readable (default) or json
             optional --output output: String
+
+            /// List observations from their observe lines without replaying
+            /// states. Much faster on big archives, but leaves out JSON sizes
+            optional --fast
         }
 
         cmd state {
@@ -66,6 +100,222 @@ xflags::xflags! {
 
             /// Get latest state by timestamp (default if no other flags specified)
             optional --latest latest: bool
+
+            /// Skip damaged observations instead of stopping at them
+            optional --recover
+
+            /// In a collection archive, output only this document
+            optional --doc doc: String
+
+            /// Print the observation's file exactly as it was archived with --keep-format
+            optional --original
+
+            /// Print the state on a single line
+            optional --compact
+
+            /// Indent the state by this many spaces (default: 2)
+            optional --indent indent: usize
+
+            /// Print object keys in sorted order
+            optional --sort-keys
+
+            /// Output only the value at this JSON Pointer (e.g. /users/0)
+            optional --pointer pointer: String
+
+            /// Write the state to this file instead of stdout, compressed if the
+            /// extension is .gz, .zlib, .deflate, .zst or .br
+            optional -o, --output output: PathBuf
+        }
+
+        cmd checkout {
+            /// Archive file to restore versions from
+            required file: PathBuf
+
+            /// Write the state after every observation, one file each
+            optional --all
+
+            /// Directory to write the files to, created if it doesn't exist
+            required --dir dir: PathBuf
+
+            /// How to name each file, using {index}, {timestamp} and {id}
+            /// (default: {index}-{timestamp}-{id}.json)
+            optional --template template: String
+        }
+
+        cmd exec {
+            /// Command to run, after --. Its stdout must be one JSON value
+            repeated command: String
+
+            /// Archive to append the output to (created if missing)
+            required --archive archive: PathBuf
+
+            /// Keep running the command at this interval, like 30s, 5m, or 1h
+            optional --every every: String
+
+            /// Insert snapshot every N observations (optional)
+            optional -s, --snapshot-interval snapshot_interval: usize
+
+            /// Write due snapshots in place of the observation's deltas instead of after them
+            optional --replace-snapshots
+
+            /// Source identifier for archive metadata, if the archive is created
+            optional --source source: String
+
+            /// Attach key=value metadata to each new observation (repeatable)
+            repeated --meta meta: String
+
+            /// Skip damaged observations in the archive instead of refusing
+            optional --recover
+
+            /// Record a hash chain over the states, so later edits are detectable
+            optional --hash
+        }
+
+        cmd watch {
+            /// JSON file to watch
+            required file: PathBuf
+
+            /// Archive to append to (defaults to the file + .json.archive, created if missing)
+            optional -o, --output output: PathBuf
+
+            /// How often to check the file for changes, in milliseconds (default 1000)
+            optional --poll-interval poll_interval: u64
+
+            /// How long the file must stay unchanged before it is recorded, in milliseconds (default 500)
+            optional --debounce debounce: u64
+
+            /// Insert snapshot every N observations (optional)
+            optional -s, --snapshot-interval snapshot_interval: usize
+
+            /// Write due snapshots in place of the observation's deltas instead of after them
+            optional --replace-snapshots
+
+            /// Source identifier for archive metadata, if the archive is created
+            optional --source source: String
+
+            /// Attach key=value metadata to each new observation (repeatable)
+            repeated --meta meta: String
+
+            /// Skip damaged observations in the archive instead of refusing
+            optional --recover
+
+            /// Record a hash chain over the states, so later edits are detectable
+            optional --hash
+        }
+
+        cmd merge {
+            /// Archives of the same document to merge, two or more
+            repeated archives: PathBuf
+
+            /// Where to write the merged archive
+            required -o, --output output: PathBuf
+
+            /// Insert snapshot every N observations (optional)
+            optional -s, --snapshot-interval snapshot_interval: usize
+
+            /// Write the merged archive without hashes instead of starting a new hash chain. Needed to merge hashed with unhashed archives
+            optional --drop-hashes
+        }
+
+        cmd meta {
+            /// Print the header metadata as JSON, or one key of it
+            cmd get {
+                /// Archive file to read
+                required file: PathBuf
+
+                /// Top-level key to print instead of the whole object
+                optional key: String
+            }
+
+            /// Replace the header metadata with a JSON object, or remove it with null
+            cmd set {
+                /// Archive file to change
+                required file: PathBuf
+
+                /// The new metadata, as a JSON object
+                required metadata: String
+            }
+
+            /// Merge a JSON object into the header metadata. A null value removes that key
+            cmd merge-patch merge {
+                /// Archive file to change
+                required file: PathBuf
+
+                /// The JSON object to merge in
+                required patch: String
+            }
+        }
+
+        cmd normalize {
+            /// Archive file to put in chronological order
+            required file: PathBuf
+
+            /// Write the result here instead of replacing the archive
+            optional -o, --output output: PathBuf
+
+            /// Insert snapshot every N observations (optional)
+            optional -s, --snapshot-interval snapshot_interval: usize
+
+            /// Write the result without hashes instead of starting a new hash chain
+            optional --drop-hashes
+        }
+
+        cmd redact {
+            /// Archive file to remove paths from
+            required file: PathBuf
+
+            /// JSON pointer to remove from every state; * matches any key or index. Repeat for more paths
+            repeated -p, --path path: String
+
+            /// Write the result here instead of replacing the archive
+            optional -o, --output output: PathBuf
+
+            /// Insert snapshot every N observations (optional)
+            optional -s, --snapshot-interval snapshot_interval: usize
+
+            /// Write the result without hashes instead of starting a new hash chain
+            optional --drop-hashes
+        }
+
+        cmd slice {
+            /// Archive file to cut a time range from
+            required file: PathBuf
+
+            /// Where to write the observations in the range
+            required -o, --output output: PathBuf
+
+            /// Keep observations at or after this timestamp
+            optional --since since: String
+
+            /// Keep observations at or before this timestamp
+            optional --until until: String
+
+            /// Keep observations before this timestamp. Pairs with --since to split an archive in two
+            optional --before before: String
+
+            /// Write the slice without hashes instead of starting a new hash chain
+            optional --drop-hashes
+        }
+
+        cmd validate {
+            /// Archive file to validate
+            required file: PathBuf
+
+            /// Output format: human-readable (default) or json
+            optional --output output: String
+
+            /// Treat warnings as errors
+            optional --strict
+        }
+
+        cmd verify-roundtrip {
+            /// JSON files to check that --keep-format can reproduce byte for byte
+            repeated files: PathBuf
+        }
+
+        cmd explain {
+            /// Diagnostic code to explain, e.g. E051
+            required code: String
         }
     }
 }
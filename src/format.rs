@@ -0,0 +1,187 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Formatting profiles, for reproducing an input file byte for byte.
+//!
+//! An archive stores JSON values, not the text they were read from. Most JSON
+//! files are written by a serializer with a fixed style, though, so the text
+//! can be rebuilt from the value and a few facts about that style: the indent
+//! unit, or none for compact output, and whether the file ends in a newline.
+//! With the `preserve-order` and `arbitrary-precision` features, key order and
+//! number formatting come back from the value itself.
+//!
+//! A profile is only recorded after rendering the value with it gives back the
+//! exact input, so `state --original` never prints something the file didn't
+//! say. Files with irregular whitespace, escaped characters or duplicate keys
+//! don't pass that check.
+
+use serde::{Deserialize, Serialize};
+use serde_json::ser::PrettyFormatter;
+use serde_json::{Map, Serializer, Value};
+
+/// The observation metadata key a profile is recorded under. The initial
+/// state's profile is in the header's `format` field instead.
+pub const METADATA_KEY: &str = "format";
+
+/// How a JSON file was laid out, as far as `render` needs to know.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatProfile {
+    /// One level of indentation, like `"  "` or `"\t"`. `None` for compact
+    /// output on a single line.
+    pub indent: Option<String>,
+    /// Whether the file ends with a newline.
+    pub newline: bool,
+}
+
+/// Where a rendering first differs from the input text, 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mismatch {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl FormatProfile {
+    /// Guess the profile of `text` from its first lines. Only `verify_roundtrip`
+    /// says whether the guess reproduces it.
+    pub fn detect(text: &str) -> FormatProfile {
+        let indent = text
+            .strip_prefix(['{', '['])
+            .and_then(|rest| rest.strip_prefix('\n'))
+            .map(|rest| rest.chars().take_while(|c| *c == ' ' || *c == '\t').collect::<String>())
+            .filter(|indent| !indent.is_empty());
+
+        FormatProfile {
+            indent,
+            newline: text.ends_with('\n'),
+        }
+    }
+
+    /// Render `value` in this profile.
+    pub fn render(&self, value: &Value) -> String {
+        let mut out = match &self.indent {
+            None => serde_json::to_string(value).expect("a JSON value always serializes"),
+            Some(indent) => {
+                let mut buffer = Vec::new();
+                let mut serializer = Serializer::with_formatter(&mut buffer, PrettyFormatter::with_indent(indent.as_bytes()));
+                value.serialize(&mut serializer).expect("a JSON value always serializes");
+                String::from_utf8(buffer).expect("serde_json writes UTF-8")
+            }
+        };
+        if self.newline {
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The profile recorded in an observation's metadata, if there is one.
+    /// A `format` object that isn't a valid profile is an error. `--meta`
+    /// can't write that key, but archives written before it was reserved may
+    /// hold a string there, which is the user's own.
+    pub fn from_metadata(metadata: Option<&Map<String, Value>>) -> Option<Result<FormatProfile, String>> {
+        match metadata?.get(METADATA_KEY)? {
+            value @ Value::Object(_) => Some(
+                serde_json::from_value::<FormatProfile>(value.clone())
+                    .map_err(|e| e.to_string())
+                    .and_then(|profile| profile.validate().map(|()| profile)),
+            ),
+            _ => None,
+        }
+    }
+
+    /// A profile can only have produced JSON if its indent is whitespace.
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.indent {
+            Some(indent) if indent.is_empty() || !indent.chars().all(|c| matches!(c, ' ' | '\t')) => {
+                Err(format!("indent {:?} is not made of spaces and tabs", indent))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Detect the profile of `text` and check that rendering `value`, the JSON
+/// parsed from it, with that profile gives back exactly `text`.
+pub fn verify_roundtrip(text: &str, value: &Value) -> Result<FormatProfile, Mismatch> {
+    let profile = FormatProfile::detect(text);
+    let rendered = profile.render(value);
+    if rendered == text {
+        return Ok(profile);
+    }
+
+    let offset = text
+        .char_indices()
+        .zip(rendered.chars())
+        .find(|((_, expected), actual)| expected != actual)
+        .map(|((offset, _), _)| offset)
+        .unwrap_or_else(|| text.len().min(rendered.len()));
+    let before = &text[..offset];
+    Err(Mismatch {
+        line: before.matches('\n').count() + 1,
+        column: before.chars().rev().take_while(|c| *c != '\n').count() + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_common_styles() {
+        let inputs = [
+            "{\"a\":[true,null],\"b\":1}",
+            "{\n  \"a\": [\n    true,\n    null\n  ],\n  \"b\": 1\n}\n",
+            "{\n\t\"a\": [],\n\t\"b\": 1\n}",
+            "[\n    1,\n    {}\n]\n",
+            "\"just a string\"\n",
+        ];
+        for input in inputs {
+            let value: Value = serde_json::from_str(input).unwrap();
+            let profile = verify_roundtrip(input, &value).unwrap();
+            assert_eq!(profile.render(&value), input);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_reports_first_difference() {
+        let input = "{\n  \"a\": 1,\n  \"b\" : 2\n}\n";
+        let value: Value = serde_json::from_str(input).unwrap();
+        assert_eq!(verify_roundtrip(input, &value), Err(Mismatch { line: 3, column: 6 }));
+
+        // Escapes serde_json wouldn't write don't survive either
+        let input = r#"{"a":"caf\u00e9"}"#;
+        let value: Value = serde_json::from_str(input).unwrap();
+        assert_eq!(verify_roundtrip(input, &value), Err(Mismatch { line: 1, column: 10 }));
+    }
+
+    #[test]
+    fn test_profile_from_metadata() {
+        let metadata = |format: Value| Map::from_iter([(METADATA_KEY.to_string(), format)]);
+
+        let found = FormatProfile::from_metadata(Some(&metadata(serde_json::json!({"indent": "  ", "newline": true}))));
+        assert_eq!(found, Some(Ok(FormatProfile { indent: Some("  ".to_string()), newline: true })));
+
+        let found = FormatProfile::from_metadata(Some(&metadata(serde_json::json!({"indent": "--", "newline": true}))));
+        assert!(matches!(found, Some(Err(_))));
+
+        assert_eq!(FormatProfile::from_metadata(Some(&metadata(serde_json::json!("yaml")))), None);
+        assert_eq!(FormatProfile::from_metadata(None), None);
+    }
+}
@@ -0,0 +1,208 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Content hashes that make an archive tamper-evident.
+//!
+//! An observe or snapshot event can carry the hash of the state its
+//! observation leaves behind, and a chain hash linking it to the hashed
+//! observation before it:
+//!
+//! ```text
+//! state = sha256:<hex of SHA-256 over the canonical JSON of the state>
+//! chain = sha256:<hex of SHA-256 over "<previous chain>\n<state>">
+//! ```
+//!
+//! The chain starts from the state hash of the header's initial state. So
+//! changing any hashed state, the initial state, or the order of hashed
+//! observations breaks every chain hash after it, and someone holding on to
+//! the latest chain hash can tell whether the history leading up to it was
+//! rewritten.
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::canonical::to_canonical_string;
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticLevel};
+use crate::events::StateHash;
+
+const PREFIX: &str = "sha256:";
+
+fn sha256(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    let mut hex = String::with_capacity(PREFIX.len() + digest.len() * 2);
+    hex.push_str(PREFIX);
+    for byte in digest {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+/// Hash of the canonical serialization of `state`.
+pub fn state_hash(state: &Value) -> String {
+    sha256(to_canonical_string(state).as_bytes())
+}
+
+/// The chain hash following `previous_chain` for a state hashing to `state_hash`.
+pub fn chain_hash(previous_chain: &str, state_hash: &str) -> String {
+    sha256(format!("{}\n{}", previous_chain, state_hash).as_bytes())
+}
+
+impl StateHash {
+    /// Hashes for `state`, chained to `previous_chain`.
+    pub fn next(previous_chain: &str, state: &Value) -> StateHash {
+        let state = state_hash(state);
+        let chain = chain_hash(previous_chain, &state);
+        StateHash { state, chain }
+    }
+}
+
+/// Checks the hashes in an archive while it is replayed.
+pub(crate) struct ChainVerifier {
+    chain: String,
+    /// Set once a hashed event has been seen; after that every observation
+    /// should be hashed.
+    started: bool,
+}
+
+impl ChainVerifier {
+    pub(crate) fn new(initial: &Value) -> Self {
+        Self {
+            chain: state_hash(initial),
+            started: false,
+        }
+    }
+
+    /// Check the initial state's hash recorded in the header. Every
+    /// observation after it should be hashed.
+    pub(crate) fn check_initial(&mut self, claimed: &str) -> Option<Diagnostic> {
+        self.started = true;
+        let actual = std::mem::replace(&mut self.chain, claimed.to_string());
+        (actual != claimed).then(|| {
+            Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::StateHashMismatch,
+                format!("The header's initial state hashes to {}, but the header says {}.", actual, claimed),
+            )
+            .with_advice("The initial state was changed after the archive was created.".to_string())
+        })
+    }
+
+    /// Check the hash an event claimed for the `state` its observation ended
+    /// up with. The chain continues from the claimed hash, so one altered
+    /// observation is reported once rather than at every later link.
+    pub(crate) fn check(&mut self, claimed: &StateHash, state: &Value) -> Option<Diagnostic> {
+        self.started = true;
+        let actual = state_hash(state);
+        let expected_chain = chain_hash(&self.chain, &claimed.state);
+        self.chain = claimed.chain.clone();
+
+        if actual != claimed.state {
+            return Some(
+                Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::StateHashMismatch,
+                    format!(
+                        "I replayed this observation and its state hashes to {}, but the archive says {}.",
+                        actual, claimed.state
+                    ),
+                )
+                .with_advice(
+                    "The observation's events, or an earlier observation's, were changed after the hash was written."
+                        .to_string(),
+                ),
+            );
+        }
+        if expected_chain != claimed.chain {
+            return Some(
+                Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::HashChainBroken,
+                    format!(
+                        "I expected the chain hash {} after the previous hashed observation, but the archive says {}.",
+                        expected_chain, claimed.chain
+                    ),
+                )
+                .with_advice(
+                    "A hashed observation before this one was removed, reordered or rewritten, \
+                     or the header's initial state was changed."
+                        .to_string(),
+                ),
+            );
+        }
+        None
+    }
+
+    /// Note an observation without a hash.
+    pub(crate) fn unhashed(&self) -> Option<Diagnostic> {
+        self.started.then(|| {
+            Diagnostic::new(
+                DiagnosticLevel::Warning,
+                DiagnosticCode::MissingStateHash,
+                "I found an observation without a hash after hashed ones.".to_string(),
+            )
+            .with_advice(
+                "Nothing vouches for this observation's state. It was either appended without \
+                 hashing or its hash was removed."
+                    .to_string(),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_state_hash_ignores_key_order() {
+        let a: Value = serde_json::from_str(r#"{"a": 1, "b": [1.0, 2]}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"b": [1, 2], "a": 1}"#).unwrap();
+        assert_eq!(state_hash(&a), state_hash(&b));
+        assert_ne!(state_hash(&a), state_hash(&json!({"a": 2, "b": [1, 2]})));
+
+        // SHA-256 of the empty object, "{}"
+        assert_eq!(
+            state_hash(&json!({})),
+            "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+    }
+
+    #[test]
+    fn test_chain_verifier() {
+        let initial = json!({"count": 0});
+        let first = StateHash::next(&state_hash(&initial), &json!({"count": 1}));
+        let second = StateHash::next(&first.chain, &json!({"count": 2}));
+
+        let mut verifier = ChainVerifier::new(&initial);
+        assert!(verifier.unhashed().is_none());
+        assert!(verifier.check(&first, &json!({"count": 1})).is_none());
+        assert!(verifier.check(&second, &json!({"count": 2})).is_none());
+        assert!(verifier.unhashed().is_some());
+
+        // Skipping a link breaks the chain, a wrong state breaks the state hash
+        let mut verifier = ChainVerifier::new(&initial);
+        let diagnostic = verifier.check(&second, &json!({"count": 2})).unwrap();
+        assert_eq!(diagnostic.code, DiagnosticCode::HashChainBroken);
+        let diagnostic = ChainVerifier::new(&initial).check(&first, &json!({"count": 5})).unwrap();
+        assert_eq!(diagnostic.code, DiagnosticCode::StateHashMismatch);
+    }
+}
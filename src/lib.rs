@@ -20,20 +20,47 @@
 //
 
 pub mod archive;
+pub mod canonical;
+pub mod checkout;
+pub mod collection;
 pub mod detection;
 pub mod diagnostics;
 pub mod diff;
 pub mod event_deserialize;
 pub mod events;
+pub mod explain;
 pub mod flags;
+pub mod format;
+pub mod hash;
+pub mod merge;
+pub mod normalize;
 pub mod pointer;
 pub mod reader;
+pub mod redact;
+pub mod replay;
+pub mod resolve;
+pub mod slice;
 
 pub use archive::{
-    append_to_archive, create_archive_from_files, default_output_filename, ArchiveBuilder, ArchiveWriter,
+    append_collection_to_archive, append_to_archive, create_archive, create_archive_from_files, create_collection_archive,
+    default_output_filename, file_provenance, format_profile, open_append, parse_observation_metadata, update_header, write_output_file,
+    ArchiveBuilder, ArchiveOptions, ArchiveWriter, OpenedArchive, SnapshotMode,
 };
+pub use checkout::{checkout_all, CheckoutSummary, FilenameTemplate};
+pub use collection::read_collection;
 pub use detection::is_json_archive;
-pub use diagnostics::{Diagnostic, DiagnosticCode, DiagnosticCollector, DiagnosticLevel};
-pub use events::{Event, Header, Observation};
+pub use diagnostics::{to_sarif, Diagnostic, DiagnosticCode, DiagnosticCollector, DiagnosticLevel};
+pub use events::{Event, Header, HeaderSummary, Observation};
+pub use explain::{explain, Explanation};
+pub use format::{verify_roundtrip, FormatProfile};
+pub use merge::{merge_archives, MergeSummary};
+pub use normalize::{normalize_archive, NormalizeSummary};
 pub use pointer::JsonPointer;
-pub use reader::{apply_add, apply_change, apply_move, apply_remove, ArchiveReader, ReadMode, ReadResult};
+pub use redact::{redact_archive, PathPattern, RedactSummary};
+pub use reader::{
+    apply_add, apply_change, apply_move, apply_remove, is_observation_snapshot, ArchiveReader, ObservationHead,
+    ObservationHeads, ReadMode, ReadResult,
+};
+pub use replay::{observation_states, states, ObservationMeta, ObservationState, ObservationStates, States};
+pub use resolve::{resolve_state, state_at, ResolvedState, StateSelector};
+pub use slice::{slice_archive, SliceSummary, TimeRange};
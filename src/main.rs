@@ -20,8 +20,9 @@
 //
 
 use json_archive::{
-    append_to_archive, create_archive_from_files, default_output_filename, is_json_archive, Diagnostic,
-    DiagnosticCode, DiagnosticLevel,
+    append_collection_to_archive, append_to_archive, create_archive_from_files, create_collection_archive,
+    default_output_filename, is_json_archive, parse_observation_metadata, ArchiveOptions, Diagnostic, DiagnosticCode,
+    DiagnosticLevel, SnapshotMode, to_sarif,
 };
 use std::path::Path;
 use std::process;
@@ -29,14 +30,29 @@ use std::process;
 mod cmd;
 mod flags;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiagnosticsFormat {
+    Human,
+    Json,
+    Sarif,
+}
+
 fn main() {
     let flags = flags::JsonArchive::from_env_or_exit();
 
-    let diagnostics = run(flags);
+    let format = match flags.diagnostics_format.as_deref() {
+        None | Some("human") => DiagnosticsFormat::Human,
+        Some("json") => DiagnosticsFormat::Json,
+        Some("sarif") => DiagnosticsFormat::Sarif,
+        Some(other) => usage_error(&format!(
+            "unknown diagnostics format: `{}`, expected one of human, json, sarif",
+            other
+        )),
+    };
 
-    for diagnostic in &diagnostics {
-        eprintln!("{}", diagnostic);
-    }
+    let diagnostics = run(flags, format);
+
+    report(format, &diagnostics);
 
     let has_fatal = diagnostics.iter().any(|d| d.is_fatal());
     if has_fatal {
@@ -44,15 +60,70 @@ fn main() {
     }
 }
 
-fn run(flags: flags::JsonArchive) -> Vec<Diagnostic> {
+/// Reject a command line the flag parser accepted but that doesn't make sense,
+/// the same way xflags rejects one it can't parse. This is about how the tool
+/// was invoked, not about any archive, so it isn't a diagnostic.
+pub(crate) fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
+
+fn report(format: DiagnosticsFormat, diagnostics: &[Diagnostic]) {
+    match format {
+        DiagnosticsFormat::Human => {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+        }
+        DiagnosticsFormat::Json => {
+            eprintln!(
+                "{}",
+                serde_json::to_string_pretty(diagnostics).unwrap_or_default()
+            );
+        }
+        DiagnosticsFormat::Sarif => {
+            eprintln!(
+                "{}",
+                serde_json::to_string_pretty(&to_sarif(diagnostics)).unwrap_or_default()
+            );
+        }
+    }
+}
+
+fn run(flags: flags::JsonArchive, format: DiagnosticsFormat) -> Vec<Diagnostic> {
+    // For the commands that keep running and report as they go. Unlike the
+    // final report, an empty one isn't worth a line.
+    let report_now = |diagnostics: &[Diagnostic]| {
+        if !diagnostics.is_empty() {
+            report(format, diagnostics);
+        }
+    };
+
     match flags.subcommand {
         flags::JsonArchiveCmd::Create(create_flags) => create_archive(&create_flags),
         flags::JsonArchiveCmd::Info(info_flags) => cmd::info::run(&info_flags),
+        flags::JsonArchiveCmd::Merge(merge_flags) => cmd::merge::run(&merge_flags),
+        flags::JsonArchiveCmd::Meta(meta_flags) => cmd::meta::run(&meta_flags),
+        flags::JsonArchiveCmd::Normalize(normalize_flags) => cmd::normalize::run(&normalize_flags),
+        flags::JsonArchiveCmd::Redact(redact_flags) => cmd::redact::run(&redact_flags),
         flags::JsonArchiveCmd::State(state_flags) => cmd::state::run(&state_flags),
+        flags::JsonArchiveCmd::Checkout(checkout_flags) => cmd::checkout::run(&checkout_flags),
+        flags::JsonArchiveCmd::Exec(exec_flags) => cmd::exec::run(&exec_flags, &report_now),
+        flags::JsonArchiveCmd::Watch(watch_flags) => cmd::watch::run(&watch_flags, &report_now),
+        flags::JsonArchiveCmd::Slice(slice_flags) => cmd::slice::run(&slice_flags),
+        flags::JsonArchiveCmd::Validate(validate_flags) => cmd::validate::run(&validate_flags),
+        flags::JsonArchiveCmd::VerifyRoundtrip(verify_flags) => cmd::verify_roundtrip::run(&verify_flags),
+        flags::JsonArchiveCmd::Explain(explain_flags) => cmd::explain::run(&explain_flags),
     }
 }
 
 fn create_archive(flags: &flags::Create) -> Vec<Diagnostic> {
+    check_keep_format(flags);
+
+    if let Some(dir) = &flags.dir {
+        return create_collection(flags, dir);
+    }
+
     if flags.inputs.is_empty() {
         return vec![Diagnostic::new(
             DiagnosticLevel::Fatal,
@@ -95,6 +166,18 @@ fn create_archive(flags: &flags::Create) -> Vec<Diagnostic> {
         return diagnostics;
     }
 
+    let observation_metadata = match parse_observation_metadata(&flags.meta) {
+        Ok(metadata) => metadata,
+        Err(diagnostics) => return diagnostics,
+    };
+
+    let metadata = match header_metadata(flags) {
+        Ok(metadata) => metadata,
+        Err(diagnostics) => return diagnostics,
+    };
+
+    let options = archive_options(flags, metadata, observation_metadata);
+
     let first_is_archive = match is_json_archive(&flags.inputs[0]) {
         Ok(is_archive) => is_archive,
         Err(e) => {
@@ -123,7 +206,9 @@ fn create_archive(flags: &flags::Create) -> Vec<Diagnostic> {
             ];
         }
 
-        return append_to_archive(&flags.inputs[0], &flags.inputs[1..], &output_path, flags.source.clone(), flags.snapshot_interval);
+        let mut diagnostics = append_to_archive(&flags.inputs[0], &flags.inputs[1..], &output_path, &options);
+        diagnostics.extend(metadata_ignored_on_append(options.metadata.is_some(), &output_path));
+        return diagnostics;
     }
 
     println!("Creating archive: {}", output_path.display());
@@ -137,12 +222,59 @@ fn create_archive(flags: &flags::Create) -> Vec<Diagnostic> {
         println!("Source: {}", source);
     }
 
-    match create_archive_from_files(
-        &flags.inputs,
-        output_path.clone(),
-        flags.source.clone(),
-        flags.snapshot_interval,
-    ) {
+    match create_archive_from_files(&flags.inputs, &output_path, &options) {
+        Ok(()) => {
+            println!("Archive created successfully: {}", output_path.display());
+            Vec::new()
+        }
+        Err(diagnostics) => diagnostics,
+    }
+}
+
+fn create_collection(flags: &flags::Create, dir: &Path) -> Vec<Diagnostic> {
+    if !flags.inputs.is_empty() {
+        usage_error(
+            "`--dir` can't be combined with input files, a collection archive only tracks a directory",
+        );
+    }
+
+    if !dir.is_dir() {
+        return vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't find the directory: {}", dir.display()),
+        )
+        .with_advice(
+            "Make sure the directory path is correct and the directory exists.".to_string(),
+        )];
+    }
+
+    let output_path = match &flags.output {
+        Some(path) => path.clone(),
+        None => default_output_filename(dir),
+    };
+
+    let observation_metadata = match parse_observation_metadata(&flags.meta) {
+        Ok(metadata) => metadata,
+        Err(diagnostics) => return diagnostics,
+    };
+
+    let metadata = match header_metadata(flags) {
+        Ok(metadata) => metadata,
+        Err(diagnostics) => return diagnostics,
+    };
+
+    let options = archive_options(flags, metadata, observation_metadata);
+
+    if output_path.exists() {
+        println!("Appending {} to collection archive: {}", dir.display(), output_path.display());
+        let mut diagnostics = append_collection_to_archive(&output_path, dir, &output_path, &options);
+        diagnostics.extend(metadata_ignored_on_append(options.metadata.is_some(), &output_path));
+        return diagnostics;
+    }
+
+    println!("Creating collection archive: {}", output_path.display());
+    match create_collection_archive(dir, &output_path, &options) {
         Ok(()) => {
             println!("Archive created successfully: {}", output_path.display());
             Vec::new()
@@ -150,3 +282,63 @@ fn create_archive(flags: &flags::Create) -> Vec<Diagnostic> {
         Err(diagnostics) => diagnostics,
     }
 }
+
+fn archive_options(
+    flags: &flags::Create,
+    metadata: Option<serde_json::Value>,
+    observation_metadata: serde_json::Map<String, serde_json::Value>,
+) -> ArchiveOptions {
+    ArchiveOptions {
+        source: flags.source.clone(),
+        metadata,
+        snapshot_interval: flags.snapshot_interval,
+        snapshot_mode: if flags.replace_snapshots {
+            SnapshotMode::Replace
+        } else {
+            SnapshotMode::Append
+        },
+        observation_metadata,
+        hash: flags.hash,
+        canonical: flags.canonicalize,
+        keep_format: flags.keep_format,
+        recover: flags.recover,
+    }
+}
+
+fn header_metadata(flags: &flags::Create) -> Result<Option<serde_json::Value>, Vec<Diagnostic>> {
+    flags
+        .metadata
+        .as_deref()
+        .map(|text| cmd::meta::parse_metadata(text, "--metadata value", false))
+        .transpose()
+}
+
+/// `--keep-format` records each file's layout to reproduce it later, which
+/// the options that rearrange what was read would defeat.
+fn check_keep_format(flags: &flags::Create) {
+    if !flags.keep_format {
+        return;
+    }
+    if flags.dir.is_some() {
+        usage_error("`--keep-format` can't be combined with `--dir`, it only works on individual files");
+    }
+    if flags.canonicalize {
+        usage_error("`--keep-format` can't be combined with `--canonicalize`, which would lose each file's key order");
+    }
+}
+
+/// The header is only written when an archive is created, so `--metadata`
+/// has nothing to do when appending.
+fn metadata_ignored_on_append(given: bool, archive: &Path) -> Option<Diagnostic> {
+    given.then(|| {
+        Diagnostic::new(
+            DiagnosticLevel::Warning,
+            DiagnosticCode::MissingHeaderField,
+            "I appended to an existing archive, so I didn't use --metadata.".to_string(),
+        )
+        .with_advice(format!(
+            "To change the metadata of an existing archive, use:\njson-archive meta merge {} '<json>'",
+            archive.display()
+        ))
+    })
+}
@@ -0,0 +1,398 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Merging archives of the same document.
+//!
+//! Two machines archiving the same source end up with two archives covering
+//! overlapping time ranges. Their events can't simply be interleaved, because
+//! each archive's deltas are relative to its own previous state. Instead each
+//! observation's full state is reconstructed, the observations from all
+//! inputs are put in timestamp order, and consecutive states are diffed again
+//! into one consistent archive.
+
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::archive::ArchiveWriter;
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticLevel};
+use crate::diff;
+use crate::events::{Header, Observation};
+use crate::hash::state_hash;
+use crate::reader::ReadMode;
+use crate::replay::{observation_states, require_intact, ObservationState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergeSummary {
+    /// Observations written after the header, as `validate` counts them.
+    pub observations: usize,
+    /// Observations left out because another input already had them.
+    pub duplicates: usize,
+}
+
+/// Merge `inputs` into a new archive at `output_path`.
+///
+/// An observation is a duplicate if its id was already merged, or if an
+/// observation with the same timestamp and state was. The header is that of
+/// the earliest created input, taking the first on a tie. Every other
+/// input's header state is an observation too, at the time that archive was
+/// created. The header's source and metadata come from the first input that
+/// has them.
+///
+/// If the inputs are hashed, the merged archive gets a new hash chain. Unless
+/// `drop_hashes` is set, inputs that are hashed can't be merged with inputs
+/// that aren't.
+pub fn merge_archives<P: AsRef<Path>, Q: AsRef<Path>>(
+    inputs: &[P],
+    output_path: Q,
+    snapshot_interval: Option<usize>,
+    drop_hashes: bool,
+) -> Result<MergeSummary, Vec<Diagnostic>> {
+    let mut observations: Vec<(usize, ObservationState)> = Vec::new();
+    let mut hashed: Option<bool> = None;
+    let mut earliest: Option<(usize, Header)> = None;
+    let mut source: Option<String> = None;
+    let mut metadata: Option<Value> = None;
+    let mut collection: Option<bool> = None;
+
+    for (input_index, input) in inputs.iter().enumerate() {
+        let path = input.as_ref();
+        let header = require_intact(path, "merge")?;
+
+        match collection {
+            Some(collection) if collection != header.collection => {
+                return Err(vec![Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::MissingHeaderField,
+                    format!(
+                        "I can't merge {} with the archives before it: one tracks a directory and the other a single file.",
+                        path.display()
+                    ),
+                )
+                .with_advice("Only merge archives of the same document.".to_string())]);
+            }
+            _ => collection = Some(header.collection),
+        }
+        match hashed {
+            Some(hashed) if !drop_hashes && hashed != header.hash.is_some() => {
+                return Err(vec![Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::MissingHeaderField,
+                    format!(
+                        "I can't merge {} with the archives before it: one records a hash chain and the other doesn't.",
+                        path.display()
+                    ),
+                )
+                .with_advice(
+                    "Merge with --drop-hashes to write the merged archive without hashes.".to_string(),
+                )]);
+            }
+            _ => hashed = Some(header.hash.is_some()),
+        }
+        source = source.or(header.source.clone());
+        metadata = metadata.or(header.metadata.clone());
+        if earliest.as_ref().is_none_or(|(_, earliest)| header.created < earliest.created) {
+            earliest = Some((input_index, header));
+        }
+
+        let states = observation_states(path, ReadMode::AppendSeek)?;
+        observations.extend(states.map(|observation| (input_index, observation)));
+    }
+
+    let Some((header_input, mut header)) = earliest else {
+        return Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::MissingHeaderField,
+            "I need at least one archive to merge, but you didn't give me any.".to_string(),
+        )]);
+    };
+    header.source = source;
+    header.metadata = metadata;
+    if drop_hashes {
+        header.hash = None;
+    }
+
+    // Stable, so observations with the same timestamp keep their input order
+    observations.sort_by_key(|(_, observation)| observation.timestamp);
+
+    let total = observations.len();
+    let mut observations = drop_duplicates(observations);
+    let duplicates = total - observations.len();
+    // The header's own state is already written as the header
+    if let Some(position) = observations
+        .iter()
+        .position(|(input, observation)| *input == header_input && observation.observation_id == "initial")
+    {
+        observations.remove(position);
+    }
+    let summary = MergeSummary { observations: observations.len(), duplicates };
+
+    let observations = observations.into_iter().map(|(_, observation)| observation);
+    write_observations(output_path, &header, observations, snapshot_interval)?;
+    Ok(summary)
+}
+
+/// Write a new archive with `header` followed by `observations`, each diffed
+/// against the one before it. If `header` has a hash, the hash chain is
+/// started again from its initial state. An observation from before the header was
+/// created is written as a snapshot instead, since its state doesn't follow
+/// from the header's. An observation called "initial", which was some other
+/// archive's header state, gets an id of its own.
+pub(crate) fn write_observations<P: AsRef<Path>>(
+    output_path: P,
+    header: &Header,
+    observations: impl IntoIterator<Item = ObservationState>,
+    snapshot_interval: Option<usize>,
+) -> Result<(), Vec<Diagnostic>> {
+    let mut header = header.clone();
+    let mut writer = ArchiveWriter::new(&output_path, snapshot_interval)?;
+    if header.hash.is_some() {
+        let hash = state_hash(&header.initial);
+        writer = writer.with_hash_chain(hash.clone());
+        header.hash = Some(hash);
+    }
+    writer.write_header(&header)?;
+
+    let mut previous = header.initial.clone();
+    for observation in observations {
+        let id = if observation.observation_id == "initial" {
+            format!("obs-{}", Uuid::new_v4())
+        } else {
+            observation.observation_id
+        };
+
+        let mut rediffed = Observation::new(id, observation.timestamp);
+        rediffed.metadata = observation.metadata;
+        if observation.timestamp < header.created {
+            writer.write_observation_as_snapshot(rediffed, &observation.state)?;
+        } else {
+            for event in diff::diff(&previous, &observation.state, "", &rediffed.id) {
+                rediffed.add_event(event);
+            }
+            writer.write_observation_with_state(rediffed, &observation.state)?;
+        }
+        previous = observation.state;
+    }
+
+    writer.finish()
+}
+
+/// A copy of the archive at `path` with a hash chain, for testing the
+/// commands that rewrite hashed archives.
+#[cfg(test)]
+pub(crate) fn hashed_copy(path: &Path) -> Result<tempfile::NamedTempFile, Box<dyn std::error::Error>> {
+    let mut header = require_intact(path, "copy").map_err(|d| format!("{:?}", d))?;
+    header.hash = Some(String::new());
+    let observations = observation_states(path, ReadMode::AppendSeek).map_err(|d| format!("{:?}", d))?;
+
+    let copy = tempfile::NamedTempFile::new()?;
+    write_observations(copy.path(), &header, observations.skip(1), None).map_err(|d| format!("{:?}", d))?;
+    assert_hash_chain(copy.path())?;
+    Ok(copy)
+}
+
+/// Check that the archive at `path` has a hash chain that validates and
+/// covers every observation. Observations may be out of order.
+#[cfg(test)]
+pub(crate) fn assert_hash_chain(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = crate::reader::ArchiveReader::new(path, ReadMode::FullValidation)?;
+    let result = reader.read(path)?;
+    let problems: Vec<_> = result
+        .diagnostics
+        .diagnostics()
+        .iter()
+        .filter(|diagnostic| diagnostic.code != DiagnosticCode::ObservationTimestampOrder)
+        .collect();
+    assert!(problems.is_empty(), "{:?}", problems);
+    assert_eq!(result.header.hash, Some(state_hash(&result.header.initial)));
+    assert!(result.chain.is_some());
+    Ok(())
+}
+
+/// Drop observations another input already had. `observations` must be in
+/// timestamp order, so equal timestamps are next to each other.
+/// Each observation is tagged with the input it came from.
+fn drop_duplicates(observations: Vec<(usize, ObservationState)>) -> Vec<(usize, ObservationState)> {
+    let mut seen_ids: HashSet<String> = HashSet::new();
+    let mut kept: Vec<(usize, ObservationState)> = Vec::new();
+
+    for (input, observation) in observations {
+        // Every archive's header state is called "initial", so that id says
+        // nothing about being the same observation
+        let is_initial = observation.observation_id == "initial";
+        if !is_initial && seen_ids.contains(&observation.observation_id) {
+            continue;
+        }
+
+        let same_time_and_state = kept
+            .iter()
+            .rev()
+            .take_while(|(_, earlier)| earlier.timestamp == observation.timestamp)
+            .any(|(_, earlier)| earlier.state == observation.state);
+        if same_time_and_state {
+            continue;
+        }
+
+        if !is_initial {
+            seen_ids.insert(observation.observation_id.clone());
+        }
+        kept.push((input, observation));
+    }
+
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::ArchiveReader;
+    use serde_json::json;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn write_archive(created: &str, initial: Value, lines: &[&str]) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+        let mut header = Header::new(initial, None);
+        header.created = created.parse()?;
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        for line in lines {
+            writeln!(temp_file, "{}", line)?;
+        }
+        Ok(temp_file)
+    }
+
+    #[test]
+    fn test_merge_archives() -> Result<(), Box<dyn std::error::Error>> {
+        let a = write_archive(
+            "2025-01-01T00:00:00Z",
+            json!({"count": 0}),
+            &[
+                r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1]"#,
+                r#"["change", "/count", 1, "obs-1"]"#,
+                r#"["observe", "obs-3", "2025-01-04T00:00:00Z", 1]"#,
+                r#"["change", "/count", 3, "obs-3"]"#,
+            ],
+        )?;
+        // Overlaps with a: obs-1 again, and a's first state under another id
+        let b = write_archive(
+            "2025-01-01T00:00:00Z",
+            json!({"count": 0}),
+            &[
+                r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1]"#,
+                r#"["change", "/count", 1, "obs-1"]"#,
+                r#"["observe", "obs-2", "2025-01-03T00:00:00Z", 1]"#,
+                r#"["change", "/count", 2, "obs-2"]"#,
+            ],
+        )?;
+        let output = NamedTempFile::new()?;
+
+        let summary = merge_archives(&[a.path(), b.path()], output.path(), None, false).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(summary, MergeSummary { observations: 3, duplicates: 2 });
+
+        let states: Vec<_> = observation_states(output.path(), ReadMode::AppendSeek)
+            .map_err(|d| format!("{:?}", d))?
+            .map(|observation| (observation.observation_id, observation.state))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("initial".to_string(), json!({"count": 0})),
+                ("obs-1".to_string(), json!({"count": 1})),
+                ("obs-2".to_string(), json!({"count": 2})),
+                ("obs-3".to_string(), json!({"count": 3})),
+            ]
+        );
+
+        let reader = ArchiveReader::new(output.path(), ReadMode::FullValidation)?;
+        let result = reader.read(output.path())?;
+        assert!(result.diagnostics.is_empty());
+        assert_eq!(result.observation_count, summary.observations);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_keeps_earliest_header_and_ids() -> Result<(), Box<dyn std::error::Error>> {
+        let a = write_archive(
+            "2025-01-03T00:00:00Z",
+            json!({"count": 3}),
+            &[r#"["observe", "obs-0", "2024-12-31T00:00:00Z", 1, {"host": "a"}]"#, r#"["change", "/count", -1, "obs-0"]"#],
+        )?;
+        let b = write_archive("2025-01-01T00:00:00Z", json!({"count": 0}), &[])?;
+        let output = NamedTempFile::new()?;
+
+        let summary = merge_archives(&[a.path(), b.path()], output.path(), None, false).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(summary, MergeSummary { observations: 2, duplicates: 0 });
+
+        let observations: Vec<_> = observation_states(output.path(), ReadMode::AppendSeek)
+            .map_err(|d| format!("{:?}", d))?
+            .map(|observation| (observation.observation_id, observation.state, observation.metadata))
+            .collect();
+        assert_eq!(observations.len(), 3);
+        assert_eq!(observations[0], ("initial".to_string(), json!({"count": 0}), None));
+        assert_eq!(
+            observations[1],
+            ("obs-0".to_string(), json!({"count": -1}), json!({"host": "a"}).as_object().cloned())
+        );
+        // a's header state, under an id of its own
+        assert!(observations[2].0.starts_with("obs-"));
+        assert_eq!(observations[2].1, json!({"count": 3}));
+
+        let reader = ArchiveReader::new(output.path(), ReadMode::FullValidation)?;
+        let result = reader.read(output.path())?;
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+        assert_eq!(result.header.created, "2025-01-01T00:00:00Z".parse::<chrono::DateTime<chrono::Utc>>()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_hashed_archives() -> Result<(), Box<dyn std::error::Error>> {
+        let a = write_archive(
+            "2025-01-01T00:00:00Z",
+            json!({"count": 0}),
+            &[r#"["observe", "obs-2", "2025-01-03T00:00:00Z", 1]"#, r#"["change", "/count", 2, "obs-2"]"#],
+        )?;
+        let b = write_archive(
+            "2025-01-01T00:00:00Z",
+            json!({"count": 0}),
+            &[r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1]"#, r#"["change", "/count", 1, "obs-1"]"#],
+        )?;
+        let (hashed_a, hashed_b) = (hashed_copy(a.path())?, hashed_copy(b.path())?);
+        let output = NamedTempFile::new()?;
+
+        merge_archives(&[hashed_a.path(), hashed_b.path()], output.path(), None, false).map_err(|d| format!("{:?}", d))?;
+        assert_hash_chain(output.path())?;
+
+        // One hashed and one not: only merged by dropping the hashes
+        let errors = merge_archives(&[hashed_a.path(), b.path()], output.path(), None, false).unwrap_err();
+        assert!(errors[0].description.contains("hash chain"), "{:?}", errors);
+        merge_archives(&[hashed_a.path(), b.path()], output.path(), None, true).map_err(|d| format!("{:?}", d))?;
+        let reader = ArchiveReader::new(output.path(), ReadMode::FullValidation)?;
+        let result = reader.read(output.path())?;
+        assert!(result.diagnostics.is_empty());
+        assert_eq!(result.header.hash, None);
+        assert_eq!(result.chain, None);
+
+        Ok(())
+    }
+}
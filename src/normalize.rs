@@ -0,0 +1,228 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Putting an archive's observations in chronological order.
+//!
+//! Nothing stops an archive from recording observations out of order, for
+//! example after a clock correction or an append from a machine whose clock
+//! was behind. Each event is a delta against the line before it, so the
+//! events can't just be shuffled. Instead every observation's full state is
+//! rebuilt, the observations after the header are sorted by timestamp, and
+//! consecutive states are diffed again.
+
+use std::path::Path;
+
+use crate::archive::write_replacement;
+use crate::diagnostics::Diagnostic;
+use crate::merge::write_observations;
+use crate::reader::ReadMode;
+use crate::replay::{observation_states, require_intact};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NormalizeSummary {
+    /// Observations written after the header, as `validate` counts them.
+    pub observations: usize,
+    /// Observations that ended up somewhere other than where they were.
+    pub moved: usize,
+}
+
+/// Rewrite the archive at `path` in chronological order.
+///
+/// Observations with the same timestamp keep their file order, and every
+/// observation keeps its id and metadata. The header stays as it is, so an
+/// observation from before the archive was created still comes after it,
+/// written as a snapshot. A hashed archive gets a new hash chain, unless
+/// `drop_hashes` is set, which writes it without hashes.
+///
+/// The result goes to `output_path` if given. Otherwise it is written next to
+/// `path` and renamed over it, so the archive is never half rewritten.
+pub fn normalize_archive<P: AsRef<Path>>(
+    path: P,
+    output_path: Option<&Path>,
+    snapshot_interval: Option<usize>,
+    drop_hashes: bool,
+) -> Result<NormalizeSummary, Vec<Diagnostic>> {
+    let path = path.as_ref();
+    let mut header = require_intact(path, "normalize")?;
+    if drop_hashes {
+        header.hash = None;
+    }
+
+    let mut observations: Vec<_> = observation_states(path, ReadMode::AppendSeek)?.skip(1).enumerate().collect();
+    // Stable, so observations with the same timestamp keep their file order
+    observations.sort_by_key(|(_, observation)| observation.timestamp);
+
+    let summary = NormalizeSummary {
+        observations: observations.len(),
+        moved: observations
+            .iter()
+            .enumerate()
+            .filter(|(position, (index, _))| position != index)
+            .count(),
+    };
+
+    let observations = observations.into_iter().map(|(_, observation)| observation);
+    write_replacement(path, output_path, |output_path| {
+        write_observations(output_path, &header, observations, snapshot_interval)
+    })?;
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Header;
+    use crate::merge::{assert_hash_chain, hashed_copy};
+    use crate::reader::ArchiveReader;
+    use serde_json::{json, Value};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn write_archive(lines: &[&str]) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+        let mut header = Header::new(json!({"count": 0}), Some("test".to_string()));
+        header.created = "2025-01-01T00:00:00Z".parse()?;
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        for line in lines {
+            writeln!(temp_file, "{}", line)?;
+        }
+        Ok(temp_file)
+    }
+
+    fn states(path: &Path) -> Result<Vec<(String, Value)>, Box<dyn std::error::Error>> {
+        Ok(observation_states(path, ReadMode::AppendSeek)
+            .map_err(|d| format!("{:?}", d))?
+            .map(|observation| (observation.observation_id, observation.state))
+            .collect())
+    }
+
+    #[test]
+    fn test_normalize_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = write_archive(&[
+            r#"["observe", "obs-1", "2025-01-03T00:00:00Z", 1]"#,
+            r#"["change", "/count", 3, "obs-1"]"#,
+            r#"["observe", "obs-2", "2025-01-02T00:00:00Z", 1]"#,
+            r#"["change", "/count", 2, "obs-2"]"#,
+            r#"["observe", "obs-3", "2025-01-04T00:00:00Z", 1]"#,
+            r#"["change", "/count", 4, "obs-3"]"#,
+        ])?;
+
+        let summary = normalize_archive(archive.path(), None, None, false).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(summary, NormalizeSummary { observations: 3, moved: 2 });
+
+        assert_eq!(
+            states(archive.path())?,
+            vec![
+                ("initial".to_string(), json!({"count": 0})),
+                ("obs-2".to_string(), json!({"count": 2})),
+                ("obs-1".to_string(), json!({"count": 3})),
+                ("obs-3".to_string(), json!({"count": 4})),
+            ]
+        );
+
+        let reader = ArchiveReader::new(archive.path(), ReadMode::FullValidation)?;
+        let result = reader.read(archive.path())?;
+        assert!(result.diagnostics.is_empty());
+        assert_eq!(result.header.source.as_deref(), Some("test"));
+        assert_eq!(result.observation_count, summary.observations);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_keeps_ids_before_header() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = write_archive(&[
+            r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1, {"host": "a"}]"#,
+            r#"["change", "/count", 2, "obs-1"]"#,
+            r#"["observe", "obs-0", "2024-12-31T00:00:00Z", 1, {"host": "b"}]"#,
+            r#"["change", "/count", -1, "obs-0"]"#,
+        ])?;
+
+        normalize_archive(archive.path(), None, None, false).map_err(|d| format!("{:?}", d))?;
+
+        let observations: Vec<_> = observation_states(archive.path(), ReadMode::AppendSeek)
+            .map_err(|d| format!("{:?}", d))?
+            .map(|observation| (observation.observation_id, observation.state, observation.metadata))
+            .collect();
+        let host = |name: &str| json!({"host": name}).as_object().cloned();
+        assert_eq!(
+            observations,
+            vec![
+                ("initial".to_string(), json!({"count": 0}), None),
+                ("obs-0".to_string(), json!({"count": -1}), host("b")),
+                ("obs-1".to_string(), json!({"count": 2}), host("a")),
+            ]
+        );
+
+        let reader = ArchiveReader::new(archive.path(), ReadMode::FullValidation)?;
+        let result = reader.read(archive.path())?;
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+        assert_eq!(result.header.created, "2025-01-01T00:00:00Z".parse::<chrono::DateTime<chrono::Utc>>()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_hashed_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = write_archive(&[
+            r#"["observe", "obs-1", "2025-01-03T00:00:00Z", 1]"#,
+            r#"["change", "/count", 3, "obs-1"]"#,
+            r#"["observe", "obs-2", "2025-01-02T00:00:00Z", 1]"#,
+            r#"["change", "/count", 2, "obs-2"]"#,
+        ])?;
+        let archive = hashed_copy(archive.path())?;
+
+        normalize_archive(archive.path(), None, None, false).map_err(|d| format!("{:?}", d))?;
+        assert_hash_chain(archive.path())?;
+        assert_eq!(
+            states(archive.path())?,
+            vec![
+                ("initial".to_string(), json!({"count": 0})),
+                ("obs-2".to_string(), json!({"count": 2})),
+                ("obs-1".to_string(), json!({"count": 3})),
+            ]
+        );
+
+        normalize_archive(archive.path(), None, None, true).map_err(|d| format!("{:?}", d))?;
+        let reader = ArchiveReader::new(archive.path(), ReadMode::FullValidation)?;
+        let result = reader.read(archive.path())?;
+        assert!(result.diagnostics.is_empty());
+        assert_eq!(result.header.hash, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_sorted_archive_is_unchanged() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = write_archive(&[
+            r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1]"#,
+            r#"["change", "/count", 1, "obs-1"]"#,
+        ])?;
+        let output = NamedTempFile::new()?;
+
+        let summary = normalize_archive(archive.path(), Some(output.path()), None, false).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(summary, NormalizeSummary { observations: 1, moved: 0 });
+        assert_eq!(states(output.path())?, states(archive.path())?);
+
+        Ok(())
+    }
+}
@@ -20,7 +20,7 @@
 //
 
 use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticLevel};
-use serde_json::Value;
+use serde_json::{Map, Value};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct JsonPointer {
@@ -28,6 +28,7 @@ pub struct JsonPointer {
 }
 
 impl JsonPointer {
+    #[allow(clippy::result_large_err)]
     pub fn new(path: &str) -> Result<Self, Diagnostic> {
         if path.is_empty() {
             return Ok(JsonPointer { tokens: vec![] });
@@ -52,6 +53,15 @@ impl JsonPointer {
         Ok(JsonPointer { tokens })
     }
 
+    /// The pointer to the containing value, or `None` for the root.
+    pub fn parent(&self) -> Option<JsonPointer> {
+        let (_, parent_tokens) = self.tokens.split_last()?;
+        Some(JsonPointer {
+            tokens: parent_tokens.to_vec(),
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
     pub fn get<'a>(&self, value: &'a Value) -> Result<&'a Value, Diagnostic> {
         let mut current = value;
 
@@ -77,7 +87,7 @@ impl JsonPointer {
                     current = arr.get(index).ok_or_else(|| {
                         Diagnostic::new(
                             DiagnosticLevel::Fatal,
-                            DiagnosticCode::PathNotFound,
+                            DiagnosticCode::ArrayIndexOutOfBounds,
                             format!(
                                 "I couldn't find index {} (array length is {})",
                                 index,
@@ -103,6 +113,7 @@ impl JsonPointer {
         Ok(current)
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn set(&self, value: &mut Value, new_value: Value) -> Result<(), Diagnostic> {
         if self.tokens.is_empty() {
             *value = new_value;
@@ -118,8 +129,8 @@ impl JsonPointer {
                     current = obj.get_mut(token).ok_or_else(|| {
                         Diagnostic::new(
                             DiagnosticLevel::Fatal,
-                            DiagnosticCode::PathNotFound,
-                            format!("I couldn't find the key '{}'", token),
+                            DiagnosticCode::ParentPathNotFound,
+                            format!("I couldn't find the parent key '{}'", token),
                         )
                     })?;
                 }
@@ -135,7 +146,7 @@ impl JsonPointer {
                     current = arr.get_mut(index).ok_or_else(|| {
                         Diagnostic::new(
                             DiagnosticLevel::Fatal,
-                            DiagnosticCode::PathNotFound,
+                            DiagnosticCode::ArrayIndexOutOfBounds,
                             format!(
                                 "I couldn't find index {} (array length is {})",
                                 index, array_len
@@ -177,7 +188,7 @@ impl JsonPointer {
                 } else {
                     return Err(Diagnostic::new(
                         DiagnosticLevel::Fatal,
-                        DiagnosticCode::PathNotFound,
+                        DiagnosticCode::ArrayIndexOutOfBounds,
                         format!(
                             "I couldn't set index {} (array length is {})",
                             index,
@@ -202,6 +213,7 @@ impl JsonPointer {
         Ok(())
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn remove(&self, value: &mut Value) -> Result<Value, Diagnostic> {
         if self.tokens.is_empty() {
             return Err(Diagnostic::new(
@@ -220,8 +232,8 @@ impl JsonPointer {
                     current = obj.get_mut(token).ok_or_else(|| {
                         Diagnostic::new(
                             DiagnosticLevel::Fatal,
-                            DiagnosticCode::PathNotFound,
-                            format!("I couldn't find the key '{}'", token),
+                            DiagnosticCode::ParentPathNotFound,
+                            format!("I couldn't find the parent key '{}'", token),
                         )
                     })?;
                 }
@@ -237,7 +249,7 @@ impl JsonPointer {
                     current = arr.get_mut(index).ok_or_else(|| {
                         Diagnostic::new(
                             DiagnosticLevel::Fatal,
-                            DiagnosticCode::PathNotFound,
+                            DiagnosticCode::ArrayIndexOutOfBounds,
                             format!(
                                 "I couldn't find index {} (array length is {})",
                                 index, array_len
@@ -260,7 +272,7 @@ impl JsonPointer {
         }
 
         match current {
-            Value::Object(obj) => obj.remove(last_token).ok_or_else(|| {
+            Value::Object(obj) => remove_key(obj, last_token).ok_or_else(|| {
                 Diagnostic::new(
                     DiagnosticLevel::Fatal,
                     DiagnosticCode::PathNotFound,
@@ -281,7 +293,7 @@ impl JsonPointer {
                 } else {
                     Err(Diagnostic::new(
                         DiagnosticLevel::Fatal,
-                        DiagnosticCode::PathNotFound,
+                        DiagnosticCode::ArrayIndexOutOfBounds,
                         format!(
                             "I couldn't remove index {} (array length is {})",
                             index,
@@ -301,19 +313,14 @@ impl JsonPointer {
             )),
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
-        if self.tokens.is_empty() {
-            return "".to_string();
+impl std::fmt::Display for JsonPointer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for token in &self.tokens {
+            write!(f, "/{}", token.replace("~", "~0").replace("/", "~1"))?;
         }
-
-        let escaped_tokens: Vec<String> = self
-            .tokens
-            .iter()
-            .map(|token| token.replace("~", "~0").replace("/", "~1"))
-            .collect();
-
-        format!("/{}", escaped_tokens.join("/"))
+        Ok(())
     }
 }
 
@@ -334,6 +341,15 @@ impl ValueTypeExt for Value {
     }
 }
 
+/// Remove `key` from `map`, leaving the other keys in their order. Plain
+/// `remove` swaps the last key into its place when keys keep their order.
+pub(crate) fn remove_key(map: &mut Map<String, Value>, key: &str) -> Option<Value> {
+    #[cfg(feature = "preserve-order")]
+    return map.shift_remove(key);
+    #[cfg(not(feature = "preserve-order"))]
+    return map.remove(key);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -403,6 +419,15 @@ mod tests {
         assert_eq!(value, json!({"baz": "qux"}));
     }
 
+    #[test]
+    #[cfg(feature = "preserve-order")]
+    fn test_remove_keeps_key_order() {
+        let pointer = JsonPointer::new("/a").unwrap();
+        let mut value: Value = serde_json::from_str(r#"{"a": 1, "b": 2, "c": 3}"#).unwrap();
+        pointer.remove(&mut value).unwrap();
+        assert_eq!(serde_json::to_string(&value).unwrap(), r#"{"b":2,"c":3}"#);
+    }
+
     #[test]
     fn test_remove_array() {
         let pointer = JsonPointer::new("/items/0").unwrap();
@@ -19,15 +19,19 @@
 // marxism@peoplesgrocers.com
 //
 
-use serde_json::Value;
-use std::collections::HashSet;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::{HashSet, VecDeque};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
 
 use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticCollector, DiagnosticLevel};
-use crate::event_deserialize::EventDeserializer;
-use crate::events::{Event, Header};
+use crate::event_deserialize::{element_spans, EventDeserializer, EventHead};
+use crate::events::{Event, Header, HeaderSummary, StateHash};
+use crate::format::FormatProfile;
+use crate::hash::ChainVerifier;
 use crate::pointer::JsonPointer;
 
 #[cfg(feature = "compression")]
@@ -41,10 +45,16 @@ use zstd::stream::read::Decoder as ZstdDecoder;
 pub enum ReadMode {
     FullValidation,
     AppendSeek,
+    /// Validate like `FullValidation`, but treat damage as recoverable: an
+    /// observation with a bad line, missing changes, or an event that doesn't
+    /// apply is dropped as a whole, and reading resumes at the next observe or
+    /// snapshot. The damage is reported as warnings, so callers can go on to
+    /// append to or extract from whatever is intact.
+    Recover,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum CompressionFormat {
+pub(crate) enum CompressionFormat {
     Gzip,
     Deflate,
     Zlib,
@@ -53,6 +63,21 @@ enum CompressionFormat {
     None,
 }
 
+/// Only needed to report compression this build can't handle
+#[cfg(not(feature = "compression"))]
+impl CompressionFormat {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            CompressionFormat::Gzip => "gzip",
+            CompressionFormat::Deflate => "deflate",
+            CompressionFormat::Zlib => "zlib",
+            CompressionFormat::Brotli => "brotli",
+            CompressionFormat::Zstd => "zstd",
+            CompressionFormat::None => "uncompressed",
+        }
+    }
+}
+
 pub struct ArchiveReader {
     mode: ReadMode,
     filename: String,
@@ -64,6 +89,10 @@ pub struct ReadResult {
     pub final_state: Value,
     pub diagnostics: DiagnosticCollector,
     pub observation_count: usize,
+    pub snapshot_count: usize,
+    /// Chain hash of the last hashed observe or snapshot, which an append
+    /// continues from. `None` if the archive isn't hashed.
+    pub chain: Option<String>,
 }
 
 pub struct EventIterator {
@@ -72,12 +101,167 @@ pub struct EventIterator {
     pub header: Header,
     filename: String,
     line_number: usize,
+    /// Line of the event most recently returned. Differs from `line_number`
+    /// in recover mode, where events are read a whole observation ahead.
+    event_line: usize,
+    recover: Option<Recovery>,
+}
+
+/// Recover mode bookkeeping for `EventIterator`: the observation being
+/// buffered, and the events of intact observations waiting to be returned.
+#[derive(Default)]
+struct Recovery {
+    pending: PendingObservation,
+    ready: VecDeque<(Event, usize)>,
+    finished: bool,
+}
+
+#[derive(Default)]
+struct PendingObservation {
+    events: Vec<(Event, usize)>,
+    declared_changes: Option<usize>,
+    /// Code of the first problem found in the observation, if any.
+    damage: Option<DiagnosticCode>,
 }
 
 impl Iterator for EventIterator {
     type Item = Event;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.recover.is_none() {
+            let event = self.next_line_event()?;
+            self.event_line = self.line_number;
+            return Some(event);
+        }
+
+        loop {
+            let recovery = self.recover.as_mut()?;
+            if let Some((event, line)) = recovery.ready.pop_front() {
+                self.event_line = line;
+                return Some(event);
+            }
+            if recovery.finished {
+                return None;
+            }
+            self.buffer_next_observation();
+        }
+    }
+}
+
+impl EventIterator {
+    fn new(
+        reader: Box<dyn BufRead>,
+        diagnostics: DiagnosticCollector,
+        header: Header,
+        filename: String,
+        mode: ReadMode,
+    ) -> Self {
+        Self {
+            reader,
+            diagnostics,
+            header,
+            filename,
+            line_number: 1,
+            event_line: 1,
+            recover: (mode == ReadMode::Recover).then(Recovery::default),
+        }
+    }
+
+    /// Line number of the event most recently returned by `next`.
+    pub fn event_line(&self) -> usize {
+        self.event_line
+    }
+
+    /// Read events until the observation being buffered is complete, then
+    /// either queue it or drop it. Damaged lines belong to the observation
+    /// they appear in, so their fatal diagnostics are downgraded to warnings
+    /// and the whole observation goes.
+    fn buffer_next_observation(&mut self) {
+        loop {
+            let checked = self.diagnostics.len();
+            let event = self.next_line_event();
+            let line = self.line_number;
+            let Some(recovery) = self.recover.as_mut() else { return };
+
+            if let Some(code) = self.diagnostics.downgrade_since(checked) {
+                recovery.pending.damage.get_or_insert(code);
+            }
+
+            match event {
+                None => {
+                    self.finish_pending_observation();
+                    if let Some(recovery) = self.recover.as_mut() {
+                        recovery.finished = true;
+                    }
+                    return;
+                }
+                Some(event @ (Event::Observe { .. } | Event::Snapshot { .. })) => {
+                    self.finish_pending_observation();
+                    let Some(recovery) = self.recover.as_mut() else { return };
+                    recovery.pending.declared_changes = match &event {
+                        Event::Observe { change_count, .. } => Some(*change_count),
+                        _ => None,
+                    };
+                    recovery.pending.events.push((event, line));
+                    if !recovery.ready.is_empty() {
+                        return;
+                    }
+                }
+                Some(event) => recovery.pending.events.push((event, line)),
+            }
+        }
+    }
+
+    fn finish_pending_observation(&mut self) {
+        let Some(recovery) = self.recover.as_mut() else { return };
+        let pending = std::mem::take(&mut recovery.pending);
+        let Some((first, first_line)) = pending.events.first() else { return };
+
+        let changes = match first {
+            Event::Observe { .. } | Event::Snapshot { .. } => pending.events.len() - 1,
+            _ => pending.events.len(),
+        };
+        let reason = if pending.damage.is_some() {
+            "one of its lines is damaged".to_string()
+        } else if pending.declared_changes.is_some_and(|declared| changes < declared) {
+            format!(
+                "it declares {} changes but only {} are intact",
+                pending.declared_changes.unwrap_or_default(),
+                changes
+            )
+        } else {
+            recovery.ready.extend(pending.events);
+            return;
+        };
+
+        let what = match first {
+            Event::Observe { observation_id, .. } | Event::Snapshot { observation_id, .. } => {
+                format!("observation '{}'", observation_id)
+            }
+            _ => format!("{} events that don't belong to any observation", pending.events.len()),
+        };
+        let code = pending.damage.unwrap_or(DiagnosticCode::ChangeCountMismatch);
+
+        self.diagnostics.add(
+            Diagnostic::new(
+                DiagnosticLevel::Warning,
+                code,
+                format!(
+                    "I dropped {} at line {} because {}, and rolled back to the state before it.",
+                    what, first_line, reason
+                ),
+            )
+            .with_location(self.filename.clone(), *first_line)
+            .with_advice(
+                "Reading resumes at the next observe or snapshot. Later observations were diffed \
+                 against the dropped one, so they may be dropped too until a snapshot resynchronizes \
+                 the state."
+                    .to_string(),
+            ),
+        );
+    }
+
+    fn next_line_event(&mut self) -> Option<Event> {
         let mut line = String::new();
 
         loop {
@@ -94,9 +278,29 @@ impl Iterator for EventIterator {
                         continue;
                     }
 
+                    let source = line.trim_end_matches(['\n', '\r']);
+
                     // Try to parse as event
                     let event_deserializer = match serde_json::from_str::<EventDeserializer>(&line) {
                         Ok(d) => d,
+                        Err(e) if e.classify() == serde_json::error::Category::Eof => {
+                            self.diagnostics.add(
+                                Diagnostic::new(
+                                    DiagnosticLevel::Fatal,
+                                    DiagnosticCode::TruncatedJson,
+                                    format!("I found a line that ends before its JSON is complete: {}", e),
+                                )
+                                .with_location(self.filename.clone(), self.line_number)
+                                .with_caret(source, source.len()..source.len())
+                                .with_advice(
+                                    "This usually means a write was interrupted, for example by a full disk \
+                                     or a killed process. Remove the incomplete line, or restore the archive \
+                                     from a backup."
+                                        .to_string(),
+                                ),
+                            );
+                            continue;
+                        }
                         Err(e) => {
                             self.diagnostics.add(
                                 Diagnostic::new(
@@ -105,7 +309,7 @@ impl Iterator for EventIterator {
                                     format!("I couldn't parse this line as JSON: {}", e),
                                 )
                                 .with_location(self.filename.clone(), self.line_number)
-                                .with_snippet(format!("{} | {}", self.line_number, line.trim()))
+                                .with_caret(source, error_offset(source, &e))
                                 .with_advice(
                                     "Each line after the header must be either:\n\
                                      - A comment starting with #\n\
@@ -118,13 +322,25 @@ impl Iterator for EventIterator {
                         }
                     };
 
-                    // Add any diagnostics from deserialization
-                    for diagnostic in event_deserializer.diagnostics {
-                        self.diagnostics.add(
-                            diagnostic
-                                .with_location(self.filename.clone(), self.line_number)
-                                .with_snippet(format!("{} | {}", self.line_number, line.trim()))
-                        );
+                    // Add any diagnostics from deserialization, pointing at the
+                    // element they're about, or at the whole event if none
+                    if !event_deserializer.diagnostics.is_empty() {
+                        let spans = element_spans(source);
+                        let span = event_deserializer
+                            .element
+                            .and_then(|index| spans.get(index).cloned())
+                            .unwrap_or_else(|| {
+                                let start = source.len() - source.trim_start().len();
+                                start..source.len()
+                            });
+
+                        for diagnostic in event_deserializer.diagnostics {
+                            self.diagnostics.add(
+                                diagnostic
+                                    .with_location(self.filename.clone(), self.line_number)
+                                    .with_caret(source, span.clone()),
+                            );
+                        }
                     }
 
                     // Return event if we have one
@@ -157,6 +373,145 @@ impl Iterator for EventIterator {
     }
 }
 
+/// Recover mode bookkeeping for `ArchiveReader::read`: the state before the
+/// current observation, so an observation whose events don't apply can be
+/// undone as a whole.
+struct Rollback {
+    state: Value,
+    dropped: bool,
+    /// Diagnostics before this index have already been looked at.
+    checked: usize,
+}
+
+impl Rollback {
+    fn begin(&mut self, state: &Value) {
+        self.state = state.clone();
+        self.dropped = false;
+    }
+}
+
+/// Whether a snapshot with `snapshot_id` starts an observation of its own.
+///
+/// A snapshot repeating the id of the observation it follows (`current`) is
+/// a checkpoint of that observation, and one repeating any id in `seen` adds
+/// nothing new. A snapshot with an id not seen before stands in for a whole
+/// observe+deltas sequence.
+pub fn is_observation_snapshot(snapshot_id: &str, current: Option<&str>, seen: &HashSet<String>) -> bool {
+    current != Some(snapshot_id) && !seen.contains(snapshot_id)
+}
+
+/// Where an observation starts, read from its observe or snapshot line alone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservationHead {
+    pub observation_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Changes declared by the observe line; 0 for an observation that is
+    /// a snapshot.
+    pub change_count: usize,
+    pub metadata: Option<Map<String, Value>>,
+    pub line_number: usize,
+}
+
+/// Iterator over the observations in an archive, without replaying them.
+/// See `ArchiveReader::observation_heads`.
+pub struct ObservationHeads {
+    reader: Box<dyn BufRead>,
+    pub diagnostics: DiagnosticCollector,
+    pub header: HeaderSummary,
+    /// Snapshot lines read so far, checkpoints included.
+    pub snapshot_count: usize,
+    filename: String,
+    line_number: usize,
+    seen: HashSet<String>,
+    current: Option<String>,
+}
+
+impl Iterator for ObservationHeads {
+    type Item = ObservationHead;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            self.line_number += 1;
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    self.diagnostics.add(
+                        Diagnostic::new(
+                            DiagnosticLevel::Fatal,
+                            DiagnosticCode::InvalidUtf8,
+                            format!("I found invalid UTF-8 bytes at line {}.", self.line_number)
+                        )
+                        .with_location(self.filename.clone(), self.line_number)
+                        .with_advice(
+                            "The JSON Archive format requires UTF-8 encoding. Make sure the file \
+                             was saved with UTF-8 encoding, not Latin-1, Windows-1252, or another encoding."
+                                .to_string()
+                        )
+                    );
+                    return None;
+                }
+                Err(e) => {
+                    self.diagnostics.add(
+                        Diagnostic::new(
+                            DiagnosticLevel::Fatal,
+                            DiagnosticCode::PathNotFound,
+                            format!("I couldn't read line {} of the archive file: {}", self.line_number, e)
+                        )
+                        .with_location(self.filename.clone(), self.line_number)
+                    );
+                    return None;
+                }
+            }
+
+            if !EventHead::is_candidate(&line) {
+                continue;
+            }
+            let Ok(head) = serde_json::from_str::<EventHead>(&line) else {
+                continue;
+            };
+            if head.is_snapshot {
+                self.snapshot_count += 1;
+            }
+
+            if head.is_snapshot
+                && !is_observation_snapshot(&head.observation_id, self.current.as_deref(), &self.seen)
+            {
+                continue;
+            }
+
+            self.seen.insert(head.observation_id.clone());
+            self.current = Some(head.observation_id.clone());
+            return Some(ObservationHead {
+                observation_id: head.observation_id,
+                timestamp: head.timestamp,
+                change_count: head.change_count,
+                metadata: head.metadata,
+                line_number: self.line_number,
+            });
+        }
+    }
+}
+
+/// Byte offset into `line` of a serde_json syntax error, for the caret.
+/// serde_json counts columns from 1.
+fn error_offset(line: &str, error: &serde_json::Error) -> std::ops::Range<usize> {
+    let offset = error.column().saturating_sub(1).min(line.len());
+    offset..offset + 1
+}
+
+/// Whether the archive at `path` is compressed, judged the same way the
+/// reader picks a decoder.
+pub(crate) fn is_compressed(path: &Path) -> std::io::Result<bool> {
+    let mut magic_bytes = [0u8; 4];
+    let bytes_read = File::open(path)?.read(&mut magic_bytes)?;
+    Ok(detect_compression_format(path, &magic_bytes[..bytes_read]) != CompressionFormat::None)
+}
+
 fn detect_compression_format(path: &Path, bytes: &[u8]) -> CompressionFormat {
     if bytes.len() < 4 {
         return CompressionFormat::None;
@@ -178,17 +533,27 @@ fn detect_compression_format(path: &Path, bytes: &[u8]) -> CompressionFormat {
     }
 
     // Check file extension for brotli (no reliable magic number) and deflate
-    if let Some(ext) = path.extension() {
-        let ext_str = ext.to_string_lossy();
-        if ext_str == "br" || path.to_string_lossy().contains(".br.") {
-            return CompressionFormat::Brotli;
-        }
-        if ext_str == "deflate" {
-            return CompressionFormat::Deflate;
-        }
+    match compression_from_extension(path) {
+        format @ (CompressionFormat::Brotli | CompressionFormat::Deflate) => format,
+        _ => CompressionFormat::None,
     }
+}
 
-    CompressionFormat::None
+/// The compression a file's name asks for. Files being read are judged by
+/// their magic bytes first; files being written only have their name.
+pub(crate) fn compression_from_extension(path: &Path) -> CompressionFormat {
+    let Some(ext) = path.extension() else {
+        return CompressionFormat::None;
+    };
+    match ext.to_string_lossy().as_ref() {
+        "gz" => CompressionFormat::Gzip,
+        "zlib" => CompressionFormat::Zlib,
+        "zst" => CompressionFormat::Zstd,
+        "br" => CompressionFormat::Brotli,
+        "deflate" => CompressionFormat::Deflate,
+        _ if path.to_string_lossy().contains(".br.") => CompressionFormat::Brotli,
+        _ => CompressionFormat::None,
+    }
 }
 
 impl ArchiveReader {
@@ -198,7 +563,85 @@ impl ArchiveReader {
     }
 
     pub fn events<P: AsRef<Path>>(&self, path: P) -> std::io::Result<(Value, EventIterator)> {
-        let path = path.as_ref();
+        let mut diagnostics = DiagnosticCollector::new();
+        let reader = self.open(path.as_ref(), &mut diagnostics)?;
+        self.header_and_events(reader, diagnostics)
+    }
+
+    /// Like `events`, reading the archive from `reader` instead of a file,
+    /// such as a buffer in memory or a request body. The archive must not be
+    /// compressed. The name given to `new` is used in diagnostics.
+    pub fn events_from<R: BufRead + 'static>(&self, reader: R) -> std::io::Result<(Value, EventIterator)> {
+        self.header_and_events(Box::new(reader), DiagnosticCollector::new())
+    }
+
+    fn header_and_events(
+        &self,
+        mut reader: Box<dyn BufRead>,
+        mut diagnostics: DiagnosticCollector,
+    ) -> std::io::Result<(Value, EventIterator)> {
+        let header = match self.read_header_line(&mut reader, &mut diagnostics)? {
+            Some(line) => self.parse_header::<Header>(&line, 1, &mut diagnostics),
+            None => None,
+        };
+        let Some(header) = header else {
+            let iterator = EventIterator::new(
+                reader,
+                diagnostics,
+                Header::new(Value::Null, None),
+                self.filename.clone(),
+                self.mode,
+            );
+            return Ok((Value::Null, iterator));
+        };
+
+        let initial = header.initial.clone();
+        let iterator = EventIterator::new(
+            reader,
+            diagnostics,
+            header,
+            self.filename.clone(),
+            self.mode,
+        );
+
+        Ok((initial, iterator))
+    }
+
+    /// Read the header without its initial state, and the observations
+    /// without replaying them. Only observe and snapshot lines are parsed, so
+    /// this stays fast on archives too big to replay. Damaged lines are
+    /// skipped without diagnostics; a full read reports them. Check
+    /// `diagnostics` on the result for fatal problems with the header before
+    /// using `header`, and again after iterating: a line that can't be read
+    /// ends the iteration with a fatal diagnostic.
+    pub fn observation_heads<P: AsRef<Path>>(&self, path: P) -> std::io::Result<ObservationHeads> {
+        let mut diagnostics = DiagnosticCollector::new();
+        let mut reader = self.open(path.as_ref(), &mut diagnostics)?;
+
+        let header = match self.read_header_line(&mut reader, &mut diagnostics)? {
+            Some(line) => self.parse_header::<HeaderSummary>(&line, 1, &mut diagnostics),
+            None => None,
+        };
+
+        Ok(ObservationHeads {
+            reader,
+            diagnostics,
+            header: header.unwrap_or_else(|| (&Header::new(Value::Null, None)).into()),
+            snapshot_count: 0,
+            filename: self.filename.clone(),
+            line_number: 1,
+            seen: HashSet::new(),
+            current: None,
+        })
+    }
+
+    /// Open the archive, decompressing it if needed. If this build can't
+    /// decompress it, the reason goes in `diagnostics` and the reader is empty.
+    fn open(
+        &self,
+        path: &Path,
+        diagnostics: &mut DiagnosticCollector,
+    ) -> std::io::Result<Box<dyn BufRead>> {
         let mut file = File::open(path)?;
 
         // Detect compression format
@@ -209,19 +652,10 @@ impl ArchiveReader {
         // Re-open file to reset position
         file = File::open(path)?;
 
-        let mut diagnostics = DiagnosticCollector::new();
-
         // Check if compression is detected but not supported
         #[cfg(not(feature = "compression"))]
         if compression_format != CompressionFormat::None {
-            let format_name = match compression_format {
-                CompressionFormat::Gzip => "gzip",
-                CompressionFormat::Deflate => "deflate",
-                CompressionFormat::Zlib => "zlib",
-                CompressionFormat::Brotli => "brotli",
-                CompressionFormat::Zstd => "zstd",
-                CompressionFormat::None => unreachable!(),
-            };
+            let format_name = compression_format.name();
 
             diagnostics.add(
                 Diagnostic::new(
@@ -239,17 +673,13 @@ impl ArchiveReader {
                 )
             );
 
-            // Return dummy values with fatal diagnostic
-            let iterator = EventIterator {
-                reader: Box::new(BufReader::new(std::io::empty())),
-                diagnostics,
-                header: Header::new(Value::Null, None),
-                filename: self.filename.clone(),
-                line_number: 1,
-            };
-            return Ok((Value::Null, iterator));
+            return Ok(Box::new(BufReader::new(std::io::empty())));
         }
 
+        // Only needed to report a compressed archive this build can't read
+        #[cfg(feature = "compression")]
+        let _ = diagnostics;
+
         // Create appropriate reader based on compression format
         #[cfg(feature = "compression")]
         let reader: Box<dyn BufRead> = match compression_format {
@@ -264,10 +694,22 @@ impl ArchiveReader {
         #[cfg(not(feature = "compression"))]
         let reader: Box<dyn BufRead> = Box::new(BufReader::new(file));
 
-        let mut reader = reader;
-        let mut header_line = String::new();
+        Ok(reader)
+    }
+
+    /// Read the first line, or return `None` with a fatal diagnostic if there
+    /// isn't a readable one. Does nothing if `open` already failed.
+    fn read_header_line(
+        &self,
+        reader: &mut Box<dyn BufRead>,
+        diagnostics: &mut DiagnosticCollector,
+    ) -> std::io::Result<Option<String>> {
+        if diagnostics.has_fatal() {
+            return Ok(None);
+        }
 
-        let _bytes_read = match reader.read_line(&mut header_line) {
+        let mut header_line = String::new();
+        match reader.read_line(&mut header_line) {
             Ok(0) => {
                 // Empty file
                 diagnostics.add(
@@ -282,16 +724,9 @@ impl ArchiveReader {
                             .to_string(),
                     ),
                 );
-                let iterator = EventIterator {
-                    reader,
-                    diagnostics,
-                    header: Header::new(Value::Null, None),
-                    filename: self.filename.clone(),
-                    line_number: 1,
-                };
-                return Ok((Value::Null, iterator));
+                Ok(None)
             }
-            Ok(n) => n,
+            Ok(_) => Ok(Some(header_line)),
             Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
                 // UTF-8 error
                 diagnostics.add(
@@ -307,69 +742,127 @@ impl ArchiveReader {
                             .to_string()
                     )
                 );
-                let iterator = EventIterator {
-                    reader,
-                    diagnostics,
-                    header: Header::new(Value::Null, None),
-                    filename: self.filename.clone(),
-                    line_number: 1,
-                };
-                return Ok((Value::Null, iterator));
-            }
-            Err(e) => return Err(e),
-        };
-
-        let header = match self.parse_header(&header_line, 1, &mut diagnostics) {
-            Some(h) => h,
-            None => {
-                let iterator = EventIterator {
-                    reader,
-                    diagnostics,
-                    header: Header::new(Value::Null, None),
-                    filename: self.filename.clone(),
-                    line_number: 1,
-                };
-                return Ok((Value::Null, iterator));
+                Ok(None)
             }
-        };
-
-        let iterator = EventIterator {
-            reader,
-            diagnostics,
-            header: header.clone(),
-            filename: self.filename.clone(),
-            line_number: 1,
-        };
-
-        Ok((header.initial, iterator))
+            Err(e) => Err(e),
+        }
     }
 
     pub fn read<P: AsRef<Path>>(&self, path: P) -> std::io::Result<ReadResult> {
-        let (initial_value, mut event_iter) = self.events(&path)?;
+        let (initial_value, event_iter) = self.events(&path)?;
+        Ok(self.read_events(initial_value, event_iter))
+    }
+
+    /// Like `read`, reading the archive from `reader` instead of a file. See
+    /// `events_from`.
+    pub fn read_from<R: BufRead + 'static>(&self, reader: R) -> std::io::Result<ReadResult> {
+        let (initial_value, event_iter) = self.events_from(reader)?;
+        Ok(self.read_events(initial_value, event_iter))
+    }
 
+    fn read_events(&self, initial_value: Value, mut event_iter: EventIterator) -> ReadResult {
         // Check for early fatal diagnostics (like compression not supported)
         if event_iter.diagnostics.has_fatal() {
-            return Ok(ReadResult {
+            return ReadResult {
                 header: Header::new(Value::Null, None),
                 final_state: Value::Null,
                 diagnostics: event_iter.diagnostics,
                 observation_count: 0,
-            });
+                snapshot_count: 0,
+                chain: None,
+            };
         }
 
-        let header = Header::new(initial_value.clone(), None);
+        let header = event_iter.header.clone();
         let mut state = initial_value;
         let mut seen_observations: HashSet<String> = HashSet::new();
         let mut current_observation: Option<(String, usize, usize)> = None;
         let mut events_in_observation = 0;
         let mut observation_count = 0;
+        let mut snapshot_count = 0;
+        let mut latest_timestamp: Option<DateTime<Utc>> = None;
+        let mut last_chain: Option<String> = None;
+        // Hashes are checked once an observation's state is complete, which
+        // in recover mode would be blamed on the observation after it
+        let mut verifier = (self.mode == ReadMode::FullValidation).then(|| ChainVerifier::new(&state));
+        if let (Some(verifier), Some(claimed)) = (verifier.as_mut(), &header.hash) {
+            if let Some(diagnostic) = verifier.check_initial(claimed) {
+                event_iter.diagnostics.add(diagnostic.with_location(self.filename.clone(), 1));
+            }
+        }
+        let mut pending_hash: Option<(StateHash, usize)> = None;
+        if self.mode == ReadMode::FullValidation {
+            if let Some(Err(problem)) = header.format.as_ref().map(FormatProfile::validate) {
+                event_iter.diagnostics.add(self.invalid_format_profile("header", problem, 1));
+            }
+        }
+        let mut rollback = (self.mode == ReadMode::Recover).then(|| Rollback {
+            state: state.clone(),
+            dropped: false,
+            checked: event_iter.diagnostics.len(),
+        });
 
         // Process events from iterator
         while let Some(event) = event_iter.next() {
-            let line_number = event_iter.line_number;
+            let line_number = event_iter.event_line;
+
+            if let Some(rollback) = rollback.as_mut() {
+                if self.roll_back_damage(rollback, &mut state, &mut event_iter.diagnostics, &current_observation) {
+                    observation_count -= 1;
+                }
+
+                if rollback.dropped && !matches!(event, Event::Observe { .. } | Event::Snapshot { .. }) {
+                    events_in_observation += 1;
+                    continue;
+                }
+            }
 
             match event {
-                Event::Observe { observation_id, timestamp: _, change_count } => {
+                Event::Observe { observation_id, timestamp, change_count, metadata, hash } => {
+                    // Recover mode would drop the observation over it, which is no help
+                    if self.mode == ReadMode::FullValidation {
+                        if let Some(Err(problem)) = FormatProfile::from_metadata(metadata.as_ref()) {
+                            let diagnostic = self.invalid_format_profile("observation", problem, line_number);
+                            event_iter.diagnostics.add(diagnostic);
+                        }
+                    }
+                    if let Some(verifier) = verifier.as_mut() {
+                        self.verify_hashes(verifier, pending_hash.take(), &state, &mut event_iter.diagnostics);
+                        if hash.is_none() {
+                            if let Some(diagnostic) = verifier.unhashed() {
+                                event_iter.diagnostics.add(diagnostic.with_location(self.filename.clone(), line_number));
+                            }
+                        }
+                    }
+                    if let Some(hash) = hash {
+                        last_chain = Some(hash.chain.clone());
+                        pending_hash = Some((hash, line_number));
+                    }
+
+                    if self.validates() {
+                        if let Some(latest) = latest_timestamp.filter(|latest| timestamp < *latest) {
+                            event_iter.diagnostics.add(
+                                Diagnostic::new(
+                                    DiagnosticLevel::Warning,
+                                    DiagnosticCode::ObservationTimestampOrder,
+                                    format!(
+                                        "I found an observation timestamped {}, which is earlier than the observation at {} before it.",
+                                        timestamp.to_rfc3339(),
+                                        latest.to_rfc3339()
+                                    ),
+                                )
+                                .with_location(self.filename.clone(), line_number)
+                                .with_advice(
+                                    "Observations may be out of order, but then file order and time order \
+                                     disagree: --index counts lines while --as-of compares timestamps. \
+                                     Run `json-archive normalize` to rewrite the archive in chronological order."
+                                        .to_string(),
+                                ),
+                            );
+                        }
+                    }
+                    latest_timestamp = latest_timestamp.max(Some(timestamp));
+
                     if let Some((_obs_id, obs_line, expected_count)) = &current_observation {
                         if events_in_observation != *expected_count {
                             event_iter.diagnostics.add(
@@ -411,12 +904,15 @@ impl ArchiveReader {
                     current_observation = Some((observation_id, line_number, change_count));
                     events_in_observation = 0;
                     observation_count += 1;
+                    if let Some(rollback) = rollback.as_mut() {
+                        rollback.begin(&state);
+                    }
                 }
 
                 Event::Add { path, value, observation_id } => {
                     events_in_observation += 1;
 
-                    if self.mode == ReadMode::FullValidation
+                    if self.validates()
                         && !seen_observations.contains(&observation_id)
                     {
                         event_iter.diagnostics.add(
@@ -434,6 +930,12 @@ impl ArchiveReader {
                         continue;
                     }
 
+                    if self.validates() {
+                        if let Some(diag) = check_old_value(&state, "add", &path) {
+                            event_iter.diagnostics.add(diag.with_location(self.filename.clone(), line_number));
+                        }
+                    }
+
                     if let Err(diag) = apply_add(&mut state, &path, value) {
                         event_iter.diagnostics.add(diag.with_location(self.filename.clone(), line_number));
                         continue;
@@ -443,7 +945,7 @@ impl ArchiveReader {
                 Event::Change { path, new_value, observation_id } => {
                     events_in_observation += 1;
 
-                    if self.mode == ReadMode::FullValidation
+                    if self.validates()
                         && !seen_observations.contains(&observation_id)
                     {
                         event_iter.diagnostics.add(
@@ -457,6 +959,12 @@ impl ArchiveReader {
                         continue;
                     }
 
+                    if self.validates() {
+                        if let Some(diag) = check_old_value(&state, "change", &path) {
+                            event_iter.diagnostics.add(diag.with_location(self.filename.clone(), line_number));
+                        }
+                    }
+
                     if let Err(diag) = apply_change(&mut state, &path, new_value) {
                         event_iter.diagnostics.add(diag.with_location(self.filename.clone(), line_number));
                         continue;
@@ -466,7 +974,7 @@ impl ArchiveReader {
                 Event::Remove { path, observation_id } => {
                     events_in_observation += 1;
 
-                    if self.mode == ReadMode::FullValidation
+                    if self.validates()
                         && !seen_observations.contains(&observation_id)
                     {
                         event_iter.diagnostics.add(
@@ -489,7 +997,7 @@ impl ArchiveReader {
                 Event::Move { path, moves, observation_id } => {
                     events_in_observation += 1;
 
-                    if self.mode == ReadMode::FullValidation
+                    if self.validates()
                         && !seen_observations.contains(&observation_id)
                     {
                         event_iter.diagnostics.add(
@@ -509,13 +1017,114 @@ impl ArchiveReader {
                     }
                 }
 
-                Event::Snapshot { observation_id: _, timestamp: _, object } => {
-                    if self.mode == ReadMode::FullValidation && state != object {
-                        event_iter.diagnostics.add(
-                            Diagnostic::new(
-                                DiagnosticLevel::Fatal,
-                                DiagnosticCode::SnapshotStateMismatch,
-                                "I found a snapshot whose state doesn't match the replayed state up to this point.".to_string()
+                Event::Snapshot { observation_id, timestamp, object, hash, metadata } => {
+                    snapshot_count += 1;
+
+                    if self.mode == ReadMode::FullValidation {
+                        if let Some(Err(problem)) = FormatProfile::from_metadata(metadata.as_ref()) {
+                            let diagnostic = self.invalid_format_profile("observation", problem, line_number);
+                            event_iter.diagnostics.add(diagnostic);
+                        }
+                    }
+
+                    if let Some(verifier) = verifier.as_mut() {
+                        self.verify_hashes(verifier, pending_hash.take(), &state, &mut event_iter.diagnostics);
+                        match &hash {
+                            Some(hash) => self.verify_hashes(
+                                verifier,
+                                Some((hash.clone(), line_number)),
+                                &object,
+                                &mut event_iter.diagnostics,
+                            ),
+                            None => {
+                                if let Some(diagnostic) = verifier.unhashed() {
+                                    event_iter.diagnostics.add(diagnostic.with_location(self.filename.clone(), line_number));
+                                }
+                            }
+                        }
+                    }
+                    if let Some(hash) = hash {
+                        last_chain = Some(hash.chain);
+                    }
+
+                    if self.validates() {
+                        if let Some(latest) = latest_timestamp.filter(|latest| timestamp < *latest) {
+                            event_iter.diagnostics.add(
+                                Diagnostic::new(
+                                    DiagnosticLevel::Warning,
+                                    DiagnosticCode::SnapshotTimestampOrder,
+                                    format!(
+                                        "I found a snapshot timestamped {}, which is earlier than the observation at {} before it.",
+                                        timestamp.to_rfc3339(),
+                                        latest.to_rfc3339()
+                                    ),
+                                )
+                                .with_location(self.filename.clone(), line_number)
+                                .with_advice(
+                                    "A snapshot records the state after the events before it, so its \
+                                     timestamp shouldn't be earlier than theirs. Check that the snapshot \
+                                     wasn't copied from another archive or written with a skewed clock."
+                                        .to_string(),
+                                ),
+                            );
+                        }
+                    }
+                    latest_timestamp = latest_timestamp.max(Some(timestamp));
+
+                    let current_id = current_observation.as_ref().map(|(obs_id, _, _)| obs_id.as_str());
+                    let is_checkpoint = current_id == Some(observation_id.as_str());
+                    let is_observation = is_observation_snapshot(&observation_id, current_id, &seen_observations);
+
+                    if !is_checkpoint && !is_observation {
+                        event_iter.diagnostics.add(
+                            Diagnostic::new(
+                                DiagnosticLevel::Warning,
+                                DiagnosticCode::DuplicateObservationId,
+                                format!("I found a snapshot reusing the earlier observation ID '{}'", observation_id),
+                            )
+                            .with_location(self.filename.clone(), line_number)
+                            .with_advice(
+                                "A snapshot should either repeat the ID of the observation right before it, \
+                                 or use a new ID if it stands in for a whole observation."
+                                    .to_string(),
+                            ),
+                        );
+                    }
+
+                    if is_observation {
+                        if let Some((_obs_id, obs_line, expected_count)) = &current_observation {
+                            if events_in_observation != *expected_count {
+                                event_iter.diagnostics.add(
+                                    Diagnostic::new(
+                                        DiagnosticLevel::Warning,
+                                        DiagnosticCode::ChangeCountMismatch,
+                                        format!(
+                                            "The observe event at line {} declared {} changes, but I found {}.",
+                                            obs_line, expected_count, events_in_observation
+                                        ),
+                                    )
+                                    .with_location(self.filename.clone(), *obs_line),
+                                );
+                            }
+                        }
+
+                        seen_observations.insert(observation_id.clone());
+                        current_observation = Some((observation_id, line_number, 0));
+                        events_in_observation = 0;
+                        observation_count += 1;
+                        if let Some(rollback) = rollback.as_mut() {
+                            rollback.begin(&state);
+                        }
+                        state = object;
+                        continue;
+                    }
+
+                    if self.validates() && state != object {
+                        event_iter.diagnostics.add(
+                            Diagnostic::new(
+                                DiagnosticLevel::Fatal,
+                                DiagnosticCode::SnapshotStateMismatch,
+                                "I found a snapshot whose state doesn't match the replayed state up to this point.".to_string()
                             )
                             .with_location(self.filename.clone(), line_number)
                             .with_advice(
@@ -532,6 +1141,16 @@ impl ArchiveReader {
             }
         }
 
+        if let Some(rollback) = rollback.as_mut() {
+            if self.roll_back_damage(rollback, &mut state, &mut event_iter.diagnostics, &current_observation) {
+                observation_count -= 1;
+            }
+        }
+
+        if let Some(verifier) = verifier.as_mut() {
+            self.verify_hashes(verifier, pending_hash.take(), &state, &mut event_iter.diagnostics);
+        }
+
         if let Some((_obs_id, obs_line, expected_count)) = &current_observation {
             if events_in_observation != *expected_count {
                 event_iter.diagnostics.add(
@@ -548,23 +1167,104 @@ impl ArchiveReader {
             }
         }
 
-        Ok(ReadResult {
+        ReadResult {
             header,
             final_state: state,
             diagnostics: event_iter.diagnostics,
             observation_count,
-        })
+            snapshot_count,
+            chain: last_chain,
+        }
+    }
+
+    fn validates(&self) -> bool {
+        self.mode != ReadMode::AppendSeek
     }
 
-    fn parse_header(
+    /// Check the hash claimed on line `claimed.1` against the `state` its
+    /// observation ended up with.
+    fn verify_hashes(
+        &self,
+        verifier: &mut ChainVerifier,
+        claimed: Option<(StateHash, usize)>,
+        state: &Value,
+        diagnostics: &mut DiagnosticCollector,
+    ) {
+        if let Some((hash, line_number)) = claimed {
+            if let Some(diagnostic) = verifier.check(&hash, state) {
+                diagnostics.add(diagnostic.with_location(self.filename.clone(), line_number));
+            }
+        }
+    }
+
+    fn invalid_format_profile(&self, owner: &str, problem: String, line_number: usize) -> Diagnostic {
+        Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::FormatNotReproducible,
+            format!("The {}'s format profile is invalid: {}.", owner, problem),
+        )
+        .with_location(self.filename.clone(), line_number)
+        .with_advice(
+            "A format profile is {\"indent\": <spaces or tabs, or null>, \"newline\": <bool>}. \
+             Without a valid one, `state --original` can't print this observation."
+                .to_string(),
+        )
+    }
+
+    /// Recover mode: downgrade the fatal diagnostics the last event produced
+    /// to warnings and, unless it was only a checkpoint snapshot disagreeing
+    /// with the replay, undo the current observation. Returns true if an
+    /// observation was dropped.
+    fn roll_back_damage(
+        &self,
+        rollback: &mut Rollback,
+        state: &mut Value,
+        diagnostics: &mut DiagnosticCollector,
+        current_observation: &Option<(String, usize, usize)>,
+    ) -> bool {
+        let damage = diagnostics.downgrade_since(rollback.checked);
+        let dropped = match damage {
+            Some(DiagnosticCode::SnapshotStateMismatch) | None => false,
+            Some(_) if rollback.dropped => false,
+            Some(code) => {
+                *state = rollback.state.clone();
+                rollback.dropped = true;
+
+                let (what, line) = match current_observation {
+                    Some((obs_id, obs_line, _)) => (format!("observation '{}'", obs_id), *obs_line),
+                    None => ("the events before the first observation".to_string(), 1),
+                };
+                diagnostics.add(
+                    Diagnostic::new(
+                        DiagnosticLevel::Warning,
+                        code,
+                        format!(
+                            "I dropped {} because one of its events doesn't apply, and rolled back to the state before it.",
+                            what
+                        ),
+                    )
+                    .with_location(self.filename.clone(), line),
+                );
+                current_observation.is_some()
+            }
+        };
+        rollback.checked = diagnostics.len();
+        dropped
+    }
+
+    /// Parse and check the header line as a full `Header` or, skipping the
+    /// initial state, a `HeaderSummary`. The line is deserialized straight
+    /// into `T` so the initial state is never built twice, which means telling
+    /// a missing `initial` apart from other problems by serde's message.
+    fn parse_header<T: HeaderLine>(
         &self,
         line: &str,
         line_number: usize,
         diagnostics: &mut DiagnosticCollector,
-    ) -> Option<Header> {
-        let value: Value = match serde_json::from_str(line) {
-            Ok(v) => v,
-            Err(e) => {
+    ) -> Option<T> {
+        let header = match serde_json::from_str::<T>(line) {
+            Ok(header) => header,
+            Err(e) if e.is_syntax() || e.is_eof() => {
                 diagnostics.add(
                     Diagnostic::new(
                         DiagnosticLevel::Fatal,
@@ -581,28 +1281,25 @@ impl ArchiveReader {
                 );
                 return None;
             }
-        };
-
-        match serde_json::from_value::<Header>(value.clone()) {
-            Ok(header) => {
-                if header.version != 1 {
-                    diagnostics.add(
-                        Diagnostic::new(
-                            DiagnosticLevel::Fatal,
-                            DiagnosticCode::UnsupportedVersion,
-                            format!("I found version {}, but I only support version 1.", header.version)
-                        )
-                        .with_location(self.filename.clone(), line_number)
-                        .with_advice(
-                            "This archive was created with a newer or older version of the format. \
-                             You may need to upgrade your tools or convert the archive."
-                                .to_string()
-                        )
-                    );
-                    return None;
-                }
-
-                Some(header)
+            Err(e) if line.trim_start().starts_with('{')
+                && e.to_string().starts_with("missing field `initial`") =>
+            {
+                diagnostics.add(
+                    Diagnostic::new(
+                        DiagnosticLevel::Fatal,
+                        DiagnosticCode::InvalidInitialState,
+                        "I couldn't find the initial state in the header.".to_string(),
+                    )
+                    .with_location(self.filename.clone(), line_number)
+                    .with_snippet(format!("{} | {}", line_number, line.trim()))
+                    .with_advice(
+                        "Every event is a change relative to the header's \"initial\" field, so \
+                         without it there is nothing to replay from. Add the state of the first \
+                         JSON file you archived as \"initial\"."
+                            .to_string(),
+                    ),
+                );
+                return None;
             }
             Err(e) => {
                 diagnostics.add(
@@ -622,13 +1319,97 @@ impl ArchiveReader {
                             .to_string(),
                     ),
                 );
-                None
+                return None;
             }
+        };
+
+        if header.version() != 1 {
+            diagnostics.add(
+                Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::UnsupportedVersion,
+                    format!("I found version {}, but I only support version 1.", header.version())
+                )
+                .with_location(self.filename.clone(), line_number)
+                .with_advice(
+                    "This archive was created with a newer or older version of the format. \
+                     You may need to upgrade your tools or convert the archive."
+                        .to_string()
+                )
+            );
+            return None;
+        }
+
+        Some(header)
+    }
+}
+
+/// What `ArchiveReader::parse_header` can parse the header line into.
+trait HeaderLine: DeserializeOwned {
+    fn version(&self) -> u32;
+}
+
+impl HeaderLine for Header {
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+impl HeaderLine for HeaderSummary {
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+/// Check that an add or change event agrees with what's already in the state:
+/// an add shouldn't overwrite an existing object key, and a change needs a value
+/// there to change. Both still apply, so this only ever warns.
+fn check_old_value(state: &Value, event_type: &str, path: &str) -> Option<Diagnostic> {
+    let pointer = JsonPointer::new(path).ok()?;
+    let exists = pointer.get(state).is_ok();
+
+    if event_type == "add" && exists {
+        // Adding into an array shifts elements rather than replacing them
+        let parent_is_object = pointer
+            .parent()
+            .and_then(|parent| parent.get(state).ok().map(|v| v.is_object()))
+            .unwrap_or(false);
+        if !parent_is_object {
+            return None;
         }
+        return Some(
+            Diagnostic::new(
+                DiagnosticLevel::Warning,
+                DiagnosticCode::OldValueMismatch,
+                format!("I found an add event for '{}', but that key already has a value.", path),
+            )
+            .with_advice(
+                "Use a change event to replace an existing value. An add event should only \
+                 introduce a key that isn't there yet."
+                    .to_string(),
+            ),
+        );
+    }
+
+    if event_type == "change" && !exists {
+        return Some(
+            Diagnostic::new(
+                DiagnosticLevel::Warning,
+                DiagnosticCode::OldValueMismatch,
+                format!("I found a change event for '{}', but there was no value there to change.", path),
+            )
+            .with_advice(
+                "Use an add event to introduce a new value. A change event should only \
+                 replace a value that already exists."
+                    .to_string(),
+            ),
+        );
     }
 
+    None
 }
 
+#[allow(clippy::result_large_err)]
 pub fn apply_add(state: &mut Value, path: &str, value: Value) -> Result<(), Diagnostic> {
     let pointer = JsonPointer::new(path).map_err(|diag| {
         diag.with_advice(
@@ -647,18 +1428,21 @@ pub fn apply_add(state: &mut Value, path: &str, value: Value) -> Result<(), Diag
     })
 }
 
+#[allow(clippy::result_large_err)]
 pub fn apply_change(state: &mut Value, path: &str, new_value: Value) -> Result<(), Diagnostic> {
     let pointer = JsonPointer::new(path)?;
     pointer.set(state, new_value)?;
     Ok(())
 }
 
+#[allow(clippy::result_large_err)]
 pub fn apply_remove(state: &mut Value, path: &str) -> Result<(), Diagnostic> {
     let pointer = JsonPointer::new(path)?;
     pointer.remove(state)?;
     Ok(())
 }
 
+#[allow(clippy::result_large_err)]
 pub fn apply_move(
     state: &mut Value,
     path: &str,
@@ -832,6 +1616,225 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_snapshot_timestamp_order() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+
+        let header = Header::new(json!({"count": 0}), None);
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(
+            temp_file,
+            r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1]"#
+        )?;
+        writeln!(temp_file, r#"["change", "/count", 1, "obs-1"]"#)?;
+        writeln!(
+            temp_file,
+            r#"["snapshot", "obs-1", "2025-01-01T00:00:00Z", {{"count": 1}}]"#
+        )?;
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::FullValidation)?;
+        let result = reader.read(temp_file.path())?;
+
+        assert!(!result.diagnostics.has_fatal());
+        assert_eq!(result.snapshot_count, 1);
+        assert_eq!(result.observation_count, 1);
+        assert!(result
+            .diagnostics
+            .diagnostics()
+            .iter()
+            .any(|d| d.code == DiagnosticCode::SnapshotTimestampOrder));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_observation_timestamp_order() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+
+        let header = Header::new(json!({"count": 0}), None);
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(
+            temp_file,
+            r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1]"#
+        )?;
+        writeln!(temp_file, r#"["change", "/count", 1, "obs-1"]"#)?;
+        writeln!(
+            temp_file,
+            r#"["observe", "obs-2", "2025-01-01T00:00:00Z", 1]"#
+        )?;
+        writeln!(temp_file, r#"["change", "/count", 2, "obs-2"]"#)?;
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::FullValidation)?;
+        let result = reader.read(temp_file.path())?;
+
+        assert!(!result.diagnostics.has_fatal());
+        assert_eq!(result.final_state, json!({"count": 2}));
+        let warnings: Vec<_> = result
+            .diagnostics
+            .diagnostics()
+            .iter()
+            .filter(|d| d.code == DiagnosticCode::ObservationTimestampOrder)
+            .collect();
+        assert_eq!(warnings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_observation_heads() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+
+        let mut header = Header::new(json!({"count": 0}), Some("test".to_string()));
+        header.metadata = Some(json!({"owner": "ops"}));
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(temp_file, r#"["observe", "obs-1", "2025-01-01T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 1, "obs-1"]"#)?;
+        writeln!(temp_file, r#"["snapshot", "obs-1", "2025-01-01T00:00:00Z", {{"count": 1}}]"#)?;
+        writeln!(temp_file, r#"["snapshot", "obs-2", "2025-01-02T00:00:00Z", {{"count": 2}}]"#)?;
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::AppendSeek)?;
+        let mut heads = reader.observation_heads(temp_file.path())?;
+
+        assert!(!heads.diagnostics.has_fatal());
+        assert_eq!(heads.header.source.as_deref(), Some("test"));
+        assert_eq!(heads.header.metadata, Some(json!({"owner": "ops"})));
+
+        let observations: Vec<(String, usize, usize)> = heads
+            .by_ref()
+            .map(|head| (head.observation_id, head.change_count, head.line_number))
+            .collect();
+        assert_eq!(
+            observations,
+            vec![("obs-1".to_string(), 1, 2), ("obs-2".to_string(), 0, 5)]
+        );
+        assert_eq!(heads.snapshot_count, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_observation_heads_stops_on_invalid_utf8() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+
+        let header = Header::new(json!({"count": 0}), None);
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(temp_file, r#"["observe", "obs-1", "2025-01-01T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 1, "obs-1"]"#)?;
+        temp_file.write_all(b"[\"change\", \"/count\", \"\xff\", \"obs-1\"]\n")?;
+        writeln!(temp_file, r#"["observe", "obs-2", "2025-01-02T00:00:00Z", 0]"#)?;
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::AppendSeek)?;
+        let mut heads = reader.observation_heads(temp_file.path())?;
+
+        let ids: Vec<String> = heads.by_ref().map(|head| head.observation_id).collect();
+        assert_eq!(ids, vec!["obs-1".to_string()]);
+        assert!(heads.diagnostics.has_fatal());
+        assert_eq!(heads.diagnostics.diagnostics()[0].code, DiagnosticCode::InvalidUtf8);
+        assert_eq!(heads.diagnostics.diagnostics()[0].line_number, Some(4));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_observation_heads_requires_initial() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+        writeln!(
+            temp_file,
+            r#"{{"type": "@peoplesgrocers/json-archive", "version": 1, "created": "2025-01-01T00:00:00Z"}}"#
+        )?;
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::AppendSeek)?;
+        let heads = reader.observation_heads(temp_file.path())?;
+
+        assert!(heads.diagnostics.has_fatal());
+        assert_eq!(
+            heads.diagnostics.diagnostics()[0].code,
+            DiagnosticCode::InvalidInitialState
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_drops_damaged_observations() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+
+        let header = Header::new(json!({"count": 0, "name": "a"}), None);
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(temp_file, r#"["observe", "obs-1", "2025-01-01T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 1, "obs-1"]"#)?;
+        // Damaged line: obs-2 goes, including the change before it
+        writeln!(temp_file, r#"["observe", "obs-2", "2025-01-02T00:00:00Z", 2]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 2, "obs-2"]"#)?;
+        writeln!(temp_file, r#"["change", "/name" "b", "obs-2"]"#)?;
+        // Event that doesn't apply: obs-3 is rolled back
+        writeln!(temp_file, r#"["observe", "obs-3", "2025-01-03T00:00:00Z", 2]"#)?;
+        writeln!(temp_file, r#"["change", "/name", "c", "obs-3"]"#)?;
+        writeln!(temp_file, r#"["remove", "/missing", "obs-3"]"#)?;
+        writeln!(temp_file, r#"["observe", "obs-4", "2025-01-04T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["add", "/x", 4, "obs-4"]"#)?;
+        // Interrupted write: obs-5 is short a change and its last line is cut off
+        writeln!(temp_file, r#"["observe", "obs-5", "2025-01-05T00:00:00Z", 2]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 5, "obs-5"]"#)?;
+        write!(temp_file, r#"["change", "/name", "e""#)?;
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::FullValidation)?;
+        assert!(reader.read(temp_file.path())?.diagnostics.has_fatal());
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::Recover)?;
+        let result = reader.read(temp_file.path())?;
+
+        assert!(!result.diagnostics.has_fatal());
+        assert_eq!(result.final_state, json!({"count": 1, "name": "a", "x": 4}));
+        assert_eq!(result.observation_count, 2);
+
+        let dropped: Vec<_> = result
+            .diagnostics
+            .diagnostics()
+            .iter()
+            .filter(|d| d.description.starts_with("I dropped"))
+            .map(|d| d.line_number)
+            .collect();
+        assert_eq!(dropped, vec![Some(4), Some(7), Some(12)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diagnostic_columns() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+
+        let header = Header::new(json!({"count": 0}), None);
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(
+            temp_file,
+            r#"["observe", "obs-1", "2025-01-01T00:00:00Z", 2]"#
+        )?;
+        writeln!(temp_file, r#"["add", "/count", 1, 7]"#)?;
+        writeln!(temp_file, r#"["change", "/count" 1, "obs-1"]"#)?;
+
+        let reader = ArchiveReader::new(temp_file.path(), ReadMode::FullValidation)?;
+        let result = reader.read(temp_file.path())?;
+        let diagnostics = result.diagnostics.diagnostics();
+
+        let wrong_type = diagnostics
+            .iter()
+            .find(|d| d.code == DiagnosticCode::WrongFieldType)
+            .unwrap();
+        assert_eq!(wrong_type.line_number, Some(3));
+        assert_eq!(wrong_type.column, Some(22));
+        assert!(wrong_type.code_snippet.as_ref().unwrap().ends_with("|                      ^"));
+
+        let bad_json = diagnostics
+            .iter()
+            .find(|d| d.code == DiagnosticCode::InvalidEventJson)
+            .unwrap();
+        assert_eq!(bad_json.line_number, Some(4));
+        assert_eq!(bad_json.column, Some(21));
+
+        Ok(())
+    }
+
     #[test]
     fn test_simple_change() -> Result<(), Box<dyn std::error::Error>> {
         let mut temp_file = NamedTempFile::new()?;
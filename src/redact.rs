@@ -0,0 +1,383 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Removing a path from an archive's entire history.
+//!
+//! When a secret gets captured, deleting it from the current state isn't
+//! enough: it is still in the header's initial state, in snapshots, and in
+//! the add and change events that wrote it. Every observation's full state is
+//! rebuilt, the path is removed from each, and consecutive states are diffed
+//! again, so nothing that mentioned the value survives.
+
+use chrono::Utc;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+use crate::archive::write_replacement;
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticLevel};
+use crate::merge::write_observations;
+use crate::pointer::remove_key;
+use crate::reader::ReadMode;
+use crate::replay::{observation_states, require_intact, ObservationState};
+
+/// A JSON pointer in which a `*` token matches any object key or array index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathPattern {
+    text: String,
+    tokens: Vec<String>,
+}
+
+impl PathPattern {
+    #[allow(clippy::result_large_err)]
+    pub fn new(path: &str) -> Result<Self, Diagnostic> {
+        let Some(rest) = path.strip_prefix('/') else {
+            let problem = if path.is_empty() {
+                "The empty path is the whole document, and I can't redact all of it.".to_string()
+            } else {
+                format!("I couldn't parse the path '{}': Path must start with '/'", path)
+            };
+            return Err(Diagnostic::new(DiagnosticLevel::Fatal, DiagnosticCode::InvalidPointerSyntax, problem)
+                .with_advice("Give a JSON pointer like /cookies or /http_headers/Authorization. A * matches any key, as in /users/*/password.".to_string()));
+        };
+
+        let tokens = rest
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect();
+
+        Ok(PathPattern {
+            text: path.to_string(),
+            tokens,
+        })
+    }
+
+    /// Remove every value the pattern matches from `value`, returning how
+    /// many were removed.
+    pub fn remove_from(&self, value: &mut Value) -> usize {
+        remove_matches(value, &self.tokens)
+    }
+}
+
+impl std::fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn remove_matches(value: &mut Value, tokens: &[String]) -> usize {
+    let Some((token, rest)) = tokens.split_first() else {
+        return 0;
+    };
+    let wildcard = token == "*";
+
+    match value {
+        Value::Object(map) if rest.is_empty() => {
+            if wildcard {
+                std::mem::take(map).len()
+            } else {
+                usize::from(remove_key(map, token).is_some())
+            }
+        }
+        Value::Object(map) => {
+            if wildcard {
+                map.values_mut().map(|child| remove_matches(child, rest)).sum()
+            } else {
+                map.get_mut(token).map_or(0, |child| remove_matches(child, rest))
+            }
+        }
+        Value::Array(items) if rest.is_empty() => {
+            if wildcard {
+                std::mem::take(items).len()
+            } else {
+                match token.parse::<usize>() {
+                    Ok(index) if index < items.len() => {
+                        items.remove(index);
+                        1
+                    }
+                    _ => 0,
+                }
+            }
+        }
+        Value::Array(items) => {
+            if wildcard {
+                items.iter_mut().map(|child| remove_matches(child, rest)).sum()
+            } else {
+                token
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| items.get_mut(index))
+                    .map_or(0, |child| remove_matches(child, rest))
+            }
+        }
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedactSummary {
+    /// Observations written, counting the header's state.
+    pub observations: usize,
+    /// Observations that had something removed.
+    pub redacted: usize,
+    /// Observations whose only changes were to redacted paths, which are
+    /// kept but no longer change anything.
+    pub emptied: usize,
+}
+
+/// Remove everything `patterns` match from every state in the archive at
+/// `path`.
+///
+/// Observation ids, timestamps and metadata are kept, including observations
+/// left with nothing to change. The header's metadata gains a `redactions`
+/// entry listing the patterns and when they were removed, but not the
+/// values. The rewritten archive is replayed and compared with the redacted
+/// states before it replaces anything. A hashed archive gets a new hash
+/// chain over the redacted states, unless `drop_hashes` is set, which
+/// writes it without hashes.
+///
+/// The result goes to `output_path` if given, otherwise it replaces the
+/// archive. If no pattern matches anything the archive is left alone.
+pub fn redact_archive<P: AsRef<Path>>(
+    path: P,
+    output_path: Option<&Path>,
+    patterns: &[PathPattern],
+    snapshot_interval: Option<usize>,
+    drop_hashes: bool,
+) -> Result<RedactSummary, Vec<Diagnostic>> {
+    let path = path.as_ref();
+    let mut header = require_intact(path, "redact")?;
+
+    let mut summary = RedactSummary {
+        observations: 0,
+        redacted: 0,
+        emptied: 0,
+    };
+    let mut observations: Vec<ObservationState> = Vec::new();
+    let mut previous: Option<(Value, Value)> = None;
+    for mut observation in observation_states(path, ReadMode::AppendSeek)? {
+        let unredacted = observation.state.clone();
+        let removed: usize = patterns
+            .iter()
+            .map(|pattern| pattern.remove_from(&mut observation.state))
+            .sum();
+
+        summary.observations += 1;
+        if removed > 0 {
+            summary.redacted += 1;
+        }
+        if let Some((previous_unredacted, previous_state)) = &previous {
+            if *previous_unredacted != unredacted && *previous_state == observation.state {
+                summary.emptied += 1;
+            }
+        }
+
+        previous = Some((unredacted, observation.state.clone()));
+        observations.push(observation);
+    }
+
+    if summary.redacted == 0 {
+        let paths: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        return Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I didn't find {} in any observation of {}, so there's nothing to redact.", paths.join(" or "), path.display()),
+        )
+        .with_advice("Check the path with `json-archive state` first. Keys are case sensitive.".to_string())]);
+    }
+
+    let Some(first) = observations.first() else {
+        return Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::EmptyFile,
+            format!("I didn't find any observations in {}.", path.display()),
+        )]);
+    };
+
+    header.initial = first.state.clone();
+    header.metadata = Some(record_redaction(header.metadata.take(), patterns));
+    if drop_hashes {
+        header.hash = None;
+    }
+
+    write_replacement(path, output_path, |output_path| {
+        write_observations(output_path, &header, observations.iter().skip(1).cloned(), snapshot_interval)?;
+        verify(output_path, &observations)
+    })?;
+
+    Ok(summary)
+}
+
+/// Add an entry for `patterns` to the header metadata's `redactions` list.
+fn record_redaction(metadata: Option<Value>, patterns: &[PathPattern]) -> Value {
+    let mut metadata = match metadata {
+        None => Map::new(),
+        Some(Value::Object(metadata)) => metadata,
+        Some(other) => Map::from_iter([("metadata".to_string(), other)]),
+    };
+
+    let redactions = metadata.entry("redactions").or_insert_with(|| json!([]));
+    if !redactions.is_array() {
+        *redactions = json!([redactions.take()]);
+    }
+    if let Value::Array(redactions) = redactions {
+        let redacted_at = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        redactions.extend(patterns.iter().map(|pattern| {
+            json!({
+                "path": pattern.to_string(),
+                "redacted_at": redacted_at,
+            })
+        }));
+    }
+
+    Value::Object(metadata)
+}
+
+/// Replay the rewritten archive and check it has exactly the expected states.
+fn verify(output_path: &Path, expected: &[ObservationState]) -> Result<(), Vec<Diagnostic>> {
+    let mismatch = |detail: String| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::InvalidEventJson,
+            format!("I replayed the redacted archive and {}, so I didn't keep it.", detail),
+        )
+        .with_advice("This is a bug in json-archive. Please report it; the original archive is unchanged.".to_string())]
+    };
+
+    let mut replayed = observation_states(output_path, ReadMode::FullValidation)?;
+    for expected in expected {
+        match replayed.next() {
+            Some(actual) if actual.state == expected.state => {}
+            Some(actual) => {
+                return Err(mismatch(format!(
+                    "observation {} came out different from what I wrote",
+                    actual.observation_id
+                )))
+            }
+            None => return Err(mismatch("it had fewer observations than I wrote".to_string())),
+        }
+    }
+    if replayed.next().is_some() {
+        return Err(mismatch("it had more observations than I wrote".to_string()));
+    }
+    if replayed.diagnostics().has_fatal() {
+        return Err(replayed.into_diagnostics().into_diagnostics());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Header;
+    use crate::merge::{assert_hash_chain, hashed_copy};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_path_pattern_remove_from() {
+        let mut value = json!({
+            "cookies": {"session": "abc"},
+            "users": [
+                {"name": "ada", "password": "x"},
+                {"name": "bob", "password": "y"},
+                {"name": "cy"}
+            ],
+            "tags": ["a", "b"]
+        });
+
+        assert_eq!(PathPattern::new("/cookies").unwrap().remove_from(&mut value), 1);
+        assert_eq!(PathPattern::new("/users/*/password").unwrap().remove_from(&mut value), 2);
+        assert_eq!(PathPattern::new("/tags/0").unwrap().remove_from(&mut value), 1);
+        assert_eq!(PathPattern::new("/missing/*").unwrap().remove_from(&mut value), 0);
+        assert_eq!(
+            value,
+            json!({
+                "users": [{"name": "ada"}, {"name": "bob"}, {"name": "cy"}],
+                "tags": ["b"]
+            })
+        );
+
+        assert!(PathPattern::new("").is_err());
+        assert!(PathPattern::new("cookies").is_err());
+    }
+
+    #[test]
+    fn test_redact_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let mut archive = NamedTempFile::new()?;
+        let header = Header::new(json!({"count": 0, "token": "secret-0"}), None);
+        writeln!(archive, "{}", serde_json::to_string(&header)?)?;
+        for line in [
+            r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1]"#,
+            r#"["change", "/token", "secret-1", "obs-1"]"#,
+            r#"["observe", "obs-2", "2025-01-03T00:00:00Z", 1]"#,
+            r#"["change", "/count", 2, "obs-2"]"#,
+            r#"["snapshot", "obs-2", "2025-01-03T00:00:00Z", {"count": 2, "token": "secret-1"}]"#,
+        ] {
+            writeln!(archive, "{}", line)?;
+        }
+
+        let patterns = [PathPattern::new("/token").unwrap()];
+        let summary = redact_archive(archive.path(), None, &patterns, None, false).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(summary, RedactSummary { observations: 3, redacted: 3, emptied: 1 });
+
+        let contents = std::fs::read_to_string(archive.path())?;
+        assert!(!contents.contains("secret"));
+
+        let states = observation_states(archive.path(), ReadMode::FullValidation).map_err(|d| format!("{:?}", d))?;
+        let metadata = states.header().metadata.clone().unwrap();
+        assert_eq!(metadata["redactions"][0]["path"], json!("/token"));
+        let states: Vec<_> = states
+            .map(|observation| (observation.observation_id, observation.state))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("initial".to_string(), json!({"count": 0})),
+                ("obs-1".to_string(), json!({"count": 0})),
+                ("obs-2".to_string(), json!({"count": 2})),
+            ]
+        );
+
+        // Nothing left to find the second time
+        assert!(redact_archive(archive.path(), None, &patterns, None, false).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redact_hashed_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let mut archive = NamedTempFile::new()?;
+        let mut header = Header::new(json!({"count": 0, "token": "secret-0"}), None);
+        header.created = "2025-01-01T00:00:00Z".parse()?;
+        writeln!(archive, "{}", serde_json::to_string(&header)?)?;
+        writeln!(archive, r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1]"#)?;
+        writeln!(archive, r#"["change", "/token", "secret-1", "obs-1"]"#)?;
+        let archive = hashed_copy(archive.path())?;
+
+        let patterns = [PathPattern::new("/token").unwrap()];
+        redact_archive(archive.path(), None, &patterns, None, false).map_err(|d| format!("{:?}", d))?;
+        assert!(!std::fs::read_to_string(archive.path())?.contains("secret"));
+        assert_hash_chain(archive.path())?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,418 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Replaying an archive one observation at a time.
+//!
+//! `resolve` reconstructs a single observation. Commands that rewrite an
+//! archive, like `merge`, need every observation's state in turn instead.
+//! `ObservationStates` yields them in file order, starting with the header's
+//! state as "initial". Only the working state is held; each yielded state is
+//! the caller's to keep or drop.
+//!
+//! `states` does the same for callers with their own types for the document,
+//! deserializing each state as it comes.
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticCollector, DiagnosticLevel};
+use crate::events::{Event, Header};
+use crate::reader::{
+    apply_add, apply_change, apply_move, apply_remove, is_observation_snapshot, ArchiveReader, EventIterator, ReadMode,
+};
+
+/// The complete state after one observation.
+#[derive(Debug, Clone)]
+pub struct ObservationState {
+    pub observation_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// The metadata recorded with the observation. `None` for the initial
+    /// state.
+    pub metadata: Option<Map<String, Value>>,
+    pub state: Value,
+}
+
+impl ObservationState {
+    /// Split into the observation's details and its state.
+    pub fn into_parts(self) -> (ObservationMeta, Value) {
+        let meta = ObservationMeta {
+            observation_id: self.observation_id,
+            timestamp: self.timestamp,
+            metadata: self.metadata,
+        };
+        (meta, self.state)
+    }
+}
+
+/// Everything about an observation except its state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObservationMeta {
+    pub observation_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// The metadata recorded with the observation. `None` for the initial
+    /// state.
+    pub metadata: Option<Map<String, Value>>,
+}
+
+pub struct ObservationStates {
+    events: EventIterator,
+    state: Value,
+    /// The observation being replayed and the line it starts on. `None` once
+    /// every observation has been yielded.
+    current: Option<(ObservationMeta, usize)>,
+    /// The event that starts `current`, held back until the state before it
+    /// has been taken.
+    pending: Option<Event>,
+    seen: HashSet<String>,
+}
+
+/// Open `path` for replaying observation by observation. Events that don't
+/// apply are skipped, as in `ReadMode::AppendSeek`; read the archive with
+/// `ArchiveReader::read` first if it needs validating. Fatal problems with
+/// the header are returned here, and problems found along the way end up in
+/// `diagnostics()`.
+pub fn observation_states<P: AsRef<Path>>(path: P, mode: ReadMode) -> Result<ObservationStates, Vec<Diagnostic>> {
+    let path = path.as_ref();
+    let reader = ArchiveReader::new(path, mode).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't open the archive file: {}", e),
+        )]
+    })?;
+
+    let (state, events) = reader.events(path).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't read the archive file: {}", e),
+        )]
+    })?;
+
+    ObservationStates::from_events(state, events)
+}
+
+/// Read an archive in full, refusing it if it is damaged. Commands that
+/// rewrite an archive call this first: replaying skips what doesn't apply,
+/// so rewriting a damaged archive would quietly lose it. `action` completes
+/// "so I can't ... it" in the message.
+pub(crate) fn require_intact(path: &Path, action: &str) -> Result<Header, Vec<Diagnostic>> {
+    let read_result = ArchiveReader::new(path, ReadMode::FullValidation)
+        .and_then(|reader| reader.read(path))
+        .map_err(|e| {
+            vec![Diagnostic::new(
+                DiagnosticLevel::Fatal,
+                DiagnosticCode::PathNotFound,
+                format!("I couldn't read the archive {}: {}", path.display(), e),
+            )]
+        })?;
+
+    if read_result.diagnostics.has_fatal() {
+        let mut diagnostics = vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::InvalidEventJson,
+            format!("The archive {} contains fatal errors, so I can't {} it.", path.display(), action),
+        )
+        .with_advice(format!(
+            "Run `json-archive validate {}` to see what's wrong with it.",
+            path.display()
+        ))];
+        diagnostics.extend(read_result.diagnostics.into_diagnostics());
+        return Err(diagnostics);
+    }
+
+    Ok(read_result.header)
+}
+
+impl ObservationStates {
+    /// Replay events already opened with `ArchiveReader::events` or
+    /// `ArchiveReader::events_from`, starting from the header's `state`.
+    pub fn from_events(state: Value, events: EventIterator) -> Result<ObservationStates, Vec<Diagnostic>> {
+        if events.diagnostics.has_fatal() {
+            return Err(events.diagnostics.into_diagnostics());
+        }
+
+        let initial = ObservationMeta {
+            observation_id: "initial".to_string(),
+            timestamp: events.header.created,
+            metadata: None,
+        };
+        Ok(ObservationStates {
+            events,
+            state,
+            current: Some((initial, 1)),
+            pending: None,
+            seen: HashSet::new(),
+        })
+    }
+
+    /// Deserialize each state as `T`, see `states`.
+    pub fn typed<T: DeserializeOwned>(self) -> States<T> {
+        States {
+            inner: self,
+            _type: PhantomData,
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.events.header
+    }
+
+    pub fn diagnostics(&self) -> &DiagnosticCollector {
+        &self.events.diagnostics
+    }
+
+    pub fn into_diagnostics(self) -> DiagnosticCollector {
+        self.events.diagnostics
+    }
+
+    /// Replay the rest of the current observation without copying its state,
+    /// which stays put until the next call. Returns the observation and the
+    /// line it starts on, or `None` once there are no more.
+    pub(crate) fn replay_observation(&mut self) -> Option<(ObservationMeta, usize)> {
+        let finished = self.current.take()?;
+        if let Some(event) = self.pending.take() {
+            self.apply(event);
+        }
+
+        while let Some(event) = self.events.next() {
+            let next = match &event {
+                Event::Observe { observation_id, timestamp, metadata, .. } => Some(ObservationMeta {
+                    observation_id: observation_id.clone(),
+                    timestamp: *timestamp,
+                    metadata: metadata.clone(),
+                }),
+                Event::Snapshot { observation_id, timestamp, metadata, .. }
+                    if is_observation_snapshot(observation_id, Some(&finished.0.observation_id), &self.seen) =>
+                {
+                    Some(ObservationMeta {
+                        observation_id: observation_id.clone(),
+                        timestamp: *timestamp,
+                        metadata: metadata.clone(),
+                    })
+                }
+                _ => None,
+            };
+
+            // The next observation starting means this one is complete
+            if let Some(next) = next {
+                self.seen.insert(next.observation_id.clone());
+                self.current = Some((next, self.events.event_line()));
+                self.pending = Some(event);
+                return Some(finished);
+            }
+
+            self.apply(event);
+        }
+
+        Some(finished)
+    }
+
+    /// The state of the observation `replay_observation` last returned, and
+    /// the problems found up to its end.
+    pub(crate) fn into_state(self) -> (Value, DiagnosticCollector) {
+        (self.state, self.events.diagnostics)
+    }
+
+    fn apply(&mut self, event: Event) {
+        apply_event(&mut self.state, event);
+    }
+}
+
+/// Apply one event to `state`, skipping it if it doesn't apply.
+pub(crate) fn apply_event(state: &mut Value, event: Event) {
+    match event {
+        Event::Observe { .. } => {}
+        Event::Add { path, value, .. } => {
+            let _ = apply_add(state, &path, value);
+        }
+        Event::Change { path, new_value, .. } => {
+            let _ = apply_change(state, &path, new_value);
+        }
+        Event::Remove { path, .. } => {
+            let _ = apply_remove(state, &path);
+        }
+        Event::Move { path, moves, .. } => {
+            let _ = apply_move(state, &path, moves);
+        }
+        Event::Snapshot { object, .. } => {
+            *state = object;
+        }
+    }
+}
+
+impl Iterator for ObservationStates {
+    type Item = ObservationState;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (meta, _line) = self.replay_observation()?;
+        // The last one can have the state outright
+        let state = if self.current.is_some() {
+            self.state.clone()
+        } else {
+            std::mem::take(&mut self.state)
+        };
+
+        Some(ObservationState {
+            observation_id: meta.observation_id,
+            timestamp: meta.timestamp,
+            metadata: meta.metadata,
+            state,
+        })
+    }
+}
+
+/// Every observation's state in file order, deserialized as `T`.
+pub struct States<T> {
+    inner: ObservationStates,
+    _type: PhantomData<fn() -> T>,
+}
+
+/// Open `path` for replaying observation by observation, like
+/// `observation_states`, with each state deserialized as `T`. Each event is
+/// applied once, so going through every state costs one read of the archive.
+///
+/// A state that doesn't fit `T`, say from before a field was added, is an
+/// `Err` item; the ones after it are still yielded.
+pub fn states<T: DeserializeOwned, P: AsRef<Path>>(path: P, mode: ReadMode) -> Result<States<T>, Vec<Diagnostic>> {
+    Ok(observation_states(path, mode)?.typed())
+}
+
+impl<T> States<T> {
+    pub fn header(&self) -> &Header {
+        self.inner.header()
+    }
+
+    pub fn diagnostics(&self) -> &DiagnosticCollector {
+        self.inner.diagnostics()
+    }
+
+    pub fn into_diagnostics(self) -> DiagnosticCollector {
+        self.inner.into_diagnostics()
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for States<T> {
+    type Item = Result<(ObservationMeta, T), Diagnostic>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (meta, state) = self.inner.next()?.into_parts();
+        Some(deserialize_state(&meta.observation_id, state).map(|state| (meta, state)))
+    }
+}
+
+/// Deserialize the state at `observation_id` as `T`.
+#[allow(clippy::result_large_err)]
+pub(crate) fn deserialize_state<T: DeserializeOwned>(observation_id: &str, state: Value) -> Result<T, Diagnostic> {
+    serde_json::from_value(state).map_err(|e| {
+        Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::TypeMismatch,
+            format!(
+                "I couldn't read the state at observation '{}' as {}: {}",
+                observation_id,
+                std::any::type_name::<T>(),
+                e
+            ),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_observation_states() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+        let mut header = Header::new(json!({"count": 0}), None);
+        header.created = "2025-01-01T00:00:00Z".parse()?;
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(temp_file, r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1, {{"file": "a.json"}}]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 1, "obs-1"]"#)?;
+        writeln!(temp_file, r#"["snapshot", "obs-1", "2025-01-02T00:00:00Z", {{"count": 1}}]"#)?;
+        writeln!(temp_file, r#"["snapshot", "obs-2", "2025-01-03T00:00:00Z", {{"count": 2}}]"#)?;
+        writeln!(temp_file, r#"["observe", "obs-3", "2025-01-04T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["add", "/extra", true, "obs-3"]"#)?;
+
+        let states: Vec<_> = observation_states(temp_file.path(), ReadMode::AppendSeek)
+            .map_err(|d| format!("{:?}", d))?
+            .map(|observation| (observation.observation_id, observation.state, observation.metadata))
+            .collect();
+
+        assert_eq!(
+            states,
+            vec![
+                ("initial".to_string(), json!({"count": 0}), None),
+                ("obs-1".to_string(), json!({"count": 1}), Some(Map::from_iter([("file".to_string(), json!("a.json"))]))),
+                ("obs-2".to_string(), json!({"count": 2}), None),
+                ("obs-3".to_string(), json!({"count": 2, "extra": true}), None),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_states() -> Result<(), Box<dyn std::error::Error>> {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Video {
+            title: String,
+            views: u64,
+        }
+
+        let mut temp_file = NamedTempFile::new()?;
+        let header = Header::new(json!({"title": "Intro", "views": 1}), None);
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(temp_file, r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["remove", "/views", "obs-1"]"#)?;
+        writeln!(temp_file, r#"["observe", "obs-2", "2025-01-03T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["add", "/views", 12345678901, "obs-2"]"#)?;
+
+        let states: Vec<_> = states::<Video, _>(temp_file.path(), ReadMode::AppendSeek)
+            .map_err(|d| format!("{:?}", d))?
+            .collect();
+
+        assert_eq!(states.len(), 3);
+        let (meta, video) = states[0].as_ref().map_err(|d| d.description.clone())?;
+        assert_eq!(meta.observation_id, "initial");
+        assert_eq!(*video, Video { title: "Intro".to_string(), views: 1 });
+
+        // A state that doesn't fit is reported without ending the iteration
+        let error = states[1].as_ref().err().ok_or("obs-1 has no views, but was read as a Video")?;
+        assert_eq!(error.code, DiagnosticCode::TypeMismatch);
+        assert!(error.description.contains("obs-1"), "{}", error.description);
+
+        let (meta, video) = states[2].as_ref().map_err(|d| d.description.clone())?;
+        assert_eq!(meta.observation_id, "obs-2");
+        assert_eq!(meta.timestamp, "2025-01-03T00:00:00Z".parse::<DateTime<Utc>>()?);
+        assert_eq!(video.views, 12345678901);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,419 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Reconstructing the state at one observation.
+//!
+//! The obvious way is to replay the archive while remembering every
+//! observation's state, then pick one. That keeps a full copy of the document
+//! per observation, which doesn't fit in memory for large documents with long
+//! histories. Instead we decide up front which observation is wanted, replay
+//! into a single state, and stop as soon as that observation is complete.
+//!
+//! `--id` and `--index` can be decided while replaying. The timestamp
+//! selectors can't, because observations aren't in chronological order, so
+//! they first make a cheap pass over the observe and snapshot lines to find
+//! the target's line number.
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticCollector, DiagnosticLevel};
+use crate::events::HeaderSummary;
+use crate::reader::{ArchiveReader, ObservationHead, ReadMode};
+use crate::replay::{deserialize_state, ObservationMeta, ObservationStates};
+
+/// Which observation's state to reconstruct.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateSelector {
+    /// The first observation with this id. "initial" is the header's state.
+    Id(String),
+    /// The Nth observation in file order, where 0 is the header's state.
+    Index(usize),
+    /// Most recent observation with timestamp <= the given time.
+    AsOf(DateTime<Utc>),
+    /// Most recent observation with timestamp < the given time.
+    Before(DateTime<Utc>),
+    /// Earliest observation with timestamp > the given time.
+    After(DateTime<Utc>),
+    /// Most recent observation by timestamp.
+    Latest,
+}
+
+#[derive(Debug)]
+pub struct ResolvedState {
+    pub header: HeaderSummary,
+    pub observation_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// The metadata recorded with the observation. `None` for the initial
+    /// state and for observations recorded without any.
+    pub metadata: Option<Map<String, Value>>,
+    pub state: Value,
+    /// Problems found while replaying. Apply errors are not reported, matching
+    /// `ReadMode::AppendSeek`; in `ReadMode::Recover` these are the warnings
+    /// about dropped observations.
+    pub diagnostics: DiagnosticCollector,
+}
+
+/// How the replay recognizes the target once the selector has been decided.
+enum Target<'a> {
+    Id(&'a str),
+    Index(usize),
+    Line(usize),
+}
+
+impl Target<'_> {
+    /// Whether the observation at `index` in file order, starting on `line`,
+    /// is the target.
+    fn matches(&self, index: usize, observation: &ObservationMeta, line: usize) -> bool {
+        match self {
+            Target::Id(id) => observation.observation_id == *id,
+            Target::Index(target) => index == *target,
+            Target::Line(target) => line == *target,
+        }
+    }
+}
+
+/// The state at the observation `selector` picks, deserialized as `T`.
+pub fn state_at<T: DeserializeOwned, P: AsRef<Path>>(
+    path: P,
+    selector: &StateSelector,
+    mode: ReadMode,
+) -> Result<T, Vec<Diagnostic>> {
+    let resolved = resolve_state(path, selector, mode)?;
+    deserialize_state(&resolved.observation_id, resolved.state).map_err(|diagnostic| vec![diagnostic])
+}
+
+pub fn resolve_state<P: AsRef<Path>>(
+    path: P,
+    selector: &StateSelector,
+    mode: ReadMode,
+) -> Result<ResolvedState, Vec<Diagnostic>> {
+    let path = path.as_ref();
+    let reader = ArchiveReader::new(path, mode).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't open the archive file: {}", e),
+        )]
+    })?;
+
+    let target = match selector {
+        StateSelector::Id(id) => Target::Id(id),
+        StateSelector::Index(index) => Target::Index(*index),
+        _ => Target::Line(find_line_by_timestamp(&reader, path, selector)?),
+    };
+
+    replay_to(&reader, path, &target, selector)
+}
+
+/// First pass for the timestamp selectors: read just the observe and snapshot
+/// lines, skipping the initial state, and pick the target's line. The header counts as an observation at
+/// line 1, timestamped when the archive was created.
+fn find_line_by_timestamp(
+    reader: &ArchiveReader,
+    path: &Path,
+    selector: &StateSelector,
+) -> Result<usize, Vec<Diagnostic>> {
+    let mut observation_heads = reader.observation_heads(path).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't read the archive file: {}", e),
+        )]
+    })?;
+    if observation_heads.diagnostics.has_fatal() {
+        return Err(observation_heads.diagnostics.into_diagnostics());
+    }
+
+    let initial = ObservationHead {
+        observation_id: "initial".to_string(),
+        timestamp: observation_heads.header.created,
+        change_count: 0,
+        metadata: None,
+        line_number: 1,
+    };
+    let heads = std::iter::once(initial).chain(observation_heads.by_ref());
+
+    let target = match selector {
+        StateSelector::AsOf(timestamp) => heads
+            .filter(|head| head.timestamp <= *timestamp)
+            .max_by_key(|head| head.timestamp)
+            .ok_or_else(|| {
+                vec![Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::PathNotFound,
+                    format!(
+                        "No observations found as of {}",
+                        timestamp.format("%Y-%m-%d %H:%M:%S UTC")
+                    ),
+                )
+                .with_advice(
+                    "Try using --after to find the first observation after this time"
+                        .to_string(),
+                )]
+            }),
+        StateSelector::Before(timestamp) => heads
+            .filter(|head| head.timestamp < *timestamp)
+            .max_by_key(|head| head.timestamp)
+            .ok_or_else(|| {
+                vec![Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::PathNotFound,
+                    format!(
+                        "No observations found before {}",
+                        timestamp.format("%Y-%m-%d %H:%M:%S UTC")
+                    ),
+                )
+                .with_advice(
+                    "Try using --as-of to include observations at exactly this time"
+                        .to_string(),
+                )]
+            }),
+        StateSelector::After(timestamp) => heads
+            .filter(|head| head.timestamp > *timestamp)
+            .min_by_key(|head| head.timestamp)
+            .ok_or_else(|| vec![
+                Diagnostic::new(
+                    DiagnosticLevel::Fatal,
+                    DiagnosticCode::PathNotFound,
+                    format!("No observations found after {}", timestamp.format("%Y-%m-%d %H:%M:%S UTC"))
+                )
+                .with_advice("Try using --as-of to find the most recent observation before or at this time".to_string())
+            ]),
+        _ => Ok(heads.max_by_key(|head| head.timestamp).unwrap()), // Safe because the header is always there
+    };
+
+    // A line that couldn't be read ends the heads early, so whatever was
+    // picked may not be the target
+    if observation_heads.diagnostics.has_fatal() {
+        return Err(observation_heads.diagnostics.into_diagnostics());
+    }
+
+    Ok(target?.line_number)
+}
+
+/// Replay into a single state until the target observation is complete.
+fn replay_to(
+    reader: &ArchiveReader,
+    path: &Path,
+    target: &Target,
+    selector: &StateSelector,
+) -> Result<ResolvedState, Vec<Diagnostic>> {
+    let (initial_state, event_iter) = reader.events(path).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't read the archive file: {}", e),
+        )]
+    })?;
+    let mut observations = ObservationStates::from_events(initial_state, event_iter)?;
+    let header = observations.header().into();
+
+    let mut index = 0;
+    while let Some((observation, line)) = observations.replay_observation() {
+        if target.matches(index, &observation, line) {
+            let (state, diagnostics) = observations.into_state();
+            return Ok(ResolvedState {
+                header,
+                observation_id: observation.observation_id,
+                timestamp: observation.timestamp,
+                metadata: observation.metadata,
+                state,
+                diagnostics,
+            });
+        }
+        index += 1;
+    }
+
+    Err(vec![not_found(target, selector, index)])
+}
+
+fn not_found(target: &Target, selector: &StateSelector, observation_count: usize) -> Diagnostic {
+    match target {
+        Target::Id(id) => Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::NonExistentObservationId,
+            format!("I couldn't find an observation with ID '{}'", id),
+        )
+        .with_advice("Use 'json-archive info' to see available observation IDs".to_string()),
+        Target::Index(index) => Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::ArrayIndexOutOfBounds,
+            format!(
+                "Index {} is out of bounds. The archive has {} observations (0-{})",
+                index,
+                observation_count,
+                observation_count - 1
+            ),
+        )
+        .with_advice("Use 'json-archive info' to see available observation indices".to_string()),
+        // The first pass found it, but the replay didn't reach it, which
+        // happens when recover mode drops it as damaged
+        Target::Line(line) => Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!(
+                "I picked the observation at line {} for {:?}, but couldn't replay it because it is damaged.",
+                line, selector
+            ),
+        )
+        .with_advice(
+            "Run 'json-archive validate' to see what is wrong with it, or pick another observation."
+                .to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Header;
+    use serde_json::json;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn write_archive() -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+        let mut header = Header::new(json!({"count": 0}), None);
+        header.created = "2025-01-01T00:00:00Z".parse()?;
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        // Out of chronological order on purpose
+        writeln!(temp_file, r#"["observe", "obs-1", "2025-01-03T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 3, "obs-1"]"#)?;
+        writeln!(temp_file, r#"["snapshot", "obs-1", "2025-01-03T00:00:00Z", {{"count": 3}}]"#)?;
+        writeln!(temp_file, r#"["observe", "obs-2", "2025-01-02T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 2, "obs-2"]"#)?;
+        writeln!(temp_file, r#"["snapshot", "obs-3", "2025-01-04T00:00:00Z", {{"count": 4}}]"#)?;
+        writeln!(temp_file, r#"["observe", "obs-4", "2025-01-05T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["add", "/extra", true, "obs-4"]"#)?;
+        Ok(temp_file)
+    }
+
+    fn resolve(file: &NamedTempFile, selector: StateSelector) -> Result<(String, Value), Vec<Diagnostic>> {
+        resolve_state(file.path(), &selector, ReadMode::AppendSeek)
+            .map(|resolved| (resolved.observation_id, resolved.state))
+    }
+
+    #[test]
+    fn test_resolve_by_id_and_index() -> Result<(), Box<dyn std::error::Error>> {
+        let file = write_archive()?;
+
+        assert_eq!(resolve(&file, StateSelector::Id("initial".into())).unwrap().1, json!({"count": 0}));
+        assert_eq!(resolve(&file, StateSelector::Id("obs-1".into())).unwrap().1, json!({"count": 3}));
+        assert_eq!(resolve(&file, StateSelector::Id("obs-3".into())).unwrap().1, json!({"count": 4}));
+        assert_eq!(
+            resolve(&file, StateSelector::Id("obs-4".into())).unwrap().1,
+            json!({"count": 4, "extra": true})
+        );
+        assert_eq!(resolve(&file, StateSelector::Index(2)).unwrap(), ("obs-2".to_string(), json!({"count": 2})));
+
+        let errors = resolve(&file, StateSelector::Index(5)).unwrap_err();
+        assert_eq!(errors[0].code, DiagnosticCode::ArrayIndexOutOfBounds);
+        assert!(errors[0].description.contains("has 5 observations"));
+
+        let errors = resolve(&file, StateSelector::Id("nope".into())).unwrap_err();
+        assert_eq!(errors[0].code, DiagnosticCode::NonExistentObservationId);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_by_timestamp() -> Result<(), Box<dyn std::error::Error>> {
+        let file = write_archive()?;
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+
+        assert_eq!(resolve(&file, StateSelector::Latest).unwrap().0, "obs-4");
+        assert_eq!(resolve(&file, StateSelector::AsOf(at("2025-01-02T00:00:00Z"))).unwrap().0, "obs-2");
+        assert_eq!(resolve(&file, StateSelector::Before(at("2025-01-02T00:00:00Z"))).unwrap().0, "initial");
+        assert_eq!(
+            resolve(&file, StateSelector::After(at("2025-01-02T00:00:00Z"))).unwrap(),
+            ("obs-1".to_string(), json!({"count": 3}))
+        );
+
+        let errors = resolve(&file, StateSelector::Before(at("2024-01-01T00:00:00Z"))).unwrap_err();
+        assert_eq!(errors[0].code, DiagnosticCode::PathNotFound);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_at() -> Result<(), Box<dyn std::error::Error>> {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Counter {
+            count: u32,
+            extra: Option<bool>,
+        }
+
+        let file = write_archive()?;
+        let counter: Counter = state_at(file.path(), &StateSelector::Latest, ReadMode::AppendSeek)
+            .map_err(|d| format!("{:?}", d))?;
+        assert_eq!(counter, Counter { count: 4, extra: Some(true) });
+
+        let errors = state_at::<Vec<u32>, _>(file.path(), &StateSelector::Index(2), ReadMode::AppendSeek).unwrap_err();
+        assert_eq!(errors[0].code, DiagnosticCode::TypeMismatch);
+        assert!(errors[0].description.contains("obs-2"), "{}", errors[0].description);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary-precision")]
+    fn test_numbers_round_trip_exactly() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::archive::{create_archive_from_files, ArchiveOptions};
+
+        // Each version differs from the one before only beyond what an f64 holds
+        let versions = [
+            "{\n  \"id\": 1,\n  \"price\": 0.1\n}",
+            "{\n  \"id\": 123456789012345678901234567890,\n  \"price\": 0.1000000000000000055,\n  \"tiny\": 1e-400\n}",
+            "{\n  \"id\": 123456789012345678901234567891,\n  \"price\": 0.1000000000000000056,\n  \"tiny\": 1.0e-400\n}",
+        ];
+        let dir = tempfile::tempdir()?;
+        let inputs = versions
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let path = dir.path().join(format!("{}.json", i));
+                std::fs::write(&path, text)?;
+                Ok(path)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let archive = dir.path().join("numbers.json.archive");
+        create_archive_from_files(
+            &inputs,
+            archive.clone(),
+            &ArchiveOptions {
+                snapshot_interval: Some(2),
+                ..Default::default()
+            },
+        )
+        .map_err(|d| format!("{:?}", d))?;
+
+        // What `state` prints, by replaying deltas and by reading the snapshot
+        for (selector, expected) in [(StateSelector::Index(1), versions[1]), (StateSelector::Latest, versions[2])] {
+            let resolved = resolve_state(&archive, &selector, ReadMode::AppendSeek).map_err(|d| format!("{:?}", d))?;
+            assert_eq!(serde_json::to_string_pretty(&resolved.state)?, expected);
+        }
+
+        Ok(())
+    }
+}
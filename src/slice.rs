@@ -0,0 +1,400 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Cutting a time range out of an archive.
+//!
+//! The state at the first observation in the range becomes the new header's
+//! initial state. The observations after it are copied as they are, with
+//! their ids and timestamps, since their deltas still apply on top of it.
+//! Archives aren't always in chronological order, so an observation outside
+//! the range can sit between two inside it; the one after such a gap is
+//! diffed again against the last state that was kept. The hash chain of a
+//! hashed archive starts again from the new initial state.
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::archive::ArchiveWriter;
+use crate::diagnostics::{Diagnostic, DiagnosticCode, DiagnosticLevel};
+use crate::diff;
+use crate::events::{Event, Header, Observation};
+use crate::hash::state_hash;
+use crate::reader::{is_observation_snapshot, ArchiveReader, ReadMode};
+use crate::replay::{apply_event, require_intact};
+
+/// Which observations to keep, by timestamp. Unset bounds don't limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    /// Keep observations at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Keep observations at or before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Keep observations strictly before this time. With `since` set to the
+    /// same time, this splits an archive into two halves that don't overlap.
+    pub before: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+    pub fn contains(&self, timestamp: DateTime<Utc>) -> bool {
+        self.since.is_none_or(|since| timestamp >= since)
+            && self.until.is_none_or(|until| timestamp <= until)
+            && self.before.is_none_or(|before| timestamp < before)
+    }
+
+    /// The bounds as they are recorded in the sliced archive's metadata.
+    fn to_json(self) -> Map<String, Value> {
+        let mut bounds = Map::new();
+        for (name, bound) in [("since", self.since), ("until", self.until), ("before", self.before)] {
+            if let Some(bound) = bound {
+                bounds.insert(name.to_string(), json!(bound.to_rfc3339()));
+            }
+        }
+        bounds
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SliceSummary {
+    /// Observations kept, counting the new header's state.
+    pub observations: usize,
+    /// Observations outside the range.
+    pub dropped: usize,
+}
+
+/// Write the observations of `archive_path` that fall in `range` to a new
+/// archive at `output_path`. The header keeps the original's source, and its
+/// metadata gains a `slice` object naming the original archive, the bounds,
+/// and the id of the observation that became the initial state. A hashed
+/// archive's slice is hashed too, unless `drop_hashes` is set.
+pub fn slice_archive<P: AsRef<Path>, Q: AsRef<Path>>(
+    archive_path: P,
+    output_path: Q,
+    range: &TimeRange,
+    drop_hashes: bool,
+) -> Result<SliceSummary, Vec<Diagnostic>> {
+    let archive_path = archive_path.as_ref();
+    let mut original = require_intact(archive_path, "slice")?;
+    if drop_hashes {
+        original.hash = None;
+    }
+
+    let reader = ArchiveReader::new(archive_path, ReadMode::AppendSeek).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't open the archive file: {}", e),
+        )]
+    })?;
+    let (state, events) = reader.events(archive_path).map_err(|e| {
+        vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't read the archive file: {}", e),
+        )]
+    })?;
+
+    let mut slicer = Slicer {
+        archive_path,
+        output_path: output_path.as_ref(),
+        range,
+        original,
+        state,
+        writer: None,
+        previous_kept: false,
+        last_kept_state: Value::Null,
+        summary: SliceSummary { observations: 0, dropped: 0 },
+    };
+
+    let mut pending = PendingObservation {
+        id: "initial".to_string(),
+        timestamp: slicer.original.created,
+        metadata: None,
+        events: Vec::new(),
+    };
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for event in events {
+        let next = match &event {
+            Event::Observe { observation_id, timestamp, metadata, .. } => {
+                Some((observation_id, *timestamp, metadata.clone()))
+            }
+            Event::Snapshot { observation_id, timestamp, metadata, .. }
+                if is_observation_snapshot(observation_id, Some(&pending.id), &seen) =>
+            {
+                Some((observation_id, *timestamp, metadata.clone()))
+            }
+            _ => None,
+        };
+
+        if let Some((id, timestamp, metadata)) = next {
+            seen.insert(id.clone());
+            let next = PendingObservation {
+                id: id.clone(),
+                timestamp,
+                metadata,
+                events: Vec::new(),
+            };
+            slicer.finish(std::mem::replace(&mut pending, next))?;
+        }
+        pending.events.push(event);
+    }
+    slicer.finish(pending)?;
+
+    let Some(writer) = slicer.writer else {
+        let bounds = Value::Object(range.to_json());
+        return Err(vec![Diagnostic::new(
+            DiagnosticLevel::Fatal,
+            DiagnosticCode::PathNotFound,
+            format!("I couldn't find any observations in {} within {}.", archive_path.display(), bounds),
+        )
+        .with_advice(format!(
+            "Run `json-archive info {}` to see when its observations were recorded.",
+            archive_path.display()
+        ))]);
+    };
+    writer.finish()?;
+
+    Ok(slicer.summary)
+}
+
+/// An observation's events, held until the next observation starts.
+struct PendingObservation {
+    id: String,
+    timestamp: DateTime<Utc>,
+    metadata: Option<Map<String, Value>>,
+    /// Starting with its observe or snapshot line. Empty for the header's state.
+    events: Vec<Event>,
+}
+
+struct Slicer<'a> {
+    archive_path: &'a Path,
+    output_path: &'a Path,
+    range: &'a TimeRange,
+    original: Header,
+    /// The original archive's state, replayed up to here.
+    state: Value,
+    /// Opened at the first observation in the range.
+    writer: Option<ArchiveWriter>,
+    previous_kept: bool,
+    /// The state after the last observation written, once a gap opens.
+    last_kept_state: Value,
+    summary: SliceSummary,
+}
+
+impl Slicer<'_> {
+    fn finish(&mut self, observation: PendingObservation) -> Result<(), Vec<Diagnostic>> {
+        let kept = self.range.contains(observation.timestamp);
+        let previous_kept = std::mem::replace(&mut self.previous_kept, kept);
+
+        if !kept {
+            if previous_kept {
+                self.last_kept_state = self.state.clone();
+            }
+            self.summary.dropped += 1;
+            replay(&mut self.state, observation.events);
+            return Ok(());
+        }
+        self.summary.observations += 1;
+
+        let Some(writer) = self.writer.as_mut() else {
+            replay(&mut self.state, observation.events);
+            return self.start(observation.id, observation.timestamp);
+        };
+
+        // Right after the previous kept observation, or complete by itself
+        let is_snapshot = matches!(observation.events.first(), Some(Event::Snapshot { .. }));
+        if previous_kept || is_snapshot {
+            let events = observation.events.clone();
+            replay(&mut self.state, observation.events);
+            for event in &events {
+                writer.write_copied_event(event, &self.state)?;
+            }
+            return Ok(());
+        }
+
+        // After a gap: the deltas are relative to a state that was left out
+        replay(&mut self.state, observation.events);
+        let mut rediffed = Observation::new(observation.id, observation.timestamp);
+        for event in diff::diff(&self.last_kept_state, &self.state, "", &rediffed.id) {
+            rediffed.add_event(event);
+        }
+        rediffed.metadata = observation.metadata;
+        writer.write_observation_with_state(rediffed, &self.state)
+    }
+
+    /// Open the output with the current state as its initial state.
+    fn start(&mut self, first_id: String, timestamp: DateTime<Utc>) -> Result<(), Vec<Diagnostic>> {
+        let mut slice = Map::new();
+        slice.insert("archive".to_string(), json!(self.archive_path.display().to_string()));
+        slice.extend(self.range.to_json());
+        slice.insert("initial_observation".to_string(), json!(first_id));
+
+        let metadata = match self.original.metadata.take() {
+            None => Map::new(),
+            Some(Value::Object(metadata)) => metadata,
+            Some(other) => Map::from_iter([("metadata".to_string(), other)]),
+        };
+        let mut metadata = metadata;
+        metadata.insert("slice".to_string(), Value::Object(slice));
+
+        let mut header = Header::new(self.state.clone(), self.original.source.clone());
+        header.created = timestamp;
+        header.metadata = Some(Value::Object(metadata));
+        header.collection = self.original.collection;
+
+        let mut writer = ArchiveWriter::new(self.output_path, None)?;
+        if self.original.hash.is_some() {
+            let hash = state_hash(&header.initial);
+            writer = writer.with_hash_chain(hash.clone());
+            header.hash = Some(hash);
+        }
+        writer.write_header(&header)?;
+        self.writer = Some(writer);
+        Ok(())
+    }
+}
+
+fn replay(state: &mut Value, events: Vec<Event>) {
+    for event in events {
+        apply_event(state, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{assert_hash_chain, hashed_copy};
+    use crate::replay::observation_states;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn write_archive() -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+        let mut header = Header::new(json!({"count": 0}), Some("test".to_string()));
+        header.created = "2024-01-01T00:00:00Z".parse()?;
+        writeln!(temp_file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(temp_file, r#"["observe", "obs-1", "2024-06-01T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 1, "obs-1"]"#)?;
+        writeln!(temp_file, r#"["observe", "obs-2", "2025-02-01T00:00:00Z", 1, {{"file": "b.json"}}]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 2, "obs-2"]"#)?;
+        // Out of order: recorded in 2024, but written after a 2025 observation
+        writeln!(temp_file, r#"["observe", "obs-late", "2024-12-01T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["add", "/late", true, "obs-late"]"#)?;
+        writeln!(temp_file, r#"["observe", "obs-3", "2025-03-01T00:00:00Z", 1]"#)?;
+        writeln!(temp_file, r#"["change", "/count", 3, "obs-3"]"#)?;
+        Ok(temp_file)
+    }
+
+    fn states(path: &Path) -> Vec<(String, Value)> {
+        observation_states(path, ReadMode::AppendSeek)
+            .unwrap()
+            .map(|observation| (observation.observation_id, observation.state))
+            .collect()
+    }
+
+    #[test]
+    fn test_slice_since() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = write_archive()?;
+        let output = NamedTempFile::new()?;
+        let range = TimeRange {
+            since: Some("2025-01-01T00:00:00Z".parse()?),
+            ..TimeRange::default()
+        };
+
+        let summary = slice_archive(archive.path(), output.path(), &range, false).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(summary, SliceSummary { observations: 2, dropped: 3 });
+
+        // obs-3 comes after the dropped obs-late, so it's diffed again, and
+        // still has the state it had in the original
+        assert_eq!(
+            states(output.path()),
+            vec![
+                ("initial".to_string(), json!({"count": 2})),
+                ("obs-3".to_string(), json!({"count": 3, "late": true})),
+            ]
+        );
+
+        let content = std::fs::read_to_string(output.path())?;
+        let header: Header = serde_json::from_str(content.lines().next().unwrap())?;
+        assert_eq!(header.source.as_deref(), Some("test"));
+        assert_eq!(header.created, "2025-02-01T00:00:00Z".parse::<DateTime<Utc>>()?);
+        let slice = &header.metadata.unwrap()["slice"];
+        assert_eq!(slice["initial_observation"], json!("obs-2"));
+        assert_eq!(slice["since"], json!("2025-01-01T00:00:00+00:00"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_slice_before() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = write_archive()?;
+        let output = NamedTempFile::new()?;
+        let range = TimeRange {
+            before: Some("2025-01-01T00:00:00Z".parse()?),
+            ..TimeRange::default()
+        };
+
+        let summary = slice_archive(archive.path(), output.path(), &range, false).map_err(|d| format!("{:?}", d))?;
+        assert_eq!(summary, SliceSummary { observations: 3, dropped: 2 });
+        assert_eq!(
+            states(output.path()),
+            vec![
+                ("initial".to_string(), json!({"count": 0})),
+                ("obs-1".to_string(), json!({"count": 1})),
+                ("obs-late".to_string(), json!({"count": 2, "late": true})),
+            ]
+        );
+
+        let range = TimeRange {
+            before: Some("2020-01-01T00:00:00Z".parse()?),
+            ..TimeRange::default()
+        };
+        let errors = slice_archive(archive.path(), output.path(), &range, false).unwrap_err();
+        assert_eq!(errors[0].code, DiagnosticCode::PathNotFound);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_slice_hashed_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = hashed_copy(write_archive()?.path())?;
+        let output = NamedTempFile::new()?;
+        // Starts at obs-2, and diffs obs-3 again after the dropped obs-late
+        let range = TimeRange {
+            since: Some("2025-01-01T00:00:00Z".parse()?),
+            ..TimeRange::default()
+        };
+
+        slice_archive(archive.path(), output.path(), &range, false).map_err(|d| format!("{:?}", d))?;
+        assert_hash_chain(output.path())?;
+
+        let range = TimeRange {
+            until: Some("2025-02-01T00:00:00Z".parse()?),
+            ..TimeRange::default()
+        };
+        slice_archive(archive.path(), output.path(), &range, false).map_err(|d| format!("{:?}", d))?;
+        assert_hash_chain(output.path())?;
+
+        Ok(())
+    }
+}
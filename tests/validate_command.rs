@@ -0,0 +1,106 @@
+// json-archive is a tool for tracking JSON file changes over time
+// Copyright (C) 2025  Peoples Grocers LLC
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// To purchase a license under different terms contact admin@peoplesgrocers.com
+// To request changes, report bugs, or give user feedback contact
+// marxism@peoplesgrocers.com
+//
+
+//! Runs the `validate` command the way a user or CI script would and checks
+//! what they'd see: the exit status and the `--output json` report.
+
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Output};
+use tempfile::NamedTempFile;
+
+/// An archive whose only problem is a warning: the observe event declares
+/// two changes but has one.
+fn archive_with_warning() -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(
+        file,
+        r#"{{"type":"@peoplesgrocers/json-archive","version":1,"created":"2025-01-01T00:00:00Z","initial":{{"count":0}}}}"#
+    )
+    .unwrap();
+    writeln!(file, r#"["observe", "obs-1", "2025-01-02T00:00:00Z", 2]"#).unwrap();
+    writeln!(file, r#"["change", "/count", 1, "obs-1"]"#).unwrap();
+    file
+}
+
+fn validate(args: &[&str], file: &NamedTempFile) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_json-archive"))
+        .arg("validate")
+        .args(args)
+        .arg(file.path())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_strict_fails_on_warnings() {
+    let file = archive_with_warning();
+
+    let output = validate(&[], &file);
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = validate(&["--strict"], &file);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error W040"), "{}", stderr);
+}
+
+#[test]
+fn test_json_report_shape() {
+    let file = archive_with_warning();
+    let archive = file.path().display().to_string();
+
+    let output = validate(&["--output", "json"], &file);
+    assert_eq!(output.status.code(), Some(0));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        report,
+        json!({
+            "archive": archive,
+            "valid": true,
+            "strict": false,
+            "observation_count": 1,
+            "snapshot_count": 0,
+            "error_count": 0,
+            "warning_count": 1,
+            "diagnostics": [{
+                "level": "warning",
+                "code": "W040",
+                "title": "Change count mismatch",
+                "message": "The observe event at line 2 declared 2 changes, but I found 1.",
+                "file": archive,
+                "line": 2,
+                "column": null,
+                "snippet": null,
+                "advice": null,
+            }],
+        })
+    );
+
+    let output = validate(&["--output", "json", "--strict"], &file);
+    assert_eq!(output.status.code(), Some(1));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["valid"], json!(false));
+    assert_eq!(report["strict"], json!(true));
+    assert_eq!(report["error_count"], json!(1));
+    assert_eq!(report["warning_count"], json!(0));
+    assert_eq!(report["diagnostics"][0]["level"], json!("error"));
+}